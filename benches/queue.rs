@@ -0,0 +1,72 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use sensor_suricata_service_rust::pb::{Metric, SensorEvent};
+use sensor_suricata_service_rust::queue::{EventBatchQueue, FlushPolicy, MemoryLimits};
+use std::time::Duration;
+
+const EVENTS_PER_WORKER: usize = 10_000;
+// Distinct hashes per worker; the rest merge into existing records.
+const HASHES_PER_WORKER: usize = 1_000;
+
+fn events(worker: usize) -> Vec<SensorEvent> {
+    (0..EVENTS_PER_WORKER)
+        .map(|i| SensorEvent {
+            event_hash_sha256: format!("{:02}-{:064}", worker, i % HASHES_PER_WORKER),
+            event_metrics_count: 1,
+            metrics: vec![Metric {
+                snort_timestamp: "2025-12-15T07:46:41.123456+0000".to_string(),
+                snort_src_address: Some("10.0.0.1".to_string()),
+                snort_dst_address: Some("10.0.0.2".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Workers adding events concurrently while a batcher drains the queue, as
+/// in the service. One shard is the former single-lock queue.
+fn add_concurrently(c: &mut Criterion) {
+    let mut group = c.benchmark_group("queue_add");
+    for workers in [1, 2, 4, 8, 16, 32] {
+        group.throughput(Throughput::Elements((workers * EVENTS_PER_WORKER) as u64));
+        for (name, shards) in [("single_lock", 1), ("sharded", workers * 4)] {
+            group.bench_with_input(BenchmarkId::new(name, workers), &workers, |b, &workers| {
+                b.iter_batched(
+                    || (0..workers).map(events).collect::<Vec<_>>(),
+                    |inputs| {
+                        let queue = EventBatchQueue::new(
+                            FlushPolicy {
+                                interval: Duration::from_millis(1),
+                                ..Default::default()
+                            },
+                            MemoryLimits::default(),
+                            shards,
+                        );
+                        let done = std::sync::atomic::AtomicBool::new(false);
+                        std::thread::scope(|s| {
+                            s.spawn(|| {
+                                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                                    queue.process_batch();
+                                }
+                            });
+                            let handles: Vec<_> = inputs
+                                .into_iter()
+                                .map(|input| {
+                                    let queue = &queue;
+                                    s.spawn(move || input.into_iter().for_each(|e| queue.add(e)))
+                                })
+                                .collect();
+                            handles.into_iter().for_each(|h| h.join().unwrap());
+                            done.store(true, std::sync::atomic::Ordering::Relaxed);
+                        });
+                    },
+                    criterion::BatchSize::LargeInput,
+                );
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, add_concurrently);
+criterion_main!(benches);
@@ -1,4 +1,10 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/sensor_event.proto")?;
+    // Serde lets scripts read and modify events as Rhai object maps.
+    tonic_build::configure()
+        .type_attribute(
+            ".pb",
+            "#[derive(serde::Serialize, serde::Deserialize)] #[serde(default)]",
+        )
+        .compile(&["proto/sensor_event.proto"], &["proto"])?;
     Ok(())
 }
@@ -0,0 +1,238 @@
+use crate::cidr::{Cidr, CidrMap};
+use crate::pb::Metric;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+/// Crypto-PAn prefix-preserving pseudonymization of IPv4/IPv6 and MAC
+/// addresses. Two addresses sharing an n-bit prefix map to addresses sharing
+/// an n-bit prefix, and the mapping is reversible by holders of the key.
+///
+/// Pseudonyms that fall into an exempt range are mapped again until they
+/// leave it, so every pseudonym maps back to a single address. Such
+/// addresses lose the prefix relation to their neighbours.
+///
+/// The key is 32 bytes: the first 16 are the AES-128 key, the last 16 are
+/// encrypted to form the padding block, as in the reference implementation.
+pub struct Anonymizer {
+    cipher: Aes128,
+    pad: u128,
+    exempt: CidrMap<()>,
+    // Metrics
+    total_anonymized: AtomicI64,
+}
+
+impl Anonymizer {
+    /// `key_path` holds the 32-byte key, raw or as 64 hex characters.
+    /// `exempt` is a `HOME_NET`-style CIDR list of addresses left unchanged.
+    pub fn load(
+        key_path: &Path,
+        exempt: &str,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let raw = fs::read(key_path)?;
+        let key = match std::str::from_utf8(&raw).ok().map(str::trim) {
+            Some(text) if text.len() == 64 => hex::decode(text)?,
+            _ => raw,
+        };
+        let key: [u8; 32] = key.try_into().map_err(|_| {
+            format!(
+                "{}: key must be 32 bytes or 64 hex characters",
+                key_path.display()
+            )
+        })?;
+
+        let mut exempt_net = CidrMap::new();
+        for cidr in exempt
+            .trim_matches(|c: char| c == '[' || c == ']' || c.is_whitespace())
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+        {
+            exempt_net.insert(cidr.parse::<Cidr>()?, ());
+        }
+
+        Ok(Self::new(&key, exempt_net))
+    }
+
+    fn new(key: &[u8; 32], exempt: CidrMap<()>) -> Self {
+        let cipher = Aes128::new(GenericArray::from_slice(&key[..16]));
+        let mut pad = GenericArray::clone_from_slice(&key[16..]);
+        cipher.encrypt_block(&mut pad);
+        Self {
+            cipher,
+            pad: u128::from_be_bytes(pad.into()),
+            exempt,
+            total_anonymized: AtomicI64::new(0),
+        }
+    }
+
+    /// Replaces the src/dst addresses, the `ip:port` fields, the MAC
+    /// addresses and address IOC indicators of the metric. The community ID
+    /// and passive DNS hostnames, which would reveal the real addresses, are
+    /// removed.
+    pub fn anonymize(&self, metric: &mut Metric) {
+        metric.event_community_id = None;
+        metric.event_src_hostnames.clear();
+        metric.event_dst_hostnames.clear();
+
+        for field in [
+            &mut metric.snort_src_address,
+            &mut metric.snort_dst_address,
+            &mut metric.snort_eth_src,
+            &mut metric.snort_eth_dst,
+        ] {
+            if let Some(value) = field.as_mut() {
+                if let Some(anonymized) = self.map_value(value, false) {
+                    *value = anonymized;
+                }
+            }
+        }
+        // An IPv6 `ip:port` without brackets would also parse as an address.
+        for field in [
+            &mut metric.snort_src_ap,
+            &mut metric.snort_dst_ap,
+            &mut metric.snort_target,
+        ] {
+            if let Some(value) = field.as_mut() {
+                if let Some(anonymized) = self.map_endpoint(value, false) {
+                    *value = anonymized;
+                }
+            }
+        }
+        for ioc in metric.event_ioc_matches.iter_mut() {
+            if ioc.field == "src_ip" || ioc.field == "dest_ip" {
+                if let Some(anonymized) = self.map_value(&ioc.indicator, false) {
+                    ioc.indicator = anonymized;
+                }
+            }
+        }
+        self.total_anonymized.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Reverses the pseudonymization of an IP address, `ip:port` or MAC
+    /// address. Returns `None` if the value is none of these. An IPv6
+    /// `ip:port` without brackets reads as an address; see `deanonymize_ap`.
+    #[allow(dead_code)] // Used by the deanonymize tool, not by the client.
+    pub fn deanonymize(&self, value: &str) -> Option<String> {
+        self.map_value(value, true)
+    }
+
+    /// Reverses the pseudonymization of an `ip:port` value, or of a bare
+    /// address such as a `snort_target` without a port.
+    #[allow(dead_code)] // Used by the deanonymize tool, not by the client.
+    pub fn deanonymize_ap(&self, value: &str) -> Option<String> {
+        self.map_endpoint(value, true)
+    }
+
+    fn map_value(&self, value: &str, reverse: bool) -> Option<String> {
+        if let Ok(ip) = value.parse::<IpAddr>() {
+            return Some(self.map_ip(ip, reverse).to_string());
+        }
+        if let Some(mac) = parse_mac(value) {
+            let mapped = self.map_bits(mac << 80, 48, reverse) >> 80;
+            return Some(format_mac(mapped));
+        }
+        self.map_ap(value, reverse)
+    }
+
+    /// Maps an `ip:port` value, falling back to a bare address.
+    fn map_endpoint(&self, value: &str, reverse: bool) -> Option<String> {
+        self.map_ap(value, reverse)
+            .or_else(|| self.map_value(value, reverse))
+    }
+
+    /// Maps the address of an `ip:port` value, IPv6 ones with or without
+    /// brackets, keeping the port.
+    fn map_ap(&self, value: &str, reverse: bool) -> Option<String> {
+        let (ip, port) = value.rsplit_once(':')?;
+        port.parse::<u16>().ok()?;
+        if let Some(ip) = ip.strip_prefix('[').and_then(|ip| ip.strip_suffix(']')) {
+            let ip: IpAddr = ip.parse().ok()?;
+            return Some(format!("[{}]:{}", self.map_ip(ip, reverse), port));
+        }
+        let ip: IpAddr = ip.parse().ok()?;
+        Some(format!("{}:{}", self.map_ip(ip, reverse), port))
+    }
+
+    fn map_ip(&self, ip: IpAddr, reverse: bool) -> IpAddr {
+        if self.exempt.contains(&ip) {
+            return ip;
+        }
+        let mut mapped = self.map_addr(ip, reverse);
+        while self.exempt.contains(&mapped) {
+            mapped = self.map_addr(mapped, reverse);
+        }
+        mapped
+    }
+
+    fn map_addr(&self, ip: IpAddr, reverse: bool) -> IpAddr {
+        match ip {
+            IpAddr::V4(v4) => {
+                let bits = (u32::from(v4) as u128) << 96;
+                let mapped = self.map_bits(bits, 32, reverse) >> 96;
+                IpAddr::V4(Ipv4Addr::from(mapped as u32))
+            }
+            IpAddr::V6(v6) => {
+                let mapped = self.map_bits(u128::from(v6), 128, reverse);
+                IpAddr::V6(Ipv6Addr::from(mapped))
+            }
+        }
+    }
+
+    /// Maps the top `width` bits of `value`. Bit `i` of the output is bit `i`
+    /// of the input XORed with the first bit of AES(input bits before `i`,
+    /// padded with the pad block), so reversing recovers the input one bit at
+    /// a time.
+    fn map_bits(&self, value: u128, width: u32, reverse: bool) -> u128 {
+        let mut original = if reverse { 0 } else { value };
+        let mut result = 0u128;
+        for pos in 0..width {
+            let prefix_mask = if pos == 0 {
+                0
+            } else {
+                u128::MAX << (128 - pos)
+            };
+            let input = (original & prefix_mask) | (self.pad & !prefix_mask);
+            let mut block = GenericArray::from(input.to_be_bytes());
+            self.cipher.encrypt_block(&mut block);
+            let flip = ((block[0] >> 7) as u128) << (127 - pos);
+
+            let bit = 1u128 << (127 - pos);
+            if reverse {
+                original |= (value ^ flip) & bit;
+            } else {
+                result |= (value ^ flip) & bit;
+            }
+        }
+        if reverse {
+            original
+        } else {
+            result
+        }
+    }
+
+    pub fn get_total_anonymized(&self) -> i64 {
+        self.total_anonymized.load(Ordering::Relaxed)
+    }
+}
+
+fn parse_mac(value: &str) -> Option<u128> {
+    let octets: Vec<&str> = value.split(':').collect();
+    if octets.len() != 6 || octets.iter().any(|o| o.len() != 2) {
+        return None;
+    }
+    octets.iter().try_fold(0u128, |mac, octet| {
+        Some((mac << 8) | u8::from_str_radix(octet, 16).ok()? as u128)
+    })
+}
+
+fn format_mac(mac: u128) -> String {
+    (0..6)
+        .rev()
+        .map(|i| format!("{:02x}", (mac >> (i * 8)) as u8))
+        .collect::<Vec<_>>()
+        .join(":")
+}
@@ -0,0 +1,126 @@
+use crate::cidr::{Cidr, CidrMap};
+use crate::pb::{Asset, Metric};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Tags alerts with their direction relative to the home network and with the
+/// inventory entry of the internal side.
+pub struct AssetTagger {
+    // False for negated entries
+    home_net: CidrMap<bool>,
+    assets: CidrMap<Asset>,
+}
+
+impl AssetTagger {
+    /// `home_net` is a `HOME_NET`-style list such as
+    /// `[10.0.0.0/8,!10.9.0.0/16]`; the most specific entry for an address
+    /// decides, so negated entries carve ranges out of wider ones. Variables
+    /// and nested groups are not supported. If it is empty, the CIDRs of the
+    /// asset inventory are treated as the home network.
+    ///
+    /// The inventory is a CSV file with the columns
+    /// `cidr,hostname,owner,criticality,zone`; a header row is optional and
+    /// lines starting with `#` are skipped.
+    pub fn load(
+        home_net: &str,
+        inventory: Option<&Path>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut home = CidrMap::new();
+        for cidr in home_net
+            .trim_matches(|c: char| c == '[' || c == ']' || c.is_whitespace())
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+        {
+            let entry = cidr.trim();
+            if entry.contains(['[', ']', '$']) {
+                return Err(format!(
+                    "HOME_NET entry {:?}: variables and nested groups are not supported",
+                    entry
+                )
+                .into());
+            }
+            match entry.strip_prefix('!') {
+                Some(negated) => home.insert(negated.parse::<Cidr>()?, false),
+                None => home.insert(entry.parse::<Cidr>()?, true),
+            }
+        }
+
+        let mut assets = CidrMap::new();
+        if let Some(path) = inventory {
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .trim(csv::Trim::All)
+                .comment(Some(b'#'))
+                .from_path(path)?;
+            for (n, record) in reader.records().enumerate() {
+                let record = record?;
+                let line = record.position().map_or(0, |p| p.line());
+                if record.iter().all(str::is_empty) {
+                    continue;
+                }
+                if n == 0 && record[0].eq_ignore_ascii_case("cidr") {
+                    continue;
+                }
+                let cidr: Cidr = record[0]
+                    .parse()
+                    .map_err(|e| format!("{}:{}: {}", path.display(), line, e))?;
+                let column = |i: usize| record.get(i).filter(|c| !c.is_empty()).map(str::to_string);
+                assets.insert(
+                    cidr,
+                    Asset {
+                        hostname: column(1).unwrap_or_default(),
+                        owner: column(2),
+                        criticality: column(3),
+                        zone: column(4),
+                    },
+                );
+            }
+        }
+
+        Ok(Self {
+            home_net: home,
+            assets,
+        })
+    }
+
+    pub fn asset_count(&self) -> usize {
+        self.assets.len()
+    }
+
+    /// Sets the metric's direction (inbound, outbound, internal or external)
+    /// and the asset entries of its home-network addresses.
+    pub fn tag(&self, metric: &mut Metric) {
+        let src = parse_ip(metric.snort_src_address.as_deref());
+        let dst = parse_ip(metric.snort_dst_address.as_deref());
+        let src_home = src.is_some_and(|ip| self.is_home(&ip));
+        let dst_home = dst.is_some_and(|ip| self.is_home(&ip));
+
+        let direction = match (src_home, dst_home) {
+            (true, true) => "internal",
+            (true, false) => "outbound",
+            (false, true) => "inbound",
+            (false, false) => "external",
+        };
+        metric.event_direction = Some(direction.to_string());
+
+        if src_home {
+            metric.event_src_asset = src.and_then(|ip| self.assets.longest_match(&ip).cloned());
+        }
+        if dst_home {
+            metric.event_dst_asset = dst.and_then(|ip| self.assets.longest_match(&ip).cloned());
+        }
+    }
+
+    fn is_home(&self, ip: &IpAddr) -> bool {
+        if self.home_net.is_empty() {
+            self.assets.contains(ip)
+        } else {
+            self.home_net.longest_match(ip) == Some(&true)
+        }
+    }
+}
+
+fn parse_ip(ip: Option<&str>) -> Option<IpAddr> {
+    ip.and_then(|ip| ip.parse().ok())
+}
@@ -0,0 +1,233 @@
+use crate::pb::{AttackEntry, SensorEvent};
+use crate::rules::Rule;
+use crate::types::SuricataAlert;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+#[derive(Debug, Default)]
+struct Mapping {
+    tactics: Vec<String>,
+    techniques: Vec<String>,
+}
+
+/// Maps alerts to MITRE ATT&CK tactics and techniques.
+///
+/// IDs are taken, in order, from the alert's `mitre_*` metadata, from the
+/// `metadata:` of the rule in the loaded ruleset, and from a local table.
+/// Names come from an ATT&CK STIX bundle, falling back to the
+/// `mitre_*_name` metadata.
+#[derive(Default)]
+pub struct AttackMapper {
+    by_sid: HashMap<i64, Mapping>,
+    by_classtype: HashMap<String, Mapping>,
+    names: HashMap<String, String>,
+    // Metrics
+    total_mapped: AtomicI64,
+}
+
+impl AttackMapper {
+    /// `table` is a CSV file of `key,tactics,techniques` rows (header
+    /// optional) where the key is `sid:<sid>` or `classtype:<classtype>` and
+    /// IDs are separated by `;`. Classtype keys match the rule's classtype
+    /// when the ruleset is loaded, otherwise the alert category.
+    ///
+    /// `bundle` is an ATT&CK STIX 2 bundle such as `enterprise-attack.json`.
+    pub fn load(
+        table: Option<&Path>,
+        bundle: Option<&Path>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut mapper = Self::default();
+
+        if let Some(path) = table {
+            for (n, line) in fs::read_to_string(path)?.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let columns: Vec<&str> = line.split(',').map(str::trim).collect();
+                let ids = |i: usize| -> Vec<String> {
+                    columns
+                        .get(i)
+                        .map(|c| c.split(';').map(|id| id.trim().to_string()).collect())
+                        .unwrap_or_default()
+                };
+                let mapping = Mapping {
+                    tactics: ids(1),
+                    techniques: ids(2),
+                };
+                match columns[0].split_once(':') {
+                    Some(("sid", sid)) => {
+                        let sid = sid
+                            .parse()
+                            .map_err(|e| format!("{}:{}: {}", path.display(), n + 1, e))?;
+                        mapper.by_sid.insert(sid, mapping);
+                    }
+                    Some(("classtype", classtype)) => {
+                        mapper
+                            .by_classtype
+                            .insert(classtype.to_lowercase(), mapping);
+                    }
+                    _ if n == 0 => {} // Header
+                    _ => {
+                        return Err(format!(
+                            "{}:{}: key must be sid:<sid> or classtype:<classtype>",
+                            path.display(),
+                            n + 1
+                        )
+                        .into())
+                    }
+                }
+            }
+        }
+
+        if let Some(path) = bundle {
+            let bundle: StixBundle = serde_json::from_str(&fs::read_to_string(path)?)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            for object in bundle.objects {
+                if object.revoked
+                    || !matches!(object.kind.as_str(), "x-mitre-tactic" | "attack-pattern")
+                {
+                    continue;
+                }
+                let id = object
+                    .external_references
+                    .iter()
+                    .find(|r| r.source_name == "mitre-attack")
+                    .and_then(|r| r.external_id.clone());
+                if let (Some(id), Some(name)) = (id, object.name) {
+                    mapper.names.insert(id, name);
+                }
+            }
+        }
+
+        Ok(mapper)
+    }
+
+    /// Sets the event's ATT&CK tactics and techniques. `rule` is the rule
+    /// that fired, if the ruleset is loaded.
+    pub fn map(&self, data: &SuricataAlert, rule: Option<&Rule>, event: &mut SensorEvent) {
+        let mut tactics = Vec::new();
+        let mut techniques = Vec::new();
+
+        if let Some(metadata) = data.alert.as_ref().and_then(|a| a.metadata.as_ref()) {
+            let list = |values: &Option<Vec<String>>| values.clone().unwrap_or_default();
+            self.add(
+                &mut tactics,
+                list(&metadata.mitre_tactic_id),
+                list(&metadata.mitre_tactic_name),
+            );
+            self.add(
+                &mut techniques,
+                list(&metadata.mitre_technique_id),
+                list(&metadata.mitre_technique_name),
+            );
+        }
+
+        if let Some(rule) = rule {
+            let values = |key: &str| -> Vec<String> {
+                rule.metadata
+                    .iter()
+                    .filter(|(k, _)| k == key)
+                    .map(|(_, v)| v.clone())
+                    .collect()
+            };
+            self.add(
+                &mut tactics,
+                values("mitre_tactic_id"),
+                values("mitre_tactic_name"),
+            );
+            self.add(
+                &mut techniques,
+                values("mitre_technique_id"),
+                values("mitre_technique_name"),
+            );
+        }
+
+        let classtype = match rule {
+            Some(rule) => rule.classtype.clone(),
+            None => event.snort_classification.clone(),
+        };
+        let table_mappings = [
+            self.by_sid.get(&event.snort_rule_sid),
+            classtype.and_then(|c| self.by_classtype.get(&c.to_lowercase())),
+        ];
+        for mapping in table_mappings.into_iter().flatten() {
+            self.add(&mut tactics, mapping.tactics.clone(), Vec::new());
+            self.add(&mut techniques, mapping.techniques.clone(), Vec::new());
+        }
+
+        if !tactics.is_empty() || !techniques.is_empty() {
+            self.total_mapped.fetch_add(1, Ordering::Relaxed);
+        }
+        event.event_attack_tactics = tactics;
+        event.event_attack_techniques = techniques;
+    }
+
+    /// Adds normalized IDs not yet in `entries`. `names` are the metadata
+    /// names listed alongside the IDs, used when the bundle has none.
+    fn add(&self, entries: &mut Vec<AttackEntry>, ids: Vec<String>, names: Vec<String>) {
+        let mut names = names.into_iter();
+        for id in ids {
+            let metadata_name = names.next().map(|n| n.replace('_', " "));
+            let id = match normalize_id(&id) {
+                Some(id) => id,
+                None => continue,
+            };
+            let name = self.names.get(&id).cloned().or(metadata_name);
+            match entries.iter_mut().find(|e| e.id == id) {
+                Some(entry) => {
+                    if entry.name.is_none() {
+                        entry.name = name;
+                    }
+                }
+                None => entries.push(AttackEntry { id, name }),
+            }
+        }
+    }
+
+    pub fn get_total_mapped(&self) -> i64 {
+        self.total_mapped.load(Ordering::Relaxed)
+    }
+}
+
+/// Normalizes `ta0011`, `T1071`, `T1071_001` and `T1071.001` style IDs.
+/// Returns `None` for anything else.
+fn normalize_id(id: &str) -> Option<String> {
+    let id = id.trim().to_uppercase().replace('_', ".");
+    let digits = |s: &str, n: usize| s.len() == n && s.bytes().all(|b| b.is_ascii_digit());
+    let valid = match id.strip_prefix("TA") {
+        Some(number) => digits(number, 4),
+        None => match id.strip_prefix('T').map(|t| t.split_once('.')) {
+            Some(Some((technique, sub))) => digits(technique, 4) && digits(sub, 3),
+            Some(None) => digits(&id[1..], 4),
+            None => false,
+        },
+    };
+    valid.then_some(id)
+}
+
+#[derive(Deserialize)]
+struct StixBundle {
+    #[serde(default)]
+    objects: Vec<StixObject>,
+}
+
+#[derive(Deserialize)]
+struct StixObject {
+    #[serde(rename = "type")]
+    kind: String,
+    name: Option<String>,
+    #[serde(default)]
+    revoked: bool,
+    #[serde(default)]
+    external_references: Vec<ExternalReference>,
+}
+
+#[derive(Deserialize)]
+struct ExternalReference {
+    source_name: String,
+    external_id: Option<String>,
+}
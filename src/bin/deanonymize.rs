@@ -0,0 +1,91 @@
+//! Reverses the client's prefix-preserving address pseudonymization for
+//! holders of the key.
+//!
+//! Values given as arguments are printed one per line. Without arguments,
+//! lines are read from stdin (e.g. exported events as JSON) and every IP
+//! address, `ip:port` and MAC address in them is replaced. Values of JSON
+//! `*_ap` and `snort_target` fields are read as `ip:port`, since an IPv6 one
+//! without brackets also parses as an address.
+
+use clap::Parser;
+use sensor_suricata_service_rust::anonymize::Anonymizer;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Key file the client was configured with
+    #[arg(long)]
+    key_path: PathBuf,
+
+    /// CIDRs the client was configured to leave unchanged
+    #[arg(long, default_value = "")]
+    exempt: String,
+
+    /// Values to de-anonymize; stdin is read if none are given
+    values: Vec<String>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let args = Args::parse();
+    let anonymizer = Anonymizer::load(&args.key_path, &args.exempt)?;
+    let mut out = io::stdout().lock();
+
+    if !args.values.is_empty() {
+        for value in &args.values {
+            match anonymizer.deanonymize(value) {
+                Some(original) => writeln!(out, "{}", original)?,
+                None => return Err(format!("not an address: {}", value).into()),
+            }
+        }
+        return Ok(());
+    }
+
+    for line in io::stdin().lock().lines() {
+        writeln!(out, "{}", replace_addresses(&anonymizer, &line?))?;
+    }
+    Ok(())
+}
+
+/// Replaces each run of address characters that parses as an address.
+fn replace_addresses(anonymizer: &Anonymizer, line: &str) -> String {
+    let is_address_char = |c: char| c.is_ascii_hexdigit() || c == ':' || c == '.';
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(is_address_char) {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c| !is_address_char(c)).unwrap_or(rest.len());
+        let token = &rest[..end];
+        let original = if is_endpoint_field(&result) {
+            anonymizer.deanonymize_ap(token)
+        } else {
+            anonymizer.deanonymize(token)
+        };
+        match original {
+            Some(original) => result.push_str(&original),
+            None => result.push_str(token),
+        }
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Whether `before` ends with the key of a JSON `ip:port` field, in snake or
+/// camel case, e.g. `"snort_dst_ap": "`.
+fn is_endpoint_field(before: &str) -> bool {
+    let Some(key) = before
+        .trim_end_matches(|c: char| c == '"' || c.is_whitespace())
+        .strip_suffix(':')
+        .map(|key| key.trim_end_matches(|c: char| c == '"' || c.is_whitespace()))
+    else {
+        return false;
+    };
+    let start = key
+        .rfind(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .map_or(0, |i| i + 1);
+    let name = key[start..].replace('_', "").to_ascii_lowercase();
+    matches!(name.as_str(), "snortsrcap" | "snortdstap" | "snorttarget")
+}
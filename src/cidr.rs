@@ -0,0 +1,178 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// An IPv4 or IPv6 network in CIDR notation. A bare address is a host route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = mask_u32(self.prefix_len);
+                u32::from(net) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = mask_u128(self.prefix_len);
+                u128::from(net) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|e| format!("invalid address in {:?}: {}", s, e))?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("invalid prefix length in {:?}", s))?,
+            None => max_len,
+        };
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+fn mask_u32(prefix_len: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0)
+}
+
+fn mask_u128(prefix_len: u8) -> u128 {
+    u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0)
+}
+
+/// Longest-prefix-match map from CIDRs to values.
+///
+/// A binary trie per address family, stored as an arena of nodes indexed by
+/// `u32` to keep large tables compact.
+pub struct CidrMap<V> {
+    nodes: Vec<TrieNode>,
+    values: Vec<V>,
+}
+
+#[derive(Clone, Copy)]
+struct TrieNode {
+    children: [u32; 2],
+    value: u32,
+}
+
+// Index 0 is the IPv4 root and index 1 the IPv6 root, so no node ever points
+// to either of them as a child and 0 doubles as "no child". Values are stored
+// 1-based, so 0 also means "no value".
+const NONE: u32 = 0;
+const V4_ROOT: usize = 0;
+const V6_ROOT: usize = 1;
+
+impl<V> Default for CidrMap<V> {
+    fn default() -> Self {
+        let empty = TrieNode {
+            children: [NONE; 2],
+            value: NONE,
+        };
+        Self {
+            nodes: vec![empty, empty],
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<V> CidrMap<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Inserts `value` for `cidr`, replacing any value stored for the same prefix.
+    pub fn insert(&mut self, cidr: Cidr, value: V) {
+        let (mut node, bits, width) = root_and_bits(&cidr.addr);
+        for i in 0..cidr.prefix_len as u32 {
+            let bit = ((bits >> (width - 1 - i)) & 1) as usize;
+            if self.nodes[node].children[bit] == NONE {
+                self.nodes.push(TrieNode {
+                    children: [NONE; 2],
+                    value: NONE,
+                });
+                self.nodes[node].children[bit] = (self.nodes.len() - 1) as u32;
+            }
+            node = self.nodes[node].children[bit] as usize;
+        }
+
+        match self.nodes[node].value {
+            NONE => {
+                self.values.push(value);
+                self.nodes[node].value = self.values.len() as u32;
+            }
+            existing => self.values[existing as usize - 1] = value,
+        }
+    }
+
+    /// Returns the value stored for exactly `cidr`, if any.
+    pub fn get_mut(&mut self, cidr: &Cidr) -> Option<&mut V> {
+        let (mut node, bits, width) = root_and_bits(&cidr.addr);
+        for i in 0..cidr.prefix_len as u32 {
+            let bit = ((bits >> (width - 1 - i)) & 1) as usize;
+            match self.nodes[node].children[bit] {
+                NONE => return None,
+                child => node = child as usize,
+            }
+        }
+        match self.nodes[node].value {
+            NONE => None,
+            index => Some(&mut self.values[index as usize - 1]),
+        }
+    }
+
+    /// Returns the value of the most specific CIDR containing `ip`.
+    pub fn longest_match(&self, ip: &IpAddr) -> Option<&V> {
+        let (mut node, bits, width) = root_and_bits(ip);
+        let mut best = self.nodes[node].value;
+        for i in 0..width {
+            let bit = ((bits >> (width - 1 - i)) & 1) as usize;
+            match self.nodes[node].children[bit] {
+                NONE => break,
+                child => node = child as usize,
+            }
+            if self.nodes[node].value != NONE {
+                best = self.nodes[node].value;
+            }
+        }
+        match best {
+            NONE => None,
+            index => Some(&self.values[index as usize - 1]),
+        }
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.longest_match(ip).is_some()
+    }
+}
+
+fn root_and_bits(ip: &IpAddr) -> (usize, u128, u32) {
+    match ip {
+        IpAddr::V4(ip) => (V4_ROOT, u32::from(*ip) as u128, 32),
+        IpAddr::V6(ip) => (V6_ROOT, u128::from(*ip), 128),
+    }
+}
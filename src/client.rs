@@ -1,11 +1,340 @@
+use crate::compression::{Compressor, CountingChannel};
 use crate::pb::sensor_service_client::SensorServiceClient;
-use crate::pb::SensorEvent;
-use log::{error, info};
+use crate::pb::{BatchAck, SensorEvent, SensorEventBatch, SequencedBatch};
+use bytes::{Buf, BufMut};
+use log::{error, info, warn};
+use prost::encoding::{DecodeContext, WireType};
+use prost::{DecodeError, Message};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
+use tonic::codec::{CompressionEncoding, ProstCodec};
+use tonic::codegen::http::uri::PathAndQuery;
 use tonic::transport::{Channel, ClientTlsConfig};
 
+// Batches sent without an acknowledgement before the client stops reading
+// new ones. Their encoded size is bounded by the outbox's `max_bytes` too.
+const MAX_UNACKED_BATCHES: usize = 1000;
+
+// Batches kept after a `StreamBatches` or `StreamData` call starts, in case
+// the server turns out not to implement it or not to accept its compression.
+// It answers before reading any of them.
+const PROBE_BATCHES: usize = 8;
+
+// Bytes of a batch message other than its events and stream id: the
+// sequence number field.
+const BATCH_OVERHEAD: usize = 11;
+
+type BatchReceiver = Arc<tokio::sync::Mutex<BatchChannels>>;
+
+/// Set once a batch has been delivered: acknowledged by the server, or sent
+/// to one that does not acknowledge batches.
+#[derive(Clone, Default)]
+pub struct Receipt(Arc<AtomicBool>);
+
+impl Receipt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_delivered(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    fn deliver(&self) {
+        self.0.store(true, Ordering::Release)
+    }
+}
+
+/// A batch on its way to the client, with a receipt if its sender needs to
+/// know when it was delivered.
+pub struct Batch {
+    pub events: Vec<SensorEvent>,
+    pub receipt: Option<Receipt>,
+}
+
+impl From<Vec<SensorEvent>> for Batch {
+    fn from(events: Vec<SensorEvent>) -> Self {
+        Self {
+            events,
+            receipt: None,
+        }
+    }
+}
+
+/// Receiving ends of the express and regular batch channels.
+pub struct BatchChannels {
+    express: mpsc::Receiver<Batch>,
+    regular: mpsc::Receiver<Batch>,
+}
+
+impl BatchChannels {
+    pub fn new(express: mpsc::Receiver<Batch>, regular: mpsc::Receiver<Batch>) -> Self {
+        Self { express, regular }
+    }
+
+    /// Next batch, taking express batches first. Returns `None` once the
+    /// regular channel is closed.
+    pub async fn recv(&mut self) -> Option<Batch> {
+        tokio::select! {
+            biased;
+            Some(batch) = self.express.recv() => Some(batch),
+            batch = self.regular.recv() => batch,
+        }
+    }
+}
+
+/// A batch held by the outbox, sent without copying its events.
+#[derive(Debug, Default, Clone)]
+struct SharedBatch(Arc<SequencedBatch>);
+
+impl Message for SharedBatch {
+    fn encode_raw<B: BufMut>(&self, buf: &mut B) {
+        self.0.encode_raw(buf)
+    }
+
+    fn merge_field<B: Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        Arc::make_mut(&mut self.0).merge_field(tag, wire_type, buf, ctx)
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.0.encoded_len()
+    }
+
+    fn clear(&mut self) {
+        self.0 = Arc::default();
+    }
+}
+
+#[derive(Default)]
+struct Unacked {
+    next_sequence: u64,
+    batches: VecDeque<SharedBatch>,
+    // Encoded size of `batches`
+    bytes: usize,
+    // Receipts of the batches, by the sequence number of their last message
+    receipts: VecDeque<(u64, Receipt)>,
+}
+
+impl Unacked {
+    fn push(&mut self, batch: SequencedBatch) {
+        self.bytes += batch.encoded_len();
+        self.batches.push_back(SharedBatch(Arc::new(batch)));
+    }
+}
+
+/// Batches sent on `StreamDataAcked` that the server has not acknowledged
+/// yet. They outlive a connection and are sent again on the next one, as are
+/// batches of a call the server turned out not to implement.
+#[derive(Default)]
+pub struct Outbox {
+    // Identifies this process's numbering to the server
+    stream_id: String,
+    // Encoded size of unacknowledged batches at which no new ones are read;
+    // 0 disables
+    max_bytes: usize,
+    unacked: Mutex<Unacked>,
+    // Set once a server turned out not to implement `StreamDataAcked` or
+    // `StreamBatches`
+    acks_unsupported: AtomicBool,
+    batches_unsupported: AtomicBool,
+    // Metrics
+    total_acked: AtomicI64,
+    total_resent: AtomicI64,
+}
+
+impl Outbox {
+    pub fn new(sensor_id: &str, max_bytes: usize) -> Self {
+        Self {
+            stream_id: format!("{}-{:016x}", sensor_id, rand::random::<u64>()),
+            max_bytes,
+            ..Default::default()
+        }
+    }
+
+    pub fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    /// Splits the batch into messages, numbers them and keeps them until
+    /// they are acknowledged.
+    fn push(&self, batch: Batch, max_bytes: usize) {
+        let chunks = split_batch(batch.events, max_bytes, &self.stream_id);
+        let mut unacked = self.unacked.lock().unwrap();
+        for events in chunks {
+            unacked.next_sequence += 1;
+            let batch = SequencedBatch {
+                sequence: unacked.next_sequence,
+                events,
+                stream_id: self.stream_id.clone(),
+            };
+            unacked.push(batch);
+        }
+        if let Some(receipt) = batch.receipt {
+            let sequence = unacked.next_sequence;
+            unacked.receipts.push_back((sequence, receipt));
+        }
+    }
+
+    /// The first unacknowledged batch numbered above `sequence`.
+    fn next_after(&self, sequence: u64) -> Option<SharedBatch> {
+        self.unacked
+            .lock()
+            .unwrap()
+            .batches
+            .iter()
+            .find(|b| b.0.sequence > sequence)
+            .cloned()
+    }
+
+    fn ack(&self, sequence: u64) {
+        let mut unacked = self.unacked.lock().unwrap();
+        while unacked
+            .batches
+            .front()
+            .is_some_and(|b| b.0.sequence <= sequence)
+        {
+            let batch = unacked.batches.pop_front().unwrap();
+            unacked.bytes -= batch.encoded_len();
+            self.total_acked.fetch_add(1, Ordering::Relaxed);
+        }
+        while unacked.receipts.front().is_some_and(|r| r.0 <= sequence) {
+            unacked.receipts.pop_front().unwrap().1.deliver();
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        let unacked = self.unacked.lock().unwrap();
+        unacked.batches.len() >= MAX_UNACKED_BATCHES
+            || (self.max_bytes > 0 && unacked.bytes >= self.max_bytes)
+    }
+
+    /// Takes the unacknowledged batches, and the receipts to deliver once
+    /// they have been sent.
+    fn take_unacked(&self) -> (Vec<SequencedBatch>, Vec<Receipt>) {
+        let mut unacked = self.unacked.lock().unwrap();
+        unacked.bytes = 0;
+        let batches = unacked
+            .batches
+            .drain(..)
+            .map(|b| Arc::try_unwrap(b.0).unwrap_or_else(|b| (*b).clone()))
+            .collect();
+        let receipts = unacked.receipts.drain(..).map(|(_, r)| r).collect();
+        (batches, receipts)
+    }
+
+    /// Puts back batches of a call the server did not implement, for the
+    /// next call to send first.
+    fn restore(&self, batches: Vec<Vec<SensorEvent>>) {
+        let mut unacked = self.unacked.lock().unwrap();
+        for events in batches {
+            unacked.push(SequencedBatch {
+                sequence: 0,
+                events,
+                stream_id: self.stream_id.clone(),
+            });
+        }
+    }
+
+    pub fn get_unacked_batches(&self) -> usize {
+        self.unacked.lock().unwrap().batches.len()
+    }
+
+    pub fn get_unacked_bytes(&self) -> usize {
+        self.unacked.lock().unwrap().bytes
+    }
+
+    pub fn get_total_acked(&self) -> i64 {
+        self.total_acked.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_resent(&self) -> i64 {
+        self.total_resent.load(Ordering::Relaxed)
+    }
+}
+
+/// Splits a batch into chunks whose messages stay within `max_bytes` once
+/// encoded; `0` leaves it whole. An event larger than that is sent on its own.
+/// `stream_id` is the one the messages carry, empty if they have none.
+pub fn split_batch(
+    events: Vec<SensorEvent>,
+    max_bytes: usize,
+    stream_id: &str,
+) -> Vec<Vec<SensorEvent>> {
+    if max_bytes == 0 {
+        return vec![events];
+    }
+    let overhead = match stream_id.len() {
+        0 => BATCH_OVERHEAD,
+        len => BATCH_OVERHEAD + 1 + prost::encoding::encoded_len_varint(len as u64) + len,
+    };
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    let mut chunk_bytes = overhead;
+    for event in events {
+        let len = event.encoded_len();
+        let bytes = 1 + prost::encoding::encoded_len_varint(len as u64) + len;
+        if !chunk.is_empty() && chunk_bytes + bytes > max_bytes {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_bytes = overhead;
+        }
+        if chunk_bytes + bytes > max_bytes {
+            warn!(
+                "Event {} of {} bytes exceeds the message budget of {} bytes",
+                event.event_hash_sha256, bytes, max_bytes
+            );
+        }
+        chunk_bytes += bytes;
+        chunk.push(event);
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+fn retain(sent: &Mutex<Vec<Vec<SensorEvent>>>, limit: usize, events: &[SensorEvent]) {
+    let mut sent = sent.lock().unwrap();
+    if sent.len() < limit {
+        sent.push(events.to_vec());
+    }
+}
+
+/// Result of a streaming call, or `None` if the server does not implement it.
+/// A call rejected for its compression fails, to be retried with another.
+fn finish(
+    result: Result<(), tonic::Status>,
+    compressor: &Compressor,
+) -> Option<Result<(), Box<dyn std::error::Error + Send + Sync>>> {
+    match result {
+        Err(status) if compressor.renegotiate(&status) => Some(Err(Box::new(status))),
+        Err(status) if status.code() == tonic::Code::Unimplemented => None,
+        Err(status) => {
+            error!("Stream failed: {}", status);
+            Some(Err(Box::new(status)))
+        }
+        Ok(()) => {
+            info!("Stream completed successfully");
+            Some(Ok(()))
+        }
+    }
+}
+
 pub struct Client {
-    client: SensorServiceClient<Channel>,
+    client: SensorServiceClient<CountingChannel>,
+    // `StreamDataAcked` is called directly, to send batches from the outbox
+    // without copying them
+    acked: tonic::client::Grpc<CountingChannel>,
+    // Encoded size that batch messages are kept within
+    message_max_bytes: usize,
+    compressor: Arc<Compressor>,
 }
 
 impl Client {
@@ -13,6 +342,8 @@ impl Client {
         host: &str,
         port: u16,
         insecure: bool,
+        message_max_bytes: usize,
+        compressor: Arc<Compressor>,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let addr = format!("http://{}:{}", host, port);
         let endpoint = Channel::from_shared(addr)?;
@@ -24,19 +355,210 @@ impl Client {
             endpoint.tls_config(tls)?.connect().await?
         };
 
-        let client = SensorServiceClient::new(channel);
-        info!("Connected to gRPC server at {}:{}", host, port);
+        let channel = CountingChannel::new(channel, compressor.clone());
+        let mut client = SensorServiceClient::new(channel.clone())
+            .accept_compressed(CompressionEncoding::Gzip)
+            .accept_compressed(CompressionEncoding::Zstd);
+        let mut acked = tonic::client::Grpc::new(channel)
+            .accept_compressed(CompressionEncoding::Gzip)
+            .accept_compressed(CompressionEncoding::Zstd);
+        let compression = compressor.current();
+        if let Some(encoding) = compression.encoding() {
+            client = client.send_compressed(encoding);
+            acked = acked.send_compressed(encoding);
+        }
+        info!(
+            "Connected to gRPC server at {}:{} (compression: {})",
+            host,
+            port,
+            compression.name()
+        );
 
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            acked,
+            message_max_bytes,
+            compressor,
+        })
     }
 
+    /// Streams batches from `rx` until the stream fails or ends. Batches are
+    /// acknowledged by the server if it implements `StreamDataAcked`;
+    /// otherwise they are sent one message per batch on `StreamBatches`, or
+    /// as individual events on `StreamData`.
     pub async fn stream_data(
         &mut self,
-        rx: std::sync::Arc<tokio::sync::Mutex<mpsc::Receiver<Vec<SensorEvent>>>>,
+        rx: BatchReceiver,
+        outbox: Arc<Outbox>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !outbox.acks_unsupported.load(Ordering::Relaxed) {
+            let result = self.stream_acked(rx.clone(), outbox.clone()).await;
+            match finish(result, &self.compressor) {
+                Some(result) => return result,
+                None => {
+                    warn!("Server does not implement StreamDataAcked; sending without acknowledgements");
+                    outbox.acks_unsupported.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        if !outbox.batches_unsupported.load(Ordering::Relaxed) {
+            let result = self.stream_batches(rx.clone(), outbox.clone()).await;
+            match finish(result, &self.compressor) {
+                Some(result) => return result,
+                None => {
+                    warn!("Server does not implement StreamBatches; sending individual events");
+                    outbox.batches_unsupported.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        let result = self.stream_events(rx, outbox).await;
+        finish(result, &self.compressor)
+            .unwrap_or_else(|| Err("server does not implement StreamData".into()))
+    }
+
+    async fn stream_acked(
+        &mut self,
+        rx: BatchReceiver,
+        outbox: Arc<Outbox>,
+    ) -> Result<(), tonic::Status> {
+        // Resent batches go even before express ones, since an
+        // acknowledgement covers every lower sequence number.
+        let resend = outbox.get_unacked_batches();
+        if resend > 0 {
+            info!("Resending {} unacknowledged batches", resend);
+            outbox
+                .total_resent
+                .fetch_add(resend as i64, Ordering::Relaxed);
+        }
+
+        let stream_outbox = outbox.clone();
+        let compressor = self.compressor.clone();
+        let max_bytes = self.message_max_bytes;
+        // Batches are only sent from the outbox, and go into it as soon as
+        // they are received, so none is lost when the call fails.
+        let stream = async_stream::stream! {
+            info!("Starting gRPC request stream with acknowledgements");
+            let mut sent = 0;
+            loop {
+                if let Some(batch) = stream_outbox.next_after(sent) {
+                    sent = batch.0.sequence;
+                    compressor.record(batch.encoded_len());
+                    yield batch;
+                    continue;
+                }
+                // Wait for acknowledgements before reading more
+                if stream_outbox.is_full() {
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                    continue;
+                }
+                let batch = {
+                    let mut rx_guard = rx.lock().await;
+                    rx_guard.recv().await
+                };
+                let batch = match batch {
+                    Some(batch) => batch,
+                    None => break,
+                };
+                info!("Sending batch of {} events", batch.events.len());
+                stream_outbox.push(batch, max_bytes);
+            }
+            info!("gRPC request stream ended");
+        };
+
+        self.acked
+            .ready()
+            .await
+            .map_err(|e| tonic::Status::unknown(format!("Service was not ready: {}", e)))?;
+        let path = PathAndQuery::from_static("/pb.SensorService/StreamDataAcked");
+        let codec = ProstCodec::<SharedBatch, BatchAck>::default();
+        let request = tonic::Request::new(stream);
+        let mut acks = self
+            .acked
+            .streaming(request, path, codec)
+            .await?
+            .into_inner();
+        while let Some(ack) = acks.message().await? {
+            outbox.ack(ack.sequence);
+        }
+        Ok(())
+    }
+
+    async fn stream_batches(
+        &mut self,
+        rx: BatchReceiver,
+        outbox: Arc<Outbox>,
+    ) -> Result<(), tonic::Status> {
+        // Batches left from an acknowledged stream go first
+        let (pending, receipts) = outbox.take_unacked();
+
+        let limit = pending.len() + PROBE_BATCHES;
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let stream_sent = sent.clone();
+        let compressor = self.compressor.clone();
+        let max_bytes = self.message_max_bytes;
+        let stream = async_stream::stream! {
+            info!("Starting gRPC request stream of batches");
+            for batch in pending {
+                retain(&stream_sent, limit, &batch.events);
+                let batch = SensorEventBatch { events: batch.events };
+                compressor.record(batch.encoded_len());
+                yield batch;
+            }
+            receipts.iter().for_each(Receipt::deliver);
+            loop {
+                let batch = {
+                    let mut rx_guard = rx.lock().await;
+                    rx_guard.recv().await
+                };
+                let batch = match batch {
+                    Some(batch) => batch,
+                    None => break,
+                };
+                info!("Sending batch of {} events", batch.events.len());
+                for events in split_batch(batch.events, max_bytes, "") {
+                    retain(&stream_sent, limit, &events);
+                    let batch = SensorEventBatch { events };
+                    compressor.record(batch.encoded_len());
+                    yield batch;
+                }
+                batch.receipt.iter().for_each(Receipt::deliver);
+            }
+            info!("gRPC request stream ended");
+        };
+
+        let request = tonic::Request::new(stream);
+        let result = self.client.stream_batches(request).await.map(|_| ());
+        if let Err(status) = &result {
+            if status.code() == tonic::Code::Unimplemented {
+                outbox.restore(std::mem::take(&mut *sent.lock().unwrap()));
+            }
+        }
+        result
+    }
+
+    async fn stream_events(
+        &mut self,
+        rx: BatchReceiver,
+        outbox: Arc<Outbox>,
+    ) -> Result<(), tonic::Status> {
+        // Batches left from an acknowledged stream go first
+        let (pending, receipts) = outbox.take_unacked();
+
+        let limit = pending.len() + PROBE_BATCHES;
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let stream_sent = sent.clone();
+        let compressor = self.compressor.clone();
         // Flatten the stream of batches into a stream of individual events
         let stream = async_stream::stream! {
             info!("Starting gRPC request stream");
+            for batch in pending {
+                retain(&stream_sent, limit, &batch.events);
+                for event in batch.events {
+                    compressor.record(event.encoded_len());
+                    yield event;
+                }
+            }
+            receipts.iter().for_each(Receipt::deliver);
             loop {
                 let batch = {
                     let mut rx_guard = rx.lock().await;
@@ -44,10 +566,13 @@ impl Client {
                 };
                 match batch {
                     Some(batch) => {
-                        info!("Sending batch of {} events", batch.len());
-                        for event in batch {
+                        info!("Sending batch of {} events", batch.events.len());
+                        retain(&stream_sent, limit, &batch.events);
+                        for event in batch.events {
+                            compressor.record(event.encoded_len());
                             yield event;
                         }
+                        batch.receipt.iter().for_each(Receipt::deliver);
                     }
                     None => break,
                 }
@@ -56,16 +581,12 @@ impl Client {
         };
 
         let request = tonic::Request::new(stream);
-
-        match self.client.stream_data(request).await {
-            Ok(_) => {
-                info!("Stream completed successfully");
-                Ok(())
-            }
-            Err(e) => {
-                error!("Stream failed: {}", e);
-                Err(Box::new(e))
+        let result = self.client.stream_data(request).await.map(|_| ());
+        if let Err(status) = &result {
+            if status.code() == tonic::Code::Unimplemented {
+                outbox.restore(std::mem::take(&mut *sent.lock().unwrap()));
             }
         }
+        result
     }
 }
@@ -0,0 +1,106 @@
+use crate::types::SuricataAlert;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use sha1::{Digest, Sha1};
+use std::net::IpAddr;
+
+const ICMP: u8 = 1;
+const TCP: u8 = 6;
+const UDP: u8 = 17;
+const ICMP6: u8 = 58;
+const SCTP: u8 = 132;
+
+/// Community ID v1 flow hash of the record's 5-tuple, e.g.
+/// `1:LQU9qZlK+B5F3KDmev6m5PMibrg=`. Returns `None` if the addresses or
+/// protocol are missing or unknown.
+pub fn community_id(data: &SuricataAlert, seed: u16) -> Option<String> {
+    let src: IpAddr = data.src_ip.as_deref()?.parse().ok()?;
+    let dst: IpAddr = data.dest_ip.as_deref()?.parse().ok()?;
+    let proto = protocol_number(data.proto.as_deref()?)?;
+
+    let (mut sport, mut dport, one_way) = match proto {
+        ICMP | ICMP6 => {
+            let icmp_type = data.icmp_type.unwrap_or(0) as u16;
+            let icmp_code = data.icmp_code.unwrap_or(0) as u16;
+            match icmp_counterpart(proto, icmp_type) {
+                Some(counterpart) => (icmp_type, counterpart, false),
+                None => (icmp_type, icmp_code, true),
+            }
+        }
+        _ => (
+            data.src_port.unwrap_or(0) as u16,
+            data.dest_port.unwrap_or(0) as u16,
+            false,
+        ),
+    };
+
+    let (mut src, mut dst) = (octets(&src), octets(&dst));
+    if src.len() != dst.len() {
+        return None;
+    }
+    if !one_way && src.cmp(&dst).then(sport.cmp(&dport)).is_gt() {
+        std::mem::swap(&mut src, &mut dst);
+        std::mem::swap(&mut sport, &mut dport);
+    }
+
+    let mut hasher = Sha1::new();
+    hasher.update(seed.to_be_bytes());
+    hasher.update(&src);
+    hasher.update(&dst);
+    hasher.update([proto, 0]);
+    if matches!(proto, ICMP | TCP | UDP | ICMP6 | SCTP) {
+        hasher.update(sport.to_be_bytes());
+        hasher.update(dport.to_be_bytes());
+    }
+    Some(format!("1:{}", BASE64.encode(hasher.finalize())))
+}
+
+/// Maps EVE `proto` values (`TCP`, `IPv6-ICMP`, `47`, ...) to IP protocol
+/// numbers.
+fn protocol_number(proto: &str) -> Option<u8> {
+    match proto.to_ascii_uppercase().as_str() {
+        "ICMP" => Some(ICMP),
+        "TCP" => Some(TCP),
+        "UDP" => Some(UDP),
+        "IPV6-ICMP" | "ICMPV6" | "ICMP6" => Some(ICMP6),
+        "SCTP" => Some(SCTP),
+        "GRE" => Some(47),
+        "ESP" => Some(50),
+        "AH" => Some(51),
+        other => other.parse().ok(),
+    }
+}
+
+/// The message type answering `icmp_type`, for request/response pairs that
+/// are treated like the two directions of a connection.
+fn icmp_counterpart(proto: u8, icmp_type: u16) -> Option<u16> {
+    let pairs: &[(u16, u16)] = if proto == ICMP {
+        // Echo, router advertisement/solicitation, timestamp, information,
+        // address mask.
+        &[(8, 0), (9, 10), (13, 14), (15, 16), (17, 18)]
+    } else {
+        // Echo, MLD query/report, router solicitation/advertisement,
+        // neighbor solicitation/advertisement, node information, home agent
+        // address discovery.
+        &[
+            (128, 129),
+            (130, 131),
+            (133, 134),
+            (135, 136),
+            (139, 140),
+            (144, 145),
+        ]
+    };
+    pairs.iter().find_map(|&(a, b)| match icmp_type {
+        t if t == a => Some(b),
+        t if t == b => Some(a),
+        _ => None,
+    })
+}
+
+fn octets(ip: &IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(v4) => v4.octets().to_vec(),
+        IpAddr::V6(v6) => v6.octets().to_vec(),
+    }
+}
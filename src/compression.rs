@@ -0,0 +1,193 @@
+use bytes::Bytes;
+use http_body::Body;
+use log::warn;
+use serde::Deserialize;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI64, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::codec::CompressionEncoding;
+use tonic::transport::Channel;
+use tonic::Status;
+
+// Bytes of the frame header in front of each gRPC message
+const FRAME_HEADER: usize = 5;
+
+/// Compression of the requests sent to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Compression {
+    /// Send messages uncompressed
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    // Encodings to try when the server rejects one, in order
+    const ALL: [Compression; 3] = [Compression::Zstd, Compression::Gzip, Compression::None];
+
+    /// Name in the `grpc-encoding` header.
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "identity",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    pub fn encoding(self) -> Option<CompressionEncoding> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some(CompressionEncoding::Gzip),
+            Compression::Zstd => Some(CompressionEncoding::Zstd),
+        }
+    }
+}
+
+/// The compression in use with the server, which can change when it
+/// rejects one, and the bytes of the messages sent before and after it.
+pub struct Compressor {
+    preferred: Compression,
+    current: AtomicU8,
+    // Metrics, including the frame header of each message
+    total_uncompressed: AtomicI64,
+    total_compressed: AtomicI64,
+}
+
+impl Compressor {
+    pub fn new(preferred: Compression) -> Self {
+        Self {
+            preferred,
+            current: AtomicU8::new(preferred as u8),
+            total_uncompressed: AtomicI64::new(0),
+            total_compressed: AtomicI64::new(0),
+        }
+    }
+
+    pub fn current(&self) -> Compression {
+        Compression::ALL
+            .into_iter()
+            .find(|c| *c as u8 == self.current.load(Ordering::Relaxed))
+            .unwrap_or_default()
+    }
+
+    /// Switches to an encoding the server accepts if `status` rejected the
+    /// current one. A server rejects an encoding it does not support with
+    /// `Unimplemented` and lists those it does in `grpc-accept-encoding`.
+    pub fn renegotiate(&self, status: &Status) -> bool {
+        let current = self.current();
+        if current == Compression::None || status.code() != tonic::Code::Unimplemented {
+            return false;
+        }
+        let accepted = match status
+            .metadata()
+            .get("grpc-accept-encoding")
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(accepted) => accepted,
+            None => return false,
+        };
+        let accepts = |c: Compression| {
+            c == Compression::None || accepted.split(',').any(|e| e.trim() == c.name())
+        };
+        if accepts(current) {
+            return false;
+        }
+        let next = std::iter::once(self.preferred)
+            .chain(Compression::ALL)
+            .find(|c| accepts(*c))
+            .unwrap_or_default();
+        warn!(
+            "Server does not accept {} compression (accepts {}); switching to {}",
+            current.name(),
+            accepted,
+            next.name()
+        );
+        self.current.store(next as u8, Ordering::Relaxed);
+        true
+    }
+
+    /// Counts a message of `len` bytes before compression.
+    pub fn record(&self, len: usize) {
+        self.total_uncompressed
+            .fetch_add((FRAME_HEADER + len) as i64, Ordering::Relaxed);
+    }
+
+    pub fn get_total_uncompressed(&self) -> i64 {
+        self.total_uncompressed.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_compressed(&self) -> i64 {
+        self.total_compressed.load(Ordering::Relaxed)
+    }
+}
+
+/// A channel that counts the request bytes it sends, after compression.
+#[derive(Clone)]
+pub struct CountingChannel {
+    inner: Channel,
+    compressor: Arc<Compressor>,
+}
+
+impl CountingChannel {
+    pub fn new(inner: Channel, compressor: Arc<Compressor>) -> Self {
+        Self { inner, compressor }
+    }
+}
+
+impl tower::Service<http::Request<BoxBody>> for CountingChannel {
+    type Response = <Channel as tower::Service<http::Request<BoxBody>>>::Response;
+    type Error = <Channel as tower::Service<http::Request<BoxBody>>>::Error;
+    type Future = <Channel as tower::Service<http::Request<BoxBody>>>::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        tower::Service::poll_ready(&mut self.inner, cx)
+    }
+
+    fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+        let compressor = self.compressor.clone();
+        let request = request.map(|inner| BoxBody::new(CountingBody { inner, compressor }));
+        tower::Service::call(&mut self.inner, request)
+    }
+}
+
+struct CountingBody {
+    inner: BoxBody,
+    compressor: Arc<Compressor>,
+}
+
+impl Body for CountingBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Status>>> {
+        let poll = Pin::new(&mut self.inner).poll_data(cx);
+        if let Poll::Ready(Some(Ok(data))) = &poll {
+            self.compressor
+                .total_compressed
+                .fetch_add(data.len() as i64, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Status>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -1,3 +1,6 @@
+use crate::compression::Compression;
+use crate::correlate::CorrelationMode;
+use crate::queue::OverflowPolicy;
 use config::{Config, ConfigError, Environment};
 use serde::Deserialize;
 
@@ -7,13 +10,55 @@ pub struct ClientConfig {
     pub server: String,
     pub port: u16,
     pub insecure: bool,
-    pub interval: u64, // Duration in seconds
+    pub interval: u64,      // Duration in seconds
+    pub batch_max_age: u64, // Duration in seconds
+    pub batch_max_events: usize,
+    pub batch_max_bytes: usize,
+    pub queue_max_records: usize,
+    pub queue_max_record_metrics: usize,
+    pub queue_max_bytes: usize,
+    pub queue_overflow: OverflowPolicy,
+    pub spool_path: Option<String>,
+    pub spool_max_bytes: u64,
+    pub spool_max_age: u64, // Duration in seconds
     pub sensor_id: String,
     pub sensor_version: String,
     pub testing_mode: bool,
     pub max_clients: Option<usize>,
-    pub max_message_size: usize,
+    pub message_max_bytes: usize,
+    pub compression: Compression,
     pub verbose: usize,
+    pub rules_path: Option<String>,
+    pub reference_config: Option<String>,
+    pub reload_interval: u64, // Duration in seconds
+    pub target_roles: bool,
+    pub filter_path: Option<String>,
+    pub threshold_path: Option<String>,
+    pub geoip_city_path: Option<String>,
+    pub geoip_asn_path: Option<String>,
+    pub home_net: Option<String>,
+    pub asset_inventory_path: Option<String>,
+    pub ioc_path: Option<String>,
+    pub pdns_max_entries: usize,
+    pub privacy_path: Option<String>,
+    pub anonymize_key_path: Option<String>,
+    pub anonymize_exempt: Option<String>,
+    pub scripts_path: Option<String>,
+    pub script_timeout_ms: u64,
+    pub plugins_path: Option<String>,
+    pub plugin_fuel: u64,
+    pub plugin_max_output_bytes: usize,
+    pub community_id_seed: u16,
+    pub attack_table_path: Option<String>,
+    pub attack_bundle_path: Option<String>,
+    pub correlation: CorrelationMode,
+    pub correlation_window: u64,       // Duration in seconds
+    pub flow_holdback_timeout: u64,    // Duration in seconds, 0 disables
+    pub flow_holdback_max_held: usize, // 0 is no limit
+    pub priority_max_severity: i64,    // 0 disables
+    pub priority_sids: Option<String>,
+    pub priority_ioc: bool,
+    pub priority_interval_ms: u64,
 }
 
 impl ClientConfig {
@@ -25,12 +70,38 @@ impl ClientConfig {
             .set_default("port", 50051)?
             .set_default("insecure", true)?
             .set_default("interval", 1)?
+            .set_default("batch_max_age", 30)?
+            .set_default("batch_max_events", 10_000)?
+            .set_default("batch_max_bytes", 4 * 1024 * 1024)?
+            .set_default("queue_max_records", 100_000)?
+            .set_default("queue_max_record_metrics", 10_000)?
+            .set_default("queue_max_bytes", 256 * 1024 * 1024)?
+            .set_default("queue_overflow", "evict-oldest")?
+            .set_default("spool_max_bytes", 1024 * 1024 * 1024)?
+            .set_default("spool_max_age", 7 * 24 * 3600)?
             .set_default("sensor_id", "sensor1")?
             .set_default("sensor_version", "unknown")?
             .set_default("testing_mode", false)?
             // max_clients default handled in main.rs
-            .set_default("max_message_size", 100)?
+            .set_default("message_max_bytes", 4 * 1024 * 1024)?
+            .set_default("compression", "none")?
             .set_default("verbose", 0)?
+            // Enrichment/filter file paths (rules_path, filter_path, geoip_*_path, ...)
+            // are unset unless wanted
+            .set_default("reload_interval", 30)?
+            .set_default("target_roles", false)?
+            .set_default("pdns_max_entries", 0)?
+            .set_default("script_timeout_ms", 50)?
+            .set_default("plugin_fuel", 10_000_000)?
+            .set_default("plugin_max_output_bytes", 1024 * 1024)?
+            .set_default("community_id_seed", 0)?
+            .set_default("correlation", "none")?
+            .set_default("correlation_window", 60)?
+            .set_default("flow_holdback_timeout", 0)?
+            .set_default("flow_holdback_max_held", 100000)?
+            .set_default("priority_max_severity", 0)?
+            .set_default("priority_ioc", false)?
+            .set_default("priority_interval_ms", 100)?
             // Add in settings from the environment (with a prefix of MES_CLIENT)
             .add_source(Environment::with_prefix("MES_CLIENT"))
             .build()?;
@@ -0,0 +1,264 @@
+use crate::pb::SensorEvent;
+use crate::types::SuricataAlert;
+use serde::Deserialize;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// A session is released once it holds this many metrics, to bound message
+// size for long-lived flows that keep alerting.
+const MAX_SESSION_METRICS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum CorrelationMode {
+    /// Every alert is its own event
+    #[default]
+    None,
+    /// Alerts of one flow (`flow_id`), whatever their signature
+    Flow,
+    /// Alerts of one signature in one flow
+    FlowSignature,
+    /// Alerts of one signature and 5-tuple
+    Tuple,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum SessionKey {
+    Flow(i64),
+    FlowSignature(i64, (i64, i64, i64)),
+    Tuple(
+        (i64, i64, i64),
+        String,
+        Option<String>,
+        Option<i64>,
+        Option<String>,
+        Option<i64>,
+    ),
+}
+
+struct Session {
+    event: SensorEvent,
+    last_seen: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    sessions: HashMap<SessionKey, Session>,
+    // Sessions touched by each flow, closed when its flow record arrives.
+    flows: HashMap<i64, Vec<SessionKey>>,
+}
+
+/// Groups the alerts of a session into one multi-metric event.
+///
+/// A session is closed when no alert was added to it for `window`, when
+/// Suricata logs the `flow` record of its flow, or when it reaches
+/// `MAX_SESSION_METRICS`. The event keeps the signature and tuple of the
+/// first alert of its session.
+pub struct Correlator {
+    mode: CorrelationMode,
+    window: Duration,
+    // Sessions are spread over shards by flow so that workers rarely contend
+    // on the same lock; by 5-tuple in `tuple` mode, where a session can span
+    // flows.
+    shards: Vec<Mutex<State>>,
+    hasher: RandomState,
+    // Metrics
+    total_correlated: AtomicI64,
+    total_flow_closed: AtomicI64,
+    total_expired: AtomicI64,
+}
+
+impl Correlator {
+    pub fn new(mode: CorrelationMode, window: Duration, shards: usize) -> Self {
+        let shards = shards.max(1).next_power_of_two();
+        Self {
+            mode,
+            window,
+            shards: (0..shards).map(|_| Mutex::new(State::default())).collect(),
+            hasher: RandomState::new(),
+            total_correlated: AtomicI64::new(0),
+            total_flow_closed: AtomicI64::new(0),
+            total_expired: AtomicI64::new(0),
+        }
+    }
+
+    pub fn mode(&self) -> CorrelationMode {
+        self.mode
+    }
+
+    /// Adds the event of an alert to its session. Returns an event that is
+    /// ready to be queued: the event itself when it is not correlated, or
+    /// the session it filled up.
+    pub fn add(&self, data: &SuricataAlert, mut event: SensorEvent) -> Option<SensorEvent> {
+        let key = match self.session_key(data, &event) {
+            Some(key) => key,
+            None => return Some(event),
+        };
+        let now = Instant::now();
+        let seen = event.metrics.last().map(|m| m.snort_timestamp.clone());
+
+        let mut state = self.shard(&key).lock().unwrap();
+        if let Some(flow_id) = data.flow_id {
+            let keys = state.flows.entry(flow_id).or_default();
+            if !keys.contains(&key) {
+                keys.push(key.clone());
+            }
+        }
+        match state.sessions.get_mut(&key) {
+            Some(session) => {
+                self.total_correlated.fetch_add(1, Ordering::Relaxed);
+                session.event.metrics.append(&mut event.metrics);
+                session.event.event_metrics_count = session.event.metrics.len() as i64;
+                session.event.event_last_seen = seen;
+                session.last_seen = now;
+                if session.event.metrics.len() >= MAX_SESSION_METRICS {
+                    return state.sessions.remove(&key).map(|s| s.event);
+                }
+                None
+            }
+            None => {
+                event.event_metrics_count = event.metrics.len() as i64;
+                event.event_first_seen = seen.clone();
+                event.event_last_seen = seen;
+                state.sessions.insert(
+                    key,
+                    Session {
+                        event,
+                        last_seen: now,
+                    },
+                );
+                None
+            }
+        }
+    }
+
+    /// Closes the sessions of the flow if `data` is its `flow` record.
+    pub fn end_flow(&self, data: &SuricataAlert) -> Vec<SensorEvent> {
+        let flow_id = match data.flow_id {
+            Some(flow_id) if data.event_type.as_deref() == Some("flow") => flow_id,
+            _ => return Vec::new(),
+        };
+        let shards = match self.mode {
+            CorrelationMode::None => return Vec::new(),
+            CorrelationMode::Tuple => &self.shards[..],
+            CorrelationMode::Flow | CorrelationMode::FlowSignature => {
+                std::slice::from_ref(self.flow_shard(flow_id))
+            }
+        };
+
+        let mut closed = Vec::new();
+        for shard in shards {
+            let mut state = shard.lock().unwrap();
+            let keys = state.flows.remove(&flow_id).unwrap_or_default();
+            closed.extend(
+                keys.iter()
+                    .filter_map(|key| state.sessions.remove(key))
+                    .map(|session| session.event),
+            );
+        }
+        self.total_flow_closed
+            .fetch_add(closed.len() as i64, Ordering::Relaxed);
+        closed
+    }
+
+    /// Closes sessions that saw no alert for the window.
+    pub fn expire(&self) -> Vec<SensorEvent> {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        for shard in &self.shards {
+            let mut state = shard.lock().unwrap();
+            let expired_keys: Vec<SessionKey> = state
+                .sessions
+                .iter()
+                .filter(|(_, session)| now.duration_since(session.last_seen) >= self.window)
+                .map(|(key, _)| key.clone())
+                .collect();
+            if expired_keys.is_empty() {
+                continue;
+            }
+
+            expired.extend(
+                expired_keys
+                    .iter()
+                    .filter_map(|key| state.sessions.remove(key))
+                    .map(|session| session.event),
+            );
+            let State { sessions, flows } = &mut *state;
+            flows.retain(|_, keys| {
+                keys.retain(|key| sessions.contains_key(key));
+                !keys.is_empty()
+            });
+        }
+        self.total_expired
+            .fetch_add(expired.len() as i64, Ordering::Relaxed);
+        expired
+    }
+
+    fn session_key(&self, data: &SuricataAlert, event: &SensorEvent) -> Option<SessionKey> {
+        let rule = (
+            event.snort_rule_gid,
+            event.snort_rule_sid,
+            event.snort_rule_rev,
+        );
+        let tuple = || {
+            SessionKey::Tuple(
+                rule,
+                event.snort_protocol.clone(),
+                data.src_ip.clone(),
+                data.src_port,
+                data.dest_ip.clone(),
+                data.dest_port,
+            )
+        };
+        match self.mode {
+            CorrelationMode::None => None,
+            CorrelationMode::Flow => match data.flow_id {
+                Some(flow_id) => Some(SessionKey::Flow(flow_id)),
+                None => Some(tuple()),
+            },
+            CorrelationMode::FlowSignature => match data.flow_id {
+                Some(flow_id) => Some(SessionKey::FlowSignature(flow_id, rule)),
+                None => Some(tuple()),
+            },
+            CorrelationMode::Tuple => Some(tuple()),
+        }
+    }
+
+    fn shard(&self, key: &SessionKey) -> &Mutex<State> {
+        match key {
+            SessionKey::Flow(flow_id) | SessionKey::FlowSignature(flow_id, _) => {
+                self.flow_shard(*flow_id)
+            }
+            SessionKey::Tuple(..) => {
+                &self.shards[self.hasher.hash_one(key) as usize & (self.shards.len() - 1)]
+            }
+        }
+    }
+
+    fn flow_shard(&self, flow_id: i64) -> &Mutex<State> {
+        &self.shards[self.hasher.hash_one(flow_id) as usize & (self.shards.len() - 1)]
+    }
+
+    pub fn get_open_sessions(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().sessions.len())
+            .sum()
+    }
+
+    pub fn get_total_correlated(&self) -> i64 {
+        self.total_correlated.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_flow_closed(&self) -> i64 {
+        self.total_flow_closed.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_expired(&self) -> i64 {
+        self.total_expired.load(Ordering::Relaxed)
+    }
+}
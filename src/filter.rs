@@ -0,0 +1,242 @@
+use crate::cidr::Cidr;
+use crate::types::SuricataAlert;
+use config::{Config, File};
+use dashmap::DashMap;
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterMode {
+    Include,
+    Exclude,
+}
+
+/// On-disk filter definition (TOML, YAML or JSON, picked by file extension).
+///
+/// Rules are evaluated in order and the first rule whose conditions all match
+/// decides; `default` applies when none does. Empty conditions match anything.
+#[derive(Debug, Deserialize)]
+struct FilterFile {
+    #[serde(default = "default_mode")]
+    default: FilterMode,
+    #[serde(default)]
+    rules: Vec<FilterRuleConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FilterRuleConfig {
+    name: String,
+    mode: FilterMode,
+    #[serde(default)]
+    gid: Vec<String>,
+    #[serde(default)]
+    sid: Vec<String>,
+    #[serde(default)]
+    category: Vec<String>,
+    #[serde(default)]
+    severity: Vec<i64>,
+    #[serde(default)]
+    action: Vec<String>,
+    #[serde(default)]
+    interface: Vec<String>,
+    #[serde(default)]
+    protocol: Vec<String>,
+    #[serde(default)]
+    src: Vec<String>,
+    #[serde(default)]
+    dst: Vec<String>,
+}
+
+fn default_mode() -> FilterMode {
+    FilterMode::Include
+}
+
+struct FilterRule {
+    name: String,
+    mode: FilterMode,
+    gid: Vec<(i64, i64)>,
+    sid: Vec<(i64, i64)>,
+    category: Vec<String>,
+    severity: Vec<i64>,
+    action: Vec<String>,
+    interface: Vec<String>,
+    protocol: Vec<String>,
+    src: Vec<Cidr>,
+    dst: Vec<Cidr>,
+}
+
+/// Signature include/exclude filter applied to parsed alerts before they are
+/// converted and queued.
+pub struct SignatureFilter {
+    default: FilterMode,
+    rules: Vec<FilterRule>,
+}
+
+impl SignatureFilter {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let file: FilterFile = Config::builder()
+            .add_source(File::from(path))
+            .build()?
+            .try_deserialize()?;
+
+        let rules = file
+            .rules
+            .into_iter()
+            .map(FilterRule::compile)
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            default: file.default,
+            rules,
+        })
+    }
+}
+
+/// Drop and per-rule hit counters of a `SignatureFilter`.
+///
+/// Rule counters are keyed by rule name and kept apart from the filter so
+/// that they survive a reload.
+#[derive(Default)]
+pub struct FilterCounters {
+    rules: DashMap<String, (AtomicI64, AtomicI64)>,
+    total_dropped: AtomicI64,
+}
+
+impl FilterCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns false if the alert should be dropped. Non-alert events pass.
+    pub fn allows(&self, filter: &SignatureFilter, data: &SuricataAlert) -> bool {
+        if data.alert.is_none() {
+            return true;
+        }
+        let mode = match filter.rules.iter().find(|rule| rule.matches(data)) {
+            Some(rule) => {
+                let dropped = rule.mode == FilterMode::Exclude;
+                match self.rules.get(&rule.name) {
+                    Some(counters) => record(&counters, dropped),
+                    None => record(&self.rules.entry(rule.name.clone()).or_default(), dropped),
+                }
+                rule.mode
+            }
+            None => filter.default,
+        };
+
+        if mode == FilterMode::Exclude {
+            self.total_dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    pub fn get_total_dropped(&self) -> i64 {
+        self.total_dropped.load(Ordering::Relaxed)
+    }
+
+    /// (name, hits, drops) counters of the rules of the filter, in rule order.
+    pub fn get_rule_counters(&self, filter: &SignatureFilter) -> Vec<(String, i64, i64)> {
+        filter
+            .rules
+            .iter()
+            .map(|rule| match self.rules.get(&rule.name) {
+                Some(counters) => (
+                    rule.name.clone(),
+                    counters.0.load(Ordering::Relaxed),
+                    counters.1.load(Ordering::Relaxed),
+                ),
+                None => (rule.name.clone(), 0, 0),
+            })
+            .collect()
+    }
+}
+
+fn record((hits, drops): &(AtomicI64, AtomicI64), dropped: bool) {
+    hits.fetch_add(1, Ordering::Relaxed);
+    if dropped {
+        drops.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl FilterRule {
+    fn compile(config: FilterRuleConfig) -> Result<Self, String> {
+        let parse_cidrs = |values: &[String]| -> Result<Vec<Cidr>, String> {
+            values
+                .iter()
+                .map(|v| v.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("filter rule {:?}: {}", config.name, e))
+        };
+        let parse_ranges = |values: &[String]| -> Result<Vec<(i64, i64)>, String> {
+            values
+                .iter()
+                .map(|v| parse_range(v))
+                .collect::<Option<_>>()
+                .ok_or_else(|| format!("filter rule {:?}: invalid id range", config.name))
+        };
+
+        Ok(Self {
+            gid: parse_ranges(&config.gid)?,
+            sid: parse_ranges(&config.sid)?,
+            src: parse_cidrs(&config.src)?,
+            dst: parse_cidrs(&config.dst)?,
+            name: config.name,
+            mode: config.mode,
+            category: config.category,
+            severity: config.severity,
+            action: config.action,
+            interface: config.interface,
+            protocol: config.protocol,
+        })
+    }
+
+    fn matches(&self, data: &SuricataAlert) -> bool {
+        let alert = match data.alert.as_ref() {
+            Some(alert) => alert,
+            None => return false,
+        };
+
+        in_ranges(&self.gid, alert.gid)
+            && in_ranges(&self.sid, alert.signature_id)
+            && (self.severity.is_empty() || self.severity.contains(&alert.severity))
+            && matches_str(&self.category, Some(&alert.category))
+            && matches_str(&self.action, Some(&alert.action))
+            && matches_str(&self.interface, data.in_iface.as_ref())
+            && matches_str(&self.protocol, data.proto.as_ref())
+            && matches_cidrs(&self.src, data.src_ip.as_ref())
+            && matches_cidrs(&self.dst, data.dest_ip.as_ref())
+    }
+}
+
+/// Parses `"2100498"` or `"2000000-2099999"` into an inclusive range.
+fn parse_range(value: &str) -> Option<(i64, i64)> {
+    match value.split_once('-') {
+        Some((lo, hi)) => Some((lo.trim().parse().ok()?, hi.trim().parse().ok()?)),
+        None => {
+            let id = value.trim().parse().ok()?;
+            Some((id, id))
+        }
+    }
+}
+
+fn in_ranges(ranges: &[(i64, i64)], id: i64) -> bool {
+    ranges.is_empty() || ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&id))
+}
+
+fn matches_str(values: &[String], field: Option<&String>) -> bool {
+    values.is_empty() || field.is_some_and(|f| values.iter().any(|v| v.eq_ignore_ascii_case(f)))
+}
+
+fn matches_cidrs(cidrs: &[Cidr], ip: Option<&String>) -> bool {
+    if cidrs.is_empty() {
+        return true;
+    }
+    match ip.and_then(|ip| ip.parse::<IpAddr>().ok()) {
+        Some(ip) => cidrs.iter().any(|cidr| cidr.contains(&ip)),
+        None => false,
+    }
+}
@@ -0,0 +1,126 @@
+use crate::pb::{GeoLocation, Metric};
+use dashmap::DashMap;
+use log::debug;
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+// Upper bound on cached addresses; the cache is cleared when it is exceeded.
+const MAX_CACHED_ADDRESSES: usize = 100_000;
+
+/// Offline GeoIP/ASN lookups against local MaxMind `.mmdb` databases.
+///
+/// Results (including misses) are cached per address for the lifetime of the
+/// loaded databases; a reload starts with an empty cache.
+pub struct GeoIp {
+    city: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+    cache: DashMap<IpAddr, Option<GeoLocation>>,
+    // Metrics
+    total_lookups: AtomicI64,
+    total_cache_hits: AtomicI64,
+}
+
+impl GeoIp {
+    pub fn load(
+        city_path: Option<&Path>,
+        asn_path: Option<&Path>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self {
+            city: city_path.map(Reader::open_readfile).transpose()?,
+            asn: asn_path.map(Reader::open_readfile).transpose()?,
+            cache: DashMap::new(),
+            total_lookups: AtomicI64::new(0),
+            total_cache_hits: AtomicI64::new(0),
+        })
+    }
+
+    /// Sets the src/dst geolocation of the metric from its addresses.
+    pub fn enrich(&self, metric: &mut Metric) {
+        metric.event_src_geo = metric
+            .snort_src_address
+            .as_deref()
+            .and_then(|ip| self.lookup(ip));
+        metric.event_dst_geo = metric
+            .snort_dst_address
+            .as_deref()
+            .and_then(|ip| self.lookup(ip));
+    }
+
+    pub fn lookup(&self, ip: &str) -> Option<GeoLocation> {
+        let ip: IpAddr = ip.parse().ok()?;
+        self.total_lookups.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(cached) = self.cache.get(&ip) {
+            self.total_cache_hits.fetch_add(1, Ordering::Relaxed);
+            return cached.clone();
+        }
+
+        let location = self.lookup_uncached(ip);
+        if self.cache.len() >= MAX_CACHED_ADDRESSES {
+            self.cache.clear();
+        }
+        self.cache.insert(ip, location.clone());
+        location
+    }
+
+    fn lookup_uncached(&self, ip: IpAddr) -> Option<GeoLocation> {
+        let mut location = GeoLocation::default();
+        let mut found = false;
+
+        if let Some(city) = self
+            .city
+            .as_ref()
+            .and_then(|r| ignore_not_found(r.lookup::<geoip2::City>(ip)))
+        {
+            if let Some(country) = city.country {
+                location.country_code = country.iso_code.map(str::to_string);
+                location.country_name = english_name(country.names);
+            }
+            if let Some(c) = city.city {
+                location.city = english_name(c.names);
+            }
+            if let Some(loc) = city.location {
+                location.latitude = loc.latitude;
+                location.longitude = loc.longitude;
+            }
+            found = true;
+        }
+
+        if let Some(asn) = self
+            .asn
+            .as_ref()
+            .and_then(|r| ignore_not_found(r.lookup::<geoip2::Asn>(ip)))
+        {
+            location.asn = asn.autonomous_system_number.map(i64::from);
+            location.as_org = asn.autonomous_system_organization.map(str::to_string);
+            found = true;
+        }
+
+        found.then_some(location)
+    }
+
+    pub fn get_total_lookups(&self) -> i64 {
+        self.total_lookups.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_cache_hits(&self) -> i64 {
+        self.total_cache_hits.load(Ordering::Relaxed)
+    }
+}
+
+fn english_name(names: Option<std::collections::BTreeMap<&str, &str>>) -> Option<String> {
+    names.and_then(|n| n.get("en").map(|s| s.to_string()))
+}
+
+fn ignore_not_found<T>(result: Result<T, MaxMindDBError>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(MaxMindDBError::AddressNotFoundError(_)) => None,
+        Err(e) => {
+            debug!("GeoIP lookup failed: {}", e);
+            None
+        }
+    }
+}
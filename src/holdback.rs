@@ -0,0 +1,207 @@
+use crate::pb::{Metric, SensorEvent};
+use crate::types::{Flow, SuricataAlert};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct Held {
+    since: Instant,
+    events: Vec<(SuricataAlert, SensorEvent)>,
+}
+
+#[derive(Default)]
+struct Flows {
+    held: HashMap<i64, Held>,
+    // Flows in the order they were first held. Entries of flows released
+    // since are skipped when they come up.
+    order: VecDeque<(Instant, i64)>,
+    // Events of all held flows
+    events: usize,
+}
+
+impl Flows {
+    /// Removes the oldest held flow.
+    fn pop_oldest(&mut self) -> Option<Held> {
+        while let Some((since, flow_id)) = self.order.pop_front() {
+            if self
+                .held
+                .get(&flow_id)
+                .is_some_and(|held| held.since == since)
+            {
+                return self.remove(flow_id);
+            }
+        }
+        None
+    }
+
+    fn remove(&mut self, flow_id: i64) -> Option<Held> {
+        let held = self.held.remove(&flow_id)?;
+        self.events -= held.events.len();
+        Some(held)
+    }
+}
+
+/// Holds alert events back until Suricata logs the `flow` record of their
+/// flow, then merges the flow's final statistics into their metrics.
+///
+/// Events of a flow whose record did not arrive within `timeout` of its
+/// first held alert are released as they are, as are those of the oldest
+/// flows while more than `max_held` events are held (`0` is no limit).
+/// Alerts without a `flow_id` are never held.
+pub struct FlowHoldback {
+    timeout: Duration,
+    max_held: usize,
+    flows: Mutex<Flows>,
+    // Metrics
+    total_enriched: AtomicI64,
+    total_timed_out: AtomicI64,
+    total_released_early: AtomicI64,
+}
+
+impl FlowHoldback {
+    pub fn new(timeout: Duration, max_held: usize) -> Self {
+        Self {
+            timeout,
+            max_held,
+            flows: Mutex::new(Flows::default()),
+            total_enriched: AtomicI64::new(0),
+            total_timed_out: AtomicI64::new(0),
+            total_released_early: AtomicI64::new(0),
+        }
+    }
+
+    /// Holds the event of an alert. Returns it if it cannot be held.
+    pub fn hold(&self, data: &SuricataAlert, event: SensorEvent) -> Option<SensorEvent> {
+        let flow_id = match data.flow_id {
+            Some(flow_id) => flow_id,
+            None => return Some(event),
+        };
+        let mut flows = self.flows.lock().unwrap();
+        let Flows { held, order, .. } = &mut *flows;
+        held.entry(flow_id)
+            .or_insert_with(|| {
+                let since = Instant::now();
+                order.push_back((since, flow_id));
+                Held {
+                    since,
+                    events: Vec::new(),
+                }
+            })
+            .events
+            .push((data.clone(), event));
+        flows.events += 1;
+        None
+    }
+
+    /// Releases the events of the flow if `data` is its `flow` record, with
+    /// the record's statistics merged in, and then, unchanged, those of the
+    /// oldest flows while more than `max_held` events are held. Each event
+    /// comes with the alert it was made from.
+    pub fn release(&self, data: &SuricataAlert) -> Vec<(SuricataAlert, SensorEvent)> {
+        let mut flows = self.flows.lock().unwrap();
+        let mut events = match data.flow_id {
+            Some(flow_id) if data.event_type.as_deref() == Some("flow") => flows
+                .remove(flow_id)
+                .map(|held| held.events)
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        for (_, event) in &mut events {
+            for metric in &mut event.metrics {
+                merge_flow_stats(data, metric);
+            }
+        }
+        self.total_enriched
+            .fetch_add(events.len() as i64, Ordering::Relaxed);
+
+        let mut released_early = 0;
+        while self.max_held > 0 && flows.events > self.max_held {
+            match flows.pop_oldest() {
+                Some(held) => {
+                    released_early += held.events.len();
+                    events.extend(held.events);
+                }
+                None => break,
+            }
+        }
+        self.total_released_early
+            .fetch_add(released_early as i64, Ordering::Relaxed);
+        events
+    }
+
+    /// Releases, unchanged, the events of flows held longer than the timeout.
+    pub fn expire(&self) -> Vec<(SuricataAlert, SensorEvent)> {
+        let now = Instant::now();
+        let mut flows = self.flows.lock().unwrap();
+        let mut events = Vec::new();
+        while let Some(&(since, flow_id)) = flows.order.front() {
+            if now.duration_since(since) < self.timeout {
+                break;
+            }
+            flows.order.pop_front();
+            if flows
+                .held
+                .get(&flow_id)
+                .is_some_and(|held| held.since == since)
+            {
+                events.extend(
+                    flows
+                        .remove(flow_id)
+                        .map(|held| held.events)
+                        .unwrap_or_default(),
+                );
+            }
+        }
+        self.total_timed_out
+            .fetch_add(events.len() as i64, Ordering::Relaxed);
+        events
+    }
+
+    /// Number of events currently held.
+    pub fn get_held(&self) -> usize {
+        self.flows.lock().unwrap().events
+    }
+
+    pub fn get_total_enriched(&self) -> i64 {
+        self.total_enriched.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_timed_out(&self) -> i64 {
+        self.total_timed_out.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_released_early(&self) -> i64 {
+        self.total_released_early.load(Ordering::Relaxed)
+    }
+}
+
+/// Overwrites the metric's alert-time counters with the flow record's final
+/// totals and adds its end time, age, state, termination reason and TCP
+/// flags.
+fn merge_flow_stats(data: &SuricataAlert, metric: &mut Metric) {
+    if let Some(flow) = data.flow.as_ref() {
+        let Flow {
+            pkts_toserver,
+            pkts_toclient,
+            bytes_toserver,
+            bytes_toclient,
+            end,
+            age,
+            state,
+            reason,
+            ..
+        } = flow.clone();
+        metric.snort_client_pkts = pkts_toserver.or(metric.snort_client_pkts);
+        metric.snort_server_pkts = pkts_toclient.or(metric.snort_server_pkts);
+        metric.snort_client_bytes = bytes_toserver.or(metric.snort_client_bytes);
+        metric.snort_server_bytes = bytes_toclient.or(metric.snort_server_bytes);
+        metric.event_flow_end = end;
+        metric.event_flow_age = age;
+        metric.event_flow_state = state;
+        metric.event_flow_reason = reason;
+    }
+    if let Some(flags) = data.tcp.as_ref().and_then(|tcp| tcp.tcp_flags.clone()) {
+        metric.snort_tcp_flags = Some(flags);
+    }
+}
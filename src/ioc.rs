@@ -0,0 +1,271 @@
+use crate::cidr::{Cidr, CidrMap};
+use crate::pb::{IocMatch, Metric};
+use crate::types::SuricataAlert;
+use log::warn;
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+// Confidence for indicators whose feed does not specify one.
+const DEFAULT_CONFIDENCE: u8 = 50;
+
+/// Feed index and confidence of an indicator. When several feeds carry the
+/// same indicator the one with the highest confidence is kept.
+#[derive(Debug, Clone, Copy)]
+struct Hit {
+    feed: u16,
+    confidence: u8,
+}
+
+/// Local threat-intel indicator sets loaded from a directory of feeds.
+///
+/// Each file is one feed named after its file stem:
+/// - `*.txt`: one indicator per line,
+/// - `*.csv`: `indicator,confidence` rows (header optional),
+/// - `*.json`: STIX 2.1 bundles; `ipv4-addr`, `ipv6-addr`, `domain-name` and
+///   `file:hashes` comparisons in indicator patterns are used.
+///
+/// Indicator types in text and CSV feeds are detected from their syntax. Only
+/// 64-bit hashes of domains and file hashes are kept in memory, and exact
+/// addresses are kept apart from networks so that large host lists do not
+/// inflate the CIDR trie.
+#[derive(Default)]
+pub struct IocSet {
+    feeds: Vec<String>,
+    hosts: HashMap<IpAddr, Hit>,
+    networks: CidrMap<Hit>,
+    domains: HashMap<u64, Hit>,
+    hashes: HashMap<u64, Hit>,
+    // Metrics
+    total_matches: AtomicI64,
+}
+
+impl IocSet {
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut set = Self::default();
+        let mut files: Vec<_> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.is_file())
+            .collect();
+        files.sort();
+
+        for file in files {
+            let feed_name = file
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let feed = set.feeds.len() as u16;
+            let content = fs::read_to_string(&file)?;
+
+            match file.extension().and_then(|e| e.to_str()) {
+                Some("txt") => {
+                    for line in content.lines() {
+                        set.insert(line, feed, DEFAULT_CONFIDENCE);
+                    }
+                }
+                Some("csv") => {
+                    for line in content.lines() {
+                        let (indicator, confidence) = match line.split_once(',') {
+                            Some((i, c)) => (i, c.trim().parse().unwrap_or(DEFAULT_CONFIDENCE)),
+                            None => (line, DEFAULT_CONFIDENCE),
+                        };
+                        set.insert(indicator, feed, confidence.min(100));
+                    }
+                }
+                Some("json") => {
+                    let bundle: StixBundle = serde_json::from_str(&content)
+                        .map_err(|e| format!("{}: {}", file.display(), e))?;
+                    for indicator in bundle.objects.iter().filter(|o| o.kind == "indicator") {
+                        let confidence = indicator
+                            .confidence
+                            .map(|c| c.min(100) as u8)
+                            .unwrap_or(DEFAULT_CONFIDENCE);
+                        for value in indicator.pattern.iter().flat_map(|p| stix_values(p)) {
+                            set.insert(value, feed, confidence);
+                        }
+                    }
+                }
+                _ => {
+                    warn!("Ignoring IOC feed with unknown format: {}", file.display());
+                    continue;
+                }
+            }
+            set.feeds.push(feed_name);
+        }
+
+        Ok(set)
+    }
+
+    pub fn indicator_count(&self) -> usize {
+        self.hosts.len() + self.networks.len() + self.domains.len() + self.hashes.len()
+    }
+
+    fn insert(&mut self, indicator: &str, feed: u16, confidence: u8) {
+        let indicator = indicator.trim().trim_matches('"');
+        if indicator.is_empty() || indicator.starts_with('#') {
+            return;
+        }
+        let hit = Hit { feed, confidence };
+
+        if let Ok(ip) = indicator.parse::<IpAddr>() {
+            keep_best(self.hosts.entry(ip).or_insert(hit), hit);
+        } else if let Ok(cidr) = indicator.parse::<Cidr>() {
+            match self.networks.get_mut(&cidr) {
+                Some(existing) => keep_best(existing, hit),
+                None => self.networks.insert(cidr, hit),
+            }
+        } else if is_file_hash(indicator) {
+            keep_best(self.hashes.entry(hash_lower(indicator)).or_insert(hit), hit);
+        } else if indicator.contains('.') && !indicator.contains(['/', ' ', ':']) {
+            let domain = indicator.trim_end_matches('.');
+            keep_best(self.domains.entry(hash_lower(domain)).or_insert(hit), hit);
+        }
+    }
+
+    /// Matches alert addresses, HTTP hostname, TLS SNI, DNS query names and
+    /// file hashes against the indicator sets and records hits on the metric.
+    pub fn match_alert(&self, data: &SuricataAlert, metric: &mut Metric) {
+        let mut matches = Vec::new();
+
+        for (field, ip) in [("src_ip", &data.src_ip), ("dest_ip", &data.dest_ip)] {
+            if let Some(ip) = ip.as_deref() {
+                if let Some(hit) = self.match_ip(ip) {
+                    matches.push(self.ioc_match(hit, field, ip));
+                }
+            }
+        }
+
+        let hostname = data.http.as_ref().and_then(|h| h.hostname.as_deref());
+        let sni = data.tls.as_ref().and_then(|t| t.sni.as_deref());
+        let mut domains: Vec<(&str, &str)> = Vec::new();
+        domains.extend(hostname.map(|h| ("http_hostname", h)));
+        domains.extend(sni.map(|s| ("tls_sni", s)));
+        if let Some(dns) = data.dns.as_ref() {
+            domains.extend(dns.rrname.as_deref().map(|q| ("dns_query", q)));
+            for query in dns.query.iter().flatten() {
+                domains.extend(query.rrname.as_deref().map(|q| ("dns_query", q)));
+            }
+        }
+        for (field, name) in domains {
+            if let Some((hit, matched)) = self.match_domain(name) {
+                matches.push(self.ioc_match(hit, field, matched));
+            }
+        }
+
+        for file in data.files.iter().flatten() {
+            for hash in [&file.md5, &file.sha1, &file.sha256].into_iter().flatten() {
+                if let Some(hit) = self.hashes.get(&hash_lower(hash)) {
+                    matches.push(self.ioc_match(*hit, "file_hash", hash));
+                }
+            }
+        }
+
+        if !matches.is_empty() {
+            self.total_matches
+                .fetch_add(matches.len() as i64, Ordering::Relaxed);
+            metric.event_ioc_matches.extend(matches);
+        }
+    }
+
+    fn match_ip(&self, ip: &str) -> Option<Hit> {
+        let ip: IpAddr = ip.parse().ok()?;
+        self.hosts
+            .get(&ip)
+            .or_else(|| self.networks.longest_match(&ip))
+            .copied()
+    }
+
+    /// Matches `name` and each of its parent domains, most specific first.
+    fn match_domain<'a>(&self, name: &'a str) -> Option<(Hit, &'a str)> {
+        let mut candidate = name.trim_end_matches('.');
+        loop {
+            if let Some(hit) = self.domains.get(&hash_lower(candidate)) {
+                return Some((*hit, candidate));
+            }
+            match candidate.split_once('.') {
+                Some((_, parent)) if parent.contains('.') => candidate = parent,
+                _ => return None,
+            }
+        }
+    }
+
+    fn ioc_match(&self, hit: Hit, field: &str, indicator: &str) -> IocMatch {
+        IocMatch {
+            feed: self.feeds[hit.feed as usize].clone(),
+            indicator: indicator.to_string(),
+            field: field.to_string(),
+            confidence: hit.confidence as i32,
+        }
+    }
+
+    pub fn get_total_matches(&self) -> i64 {
+        self.total_matches.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Deserialize)]
+struct StixBundle {
+    #[serde(default)]
+    objects: Vec<StixObject>,
+}
+
+#[derive(Deserialize)]
+struct StixObject {
+    #[serde(rename = "type")]
+    kind: String,
+    pattern: Option<String>,
+    confidence: Option<u32>,
+}
+
+/// Extracts the quoted values of `ipv4-addr:value`, `ipv6-addr:value`,
+/// `domain-name:value` and `file:hashes.*` comparisons from a STIX pattern
+/// such as `[ipv4-addr:value = '198.51.100.1/32' OR domain-name:value = 'x.example']`.
+fn stix_values(pattern: &str) -> Vec<&str> {
+    let mut values = Vec::new();
+    let mut rest = pattern;
+    while let Some(eq) = rest.find('=') {
+        let path = rest[..eq]
+            .rsplit(['[', '(', ' '])
+            .find(|s| !s.is_empty())
+            .unwrap_or_default();
+        let after = rest[eq + 1..].trim_start();
+        let value = after
+            .strip_prefix('\'')
+            .and_then(|v| v.split_once('\''))
+            .map(|(value, _)| value);
+        if let Some(value) = value {
+            if matches!(
+                path,
+                "ipv4-addr:value" | "ipv6-addr:value" | "domain-name:value"
+            ) || path.starts_with("file:hashes")
+            {
+                values.push(value);
+            }
+        }
+        rest = &rest[eq + 1..];
+    }
+    values
+}
+
+fn keep_best(existing: &mut Hit, hit: Hit) {
+    if hit.confidence > existing.confidence {
+        *existing = hit;
+    }
+}
+
+fn is_file_hash(value: &str) -> bool {
+    matches!(value.len(), 32 | 40 | 64) && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn hash_lower(value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for b in value.bytes() {
+        b.to_ascii_lowercase().hash(&mut hasher);
+    }
+    hasher.finish()
+}
@@ -3,4 +3,28 @@
 // This project is primarily a binary (`main.rs`), but exposing core modules as a
 // library makes it easy to write integration tests (e.g. JSON parsing).
 
+pub mod anonymize;
+pub mod assets;
+pub mod attack;
+pub mod cidr;
+pub mod client;
+pub mod community_id;
+pub mod compression;
+pub mod correlate;
+pub mod filter;
+pub mod geoip;
+pub mod holdback;
+pub mod ioc;
+pub mod listener;
+pub mod pb;
+pub mod pdns;
+pub mod plugins;
+pub mod priority;
+pub mod privacy;
+pub mod processor;
+pub mod queue;
+pub mod rules;
+pub mod scripting;
+pub mod spool;
+pub mod threshold;
 pub mod types;
@@ -7,6 +7,11 @@ use std::path::Path;
 
 use std::sync::atomic::{AtomicI64, Ordering};
 
+/// Reads EVE lines from the Suricata socket and hands them to the workers.
+///
+/// Lines with a `flow_id` always go to the same worker for a flow, so that
+/// an alert is held back or correlated before the worker reads its flow's
+/// `flow` record. Other lines are spread round-robin.
 pub struct Listener {
     socket_path: String,
     // Metrics
@@ -62,7 +67,10 @@ impl Listener {
                     for line in lines_iter {
                         match line {
                             Ok(line_content) => {
-                                let idx = counter % num_workers;
+                                let idx = match flow_id(&line_content) {
+                                    Some(flow_id) => (flow_id % num_workers as u64) as usize,
+                                    None => counter % num_workers,
+                                };
                                 // Use send since we are using std::sync::mpsc
                                 if let Err(e) = txs[idx].send(line_content) {
                                     error!("Failed to send raw line to worker {}: {}", idx, e);
@@ -97,3 +105,15 @@ impl Listener {
         self.latest_read_per_sec.store(count, Ordering::Relaxed);
     }
 }
+
+/// The top-level `flow_id` of an EVE line, found without parsing the JSON.
+/// Suricata writes it before any nested object.
+pub fn flow_id(line: &str) -> Option<u64> {
+    const KEY: &str = "\"flow_id\":";
+    let start = line.find(KEY)? + KEY.len();
+    let digits = line[start..].trim_start();
+    let end = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    digits[..end].parse().ok()
+}
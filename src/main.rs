@@ -1,9 +1,30 @@
+mod anonymize;
+mod assets;
+mod attack;
+mod cidr;
 mod client;
+mod community_id;
+mod compression;
 mod config;
+mod correlate;
+mod filter;
+mod geoip;
+mod holdback;
+mod ioc;
 mod listener;
 mod pb;
+mod pdns;
+mod pipeline;
+mod plugins;
+mod priority;
+mod privacy;
 mod processor;
 mod queue;
+mod reload;
+mod rules;
+mod scripting;
+mod spool;
+mod threshold;
 mod types;
 
 use clap::Parser;
@@ -33,6 +54,36 @@ struct Args {
     #[arg(short = 'i', long)]
     interval: Option<u64>,
 
+    #[arg(long)]
+    batch_max_age: Option<u64>,
+
+    #[arg(long)]
+    batch_max_events: Option<usize>,
+
+    #[arg(long)]
+    batch_max_bytes: Option<usize>,
+
+    #[arg(long)]
+    queue_max_records: Option<usize>,
+
+    #[arg(long)]
+    queue_max_record_metrics: Option<usize>,
+
+    #[arg(long)]
+    queue_max_bytes: Option<usize>,
+
+    #[arg(long, value_enum)]
+    queue_overflow: Option<queue::OverflowPolicy>,
+
+    #[arg(long)]
+    spool_path: Option<String>,
+
+    #[arg(long)]
+    spool_max_bytes: Option<u64>,
+
+    #[arg(long)]
+    spool_max_age: Option<u64>,
+
     #[arg(long)]
     sensor_id: Option<String>,
 
@@ -45,15 +96,130 @@ struct Args {
     #[arg(short = 'k', long)]
     max_clients: Option<usize>,
 
-    #[arg(short = 'm', long)]
+    /// Deprecated and ignored; use --message-max-bytes
+    #[arg(short = 'm', long, hide = true)]
     max_message_size: Option<usize>,
 
+    #[arg(long)]
+    message_max_bytes: Option<usize>,
+
+    #[arg(long, value_enum)]
+    compression: Option<compression::Compression>,
+
     #[arg(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
+
+    #[arg(long)]
+    rules_path: Option<String>,
+
+    #[arg(long)]
+    reference_config: Option<String>,
+
+    #[arg(long)]
+    reload_interval: Option<u64>,
+
+    #[arg(long)]
+    target_roles: Option<bool>,
+
+    #[arg(long)]
+    filter_path: Option<String>,
+
+    #[arg(long)]
+    threshold_path: Option<String>,
+
+    #[arg(long)]
+    geoip_city_path: Option<String>,
+
+    #[arg(long)]
+    geoip_asn_path: Option<String>,
+
+    #[arg(long)]
+    home_net: Option<String>,
+
+    #[arg(long)]
+    asset_inventory_path: Option<String>,
+
+    #[arg(long)]
+    ioc_path: Option<String>,
+
+    #[arg(long)]
+    pdns_max_entries: Option<usize>,
+
+    #[arg(long)]
+    privacy_path: Option<String>,
+
+    #[arg(long)]
+    anonymize_key_path: Option<String>,
+
+    #[arg(long)]
+    anonymize_exempt: Option<String>,
+
+    #[arg(long)]
+    scripts_path: Option<String>,
+
+    #[arg(long)]
+    script_timeout_ms: Option<u64>,
+
+    #[arg(long)]
+    plugins_path: Option<String>,
+
+    #[arg(long)]
+    plugin_fuel: Option<u64>,
+
+    #[arg(long)]
+    plugin_max_output_bytes: Option<usize>,
+
+    #[arg(long)]
+    community_id_seed: Option<u16>,
+
+    #[arg(long)]
+    attack_table_path: Option<String>,
+
+    #[arg(long)]
+    attack_bundle_path: Option<String>,
+
+    #[arg(long, value_enum)]
+    correlation: Option<correlate::CorrelationMode>,
+
+    #[arg(long)]
+    correlation_window: Option<u64>,
+
+    #[arg(long)]
+    flow_holdback_timeout: Option<u64>,
+
+    #[arg(long)]
+    flow_holdback_max_held: Option<usize>,
+
+    #[arg(long)]
+    priority_max_severity: Option<i64>,
+
+    #[arg(long)]
+    priority_sids: Option<String>,
+
+    #[arg(long)]
+    priority_ioc: Option<bool>,
+
+    #[arg(long)]
+    priority_interval_ms: Option<u64>,
+}
+
+/// Queues an event on the priority lane if it matches, or on the regular queue.
+fn enqueue(
+    queue: &queue::EventBatchQueue,
+    lane: Option<&priority::PriorityLane>,
+    event: pb::SensorEvent,
+) {
+    let event = match lane {
+        Some(lane) => lane.add(event),
+        None => Some(event),
+    };
+    if let Some(event) = event {
+        queue.add(event);
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let args = Args::parse();
 
     // Load configuration
@@ -75,6 +241,36 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     if let Some(interval) = args.interval {
         conf.interval = interval;
     }
+    if let Some(batch_max_age) = args.batch_max_age {
+        conf.batch_max_age = batch_max_age;
+    }
+    if let Some(batch_max_events) = args.batch_max_events {
+        conf.batch_max_events = batch_max_events;
+    }
+    if let Some(batch_max_bytes) = args.batch_max_bytes {
+        conf.batch_max_bytes = batch_max_bytes;
+    }
+    if let Some(queue_max_records) = args.queue_max_records {
+        conf.queue_max_records = queue_max_records;
+    }
+    if let Some(queue_max_record_metrics) = args.queue_max_record_metrics {
+        conf.queue_max_record_metrics = queue_max_record_metrics;
+    }
+    if let Some(queue_max_bytes) = args.queue_max_bytes {
+        conf.queue_max_bytes = queue_max_bytes;
+    }
+    if let Some(queue_overflow) = args.queue_overflow {
+        conf.queue_overflow = queue_overflow;
+    }
+    if let Some(spool_path) = args.spool_path {
+        conf.spool_path = Some(spool_path);
+    }
+    if let Some(spool_max_bytes) = args.spool_max_bytes {
+        conf.spool_max_bytes = spool_max_bytes;
+    }
+    if let Some(spool_max_age) = args.spool_max_age {
+        conf.spool_max_age = spool_max_age;
+    }
     if let Some(sensor_id) = args.sensor_id {
         conf.sensor_id = sensor_id;
     }
@@ -87,12 +283,108 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     if let Some(max_clients) = args.max_clients {
         conf.max_clients = Some(max_clients);
     }
-    if let Some(max_message_size) = args.max_message_size {
-        conf.max_message_size = max_message_size;
+    if let Some(message_max_bytes) = args.message_max_bytes {
+        conf.message_max_bytes = message_max_bytes;
+    }
+    if let Some(compression) = args.compression {
+        conf.compression = compression;
     }
     if args.verbose > 0 {
         conf.verbose = args.verbose as usize;
     }
+    if let Some(rules_path) = args.rules_path {
+        conf.rules_path = Some(rules_path);
+    }
+    if let Some(reference_config) = args.reference_config {
+        conf.reference_config = Some(reference_config);
+    }
+    if let Some(reload_interval) = args.reload_interval {
+        conf.reload_interval = reload_interval;
+    }
+    if let Some(target_roles) = args.target_roles {
+        conf.target_roles = target_roles;
+    }
+    if let Some(filter_path) = args.filter_path {
+        conf.filter_path = Some(filter_path);
+    }
+    if let Some(threshold_path) = args.threshold_path {
+        conf.threshold_path = Some(threshold_path);
+    }
+    if let Some(geoip_city_path) = args.geoip_city_path {
+        conf.geoip_city_path = Some(geoip_city_path);
+    }
+    if let Some(geoip_asn_path) = args.geoip_asn_path {
+        conf.geoip_asn_path = Some(geoip_asn_path);
+    }
+    if let Some(home_net) = args.home_net {
+        conf.home_net = Some(home_net);
+    }
+    if let Some(asset_inventory_path) = args.asset_inventory_path {
+        conf.asset_inventory_path = Some(asset_inventory_path);
+    }
+    if let Some(ioc_path) = args.ioc_path {
+        conf.ioc_path = Some(ioc_path);
+    }
+    if let Some(pdns_max_entries) = args.pdns_max_entries {
+        conf.pdns_max_entries = pdns_max_entries;
+    }
+    if let Some(privacy_path) = args.privacy_path {
+        conf.privacy_path = Some(privacy_path);
+    }
+    if let Some(anonymize_key_path) = args.anonymize_key_path {
+        conf.anonymize_key_path = Some(anonymize_key_path);
+    }
+    if let Some(anonymize_exempt) = args.anonymize_exempt {
+        conf.anonymize_exempt = Some(anonymize_exempt);
+    }
+    if let Some(scripts_path) = args.scripts_path {
+        conf.scripts_path = Some(scripts_path);
+    }
+    if let Some(script_timeout_ms) = args.script_timeout_ms {
+        conf.script_timeout_ms = script_timeout_ms;
+    }
+    if let Some(plugins_path) = args.plugins_path {
+        conf.plugins_path = Some(plugins_path);
+    }
+    if let Some(plugin_fuel) = args.plugin_fuel {
+        conf.plugin_fuel = plugin_fuel;
+    }
+    if let Some(plugin_max_output_bytes) = args.plugin_max_output_bytes {
+        conf.plugin_max_output_bytes = plugin_max_output_bytes;
+    }
+    if let Some(community_id_seed) = args.community_id_seed {
+        conf.community_id_seed = community_id_seed;
+    }
+    if let Some(attack_table_path) = args.attack_table_path {
+        conf.attack_table_path = Some(attack_table_path);
+    }
+    if let Some(attack_bundle_path) = args.attack_bundle_path {
+        conf.attack_bundle_path = Some(attack_bundle_path);
+    }
+    if let Some(correlation) = args.correlation {
+        conf.correlation = correlation;
+    }
+    if let Some(correlation_window) = args.correlation_window {
+        conf.correlation_window = correlation_window;
+    }
+    if let Some(flow_holdback_timeout) = args.flow_holdback_timeout {
+        conf.flow_holdback_timeout = flow_holdback_timeout;
+    }
+    if let Some(flow_holdback_max_held) = args.flow_holdback_max_held {
+        conf.flow_holdback_max_held = flow_holdback_max_held;
+    }
+    if let Some(priority_max_severity) = args.priority_max_severity {
+        conf.priority_max_severity = priority_max_severity;
+    }
+    if let Some(priority_sids) = args.priority_sids {
+        conf.priority_sids = Some(priority_sids);
+    }
+    if let Some(priority_ioc) = args.priority_ioc {
+        conf.priority_ioc = priority_ioc;
+    }
+    if let Some(priority_interval_ms) = args.priority_interval_ms {
+        conf.priority_interval_ms = priority_interval_ms;
+    }
 
     // Initialize logger
     let log_level = match conf.verbose {
@@ -106,6 +398,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
     info!("Starting client with configuration: {:?}", conf);
+    if args.max_message_size.is_some() {
+        warn!("--max-message-size is deprecated and ignored; use --message-max-bytes");
+    }
 
     // Determine number of workers
     let num_workers = if let Some(max_clients) = conf.max_clients {
@@ -132,25 +427,126 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         worker_rxs.push(rx);
     }
 
-    // Channel for batches of events
+    // Channels for batches of events; the client takes express batches first
     let (batch_tx, batch_rx) = mpsc::channel(100);
-    let batch_rx = std::sync::Arc::new(tokio::sync::Mutex::new(batch_rx));
+    let (express_tx, express_rx) = mpsc::channel(100);
+    let batch_rx = std::sync::Arc::new(tokio::sync::Mutex::new(client::BatchChannels::new(
+        express_rx, batch_rx,
+    )));
 
     // Initialize EventBatchQueue on stack
-    let queue = queue::EventBatchQueue::new(0); // 0 second delta for immediate processing
+    let limits = queue::MemoryLimits {
+        max_records: conf.queue_max_records,
+        max_record_metrics: conf.queue_max_record_metrics,
+        max_bytes: conf.queue_max_bytes,
+        overflow: conf.queue_overflow,
+    };
+    let queue = queue::EventBatchQueue::new(
+        queue::FlushPolicy {
+            interval: std::time::Duration::from_secs(conf.interval),
+            max_age: std::time::Duration::from_secs(conf.batch_max_age),
+            max_events: conf.batch_max_events,
+            max_bytes: conf.batch_max_bytes,
+        },
+        limits,
+        num_workers * 4, // Shards, so that workers rarely share a lock
+    );
+
+    // Optional express lane for high-priority events
+    let priority_rule = priority::PriorityRule::new(
+        conf.priority_max_severity,
+        conf.priority_sids.as_deref().unwrap_or_default(),
+        conf.priority_ioc,
+    )?;
+    let lane = priority_rule.is_enabled().then(|| {
+        let interval = std::time::Duration::from_millis(conf.priority_interval_ms);
+        let policy = queue::FlushPolicy {
+            interval,
+            max_age: interval,
+            ..Default::default()
+        };
+        priority::PriorityLane::new(
+            priority_rule,
+            queue::EventBatchQueue::new(policy, limits, num_workers),
+        )
+    });
 
     // Initialize Listener on stack
     let listener = listener::Listener::new(&conf.file);
 
+    // Optional filtering and enrichment stages
+    let pipeline = pipeline::Pipeline::new(&conf)?;
+
+    // Optional grouping of alerts into multi-metric events
+    let correlator = correlate::Correlator::new(
+        conf.correlation,
+        std::time::Duration::from_secs(conf.correlation_window),
+        num_workers,
+    );
+
+    // Optional hold-back of alerts until their flow's final statistics arrive
+    let holdback = (conf.flow_holdback_timeout > 0).then(|| {
+        holdback::FlowHoldback::new(
+            std::time::Duration::from_secs(conf.flow_holdback_timeout),
+            conf.flow_holdback_max_held,
+        )
+    });
+
+    // Optional disk spool for batches the server cannot take
+    let spool = match &conf.spool_path {
+        Some(path) => {
+            let spool = spool::Spool::open(
+                std::path::Path::new(path),
+                conf.spool_max_bytes,
+                std::time::Duration::from_secs(conf.spool_max_age),
+            )?;
+            info!(
+                "Spool at {}: {} batches pending",
+                path,
+                spool.get_spooled_batches()
+            );
+            Some(spool)
+        }
+        None => None,
+    };
+    // Express batches get a spool of their own, drained first
+    let express_spool = match (&conf.spool_path, &lane) {
+        (Some(path), Some(_)) => Some(spool::Spool::open(
+            &std::path::Path::new(path).join("express"),
+            conf.spool_max_bytes,
+            std::time::Duration::from_secs(conf.spool_max_age),
+        )?),
+        _ => None,
+    };
+    // Whether the gRPC stream is up, so that batches are spooled meanwhile
+    let connected = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    // Batches awaiting a server acknowledgement, kept across reconnects
+    let outbox = std::sync::Arc::new(client::Outbox::new(&conf.sensor_id, conf.queue_max_bytes));
+    info!("Numbering batches in stream {}", outbox.stream_id());
+    // Request compression, renegotiated if the server rejects it
+    let compressor = std::sync::Arc::new(compression::Compressor::new(conf.compression));
+
     // Use scoped threads to share stack-allocated queue and listener
     let server = conf.server.clone();
     let port = conf.port;
     let insecure = conf.insecure;
+    let message_max_bytes = conf.message_max_bytes;
     let batch_rx_clone = batch_rx.clone();
+    let connected_clone = connected.clone();
+    let outbox_clone = outbox.clone();
+    let compressor_clone = compressor.clone();
     tokio::spawn(async move {
         loop {
             let mut client = loop {
-                match client::Client::new(&server, port, insecure).await {
+                match client::Client::new(
+                    &server,
+                    port,
+                    insecure,
+                    message_max_bytes,
+                    compressor_clone.clone(),
+                )
+                .await
+                {
                     Ok(c) => break c,
                     Err(e) => {
                         error!(
@@ -161,7 +557,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
             };
-            if let Err(e) = client.stream_data(batch_rx_clone.clone()).await {
+            connected_clone.store(true, std::sync::atomic::Ordering::Relaxed);
+            let result = client
+                .stream_data(batch_rx_clone.clone(), outbox_clone.clone())
+                .await;
+            connected_clone.store(false, std::sync::atomic::Ordering::Relaxed);
+            if let Err(e) = result {
                 error!("gRPC streaming error: {}. Reconnecting...", e);
                 tokio::time::sleep(std::time::Duration::from_secs(1)).await;
             } else {
@@ -177,6 +578,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         for i in 0..num_workers {
             let worker_rx = worker_rxs.remove(0); // Take ownership of one receiver
             let queue_ref = &queue;
+            let lane_ref = lane.as_ref();
+            let pipeline_ref = &pipeline;
+            let correlator_ref = &correlator;
+            let holdback_ref = holdback.as_ref();
             let sensor_id = conf.sensor_id.clone(); // Clone sensor_id for each worker
             let sensor_version = conf.sensor_version.clone(); // Clone version for each worker
 
@@ -215,11 +620,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             // Always override/ensure the configured sensor_version.
                             alert.metadata.sensor_version = sensor_version.clone();
 
-                            if let Some((mut event, metric)) =
-                                processor::convert_suricata_alert_to_sensor_event(&alert)
+                            if let Some(event) = pipeline_ref.process(&mut alert) {
+                                // Priority events skip the holdback and
+                                // correlation delays.
+                                let event = match lane_ref {
+                                    Some(lane) => lane.add(event),
+                                    None => Some(event),
+                                };
+                                let event = match (event, holdback_ref) {
+                                    (Some(event), Some(holdback)) => holdback.hold(&alert, event),
+                                    (event, _) => event,
+                                };
+                                if let Some(event) = event {
+                                    if let Some(event) = correlator_ref.add(&alert, event) {
+                                        queue_ref.add(event);
+                                    }
+                                }
+                            }
+                            // Held events of an ending flow, and of the oldest
+                            // flows past the cap, are released before its
+                            // correlation sessions are closed.
+                            for (data, event) in
+                                holdback_ref.map(|h| h.release(&alert)).unwrap_or_default()
                             {
-                                event.metrics.push(metric);
-                                queue_ref.add(event);
+                                if let Some(event) = correlator_ref.add(&data, event) {
+                                    enqueue(queue_ref, lane_ref, event);
+                                }
+                            }
+                            for event in correlator_ref.end_flow(&alert) {
+                                enqueue(queue_ref, lane_ref, event);
                             }
                         }
                         Err(e) => {
@@ -245,33 +674,192 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         // Spawn Watcher
         let queue_ref = &queue;
+        let lane_ref = lane.as_ref();
+        let correlator_ref = &correlator;
+        let holdback_ref = holdback.as_ref();
+        let spool_ref = spool.as_ref();
+        let connected_ref = &connected;
         let batch_tx_clone = batch_tx.clone(); // Clone for the watcher thread
         s.spawn(move || {
+            let mut last_expire = std::time::Instant::now();
             loop {
                 // Poll frequently for high throughput
                 std::thread::sleep(std::time::Duration::from_millis(10));
+                // Held flows and idle correlation sessions are checked once a
+                // second
+                if last_expire.elapsed() >= std::time::Duration::from_secs(1) {
+                    last_expire = std::time::Instant::now();
+                    for (data, event) in holdback_ref.map(|h| h.expire()).unwrap_or_default() {
+                        if let Some(event) = correlator_ref.add(&data, event) {
+                            enqueue(queue_ref, lane_ref, event);
+                        }
+                    }
+                    for event in correlator_ref.expire() {
+                        enqueue(queue_ref, lane_ref, event);
+                    }
+                }
                 let batch = queue_ref.process_batch();
-                if !batch.is_empty() {
-                    if let Err(e) = batch_tx_clone.blocking_send(batch) {
-                        error!("Failed to send batch to gRPC client: {}", e);
-                        break; // Exit loop if send fails (likely client disconnected)
+                if batch.is_empty() {
+                    continue;
+                }
+                let spool = match spool_ref {
+                    Some(spool) => spool,
+                    None => {
+                        if let Err(e) = batch_tx_clone.blocking_send(batch.into()) {
+                            error!("Failed to send batch to gRPC client: {}", e);
+                            break; // Exit loop if send fails (likely client disconnected)
+                        }
+                        continue;
+                    }
+                };
+                // Batches go to the spool while the server is unreachable or
+                // older batches are still spooled, so they stay in order.
+                let batch = if connected_ref.load(std::sync::atomic::Ordering::Relaxed)
+                    && spool.is_idle()
+                {
+                    match batch_tx_clone.try_send(batch.into()) {
+                        Ok(()) => continue,
+                        Err(mpsc::error::TrySendError::Full(batch)) => batch.events,
+                        Err(mpsc::error::TrySendError::Closed(_)) => {
+                            error!("Failed to send batch to gRPC client: channel closed");
+                            break;
+                        }
                     }
+                } else {
+                    batch
+                };
+                if let Err(e) = spool.push(&batch) {
+                    error!("Failed to spool batch of {} events: {}", batch.len(), e);
                 }
             }
         });
 
+        // Spawn Express Watcher
+        if let Some(lane) = lane.as_ref() {
+            let spool_ref = express_spool.as_ref();
+            let express_tx_clone = express_tx.clone();
+            s.spawn(move || loop {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                let batch = lane.queue().process_batch();
+                if batch.is_empty() {
+                    continue;
+                }
+                // Express batches wait in their own channel while the server
+                // is unreachable, so they go out first once it is back. Only
+                // when that fills up do they go to the express spool, and
+                // after it until it is drained, so they stay in order.
+                let batch = if spool_ref.is_none_or(|spool| spool.is_idle()) {
+                    match express_tx_clone.try_send(batch.into()) {
+                        Ok(()) => continue,
+                        Err(mpsc::error::TrySendError::Full(batch)) => batch.events,
+                        Err(mpsc::error::TrySendError::Closed(_)) => {
+                            error!("Failed to send express batch to gRPC client: channel closed");
+                            break;
+                        }
+                    }
+                } else {
+                    batch
+                };
+                match spool_ref {
+                    Some(spool) => {
+                        if let Err(e) = spool.push(&batch) {
+                            error!("Failed to spool batch of {} events: {}", batch.len(), e);
+                        }
+                    }
+                    None => {
+                        if let Err(e) = express_tx_clone.blocking_send(batch.into()) {
+                            error!("Failed to send express batch to gRPC client: {}", e);
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+
+        // Spawn Spool Drainer
+        if let Some(regular_spool) = spool.as_ref() {
+            let express_spool_ref = express_spool.as_ref();
+            let connected_ref = &connected;
+            let batch_tx_clone = batch_tx.clone();
+            let express_tx_clone = express_tx.clone();
+            // Receipts of the batches sent, oldest first. A spool only moves
+            // its read position past a batch once it was delivered.
+            let mut receipts: std::collections::VecDeque<(&spool::Spool, client::Receipt)> =
+                std::collections::VecDeque::new();
+            s.spawn(move || loop {
+                while receipts.front().is_some_and(|(_, r)| r.is_delivered()) {
+                    let (spool, _) = receipts.pop_front().unwrap();
+                    if let Err(e) = spool.delivered() {
+                        error!("Failed to update spool read position: {}", e);
+                    }
+                }
+                if !connected_ref.load(std::sync::atomic::Ordering::Relaxed) {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                    continue;
+                }
+                // Spooled express batches go first, onto the express channel
+                let (spool, tx) = match express_spool_ref {
+                    Some(express) if express.get_spooled_batches() > 0 => {
+                        (express, &express_tx_clone)
+                    }
+                    _ => (regular_spool, &batch_tx_clone),
+                };
+                match spool.pop() {
+                    Ok(Some(events)) => {
+                        let receipt = client::Receipt::new();
+                        let batch = client::Batch {
+                            events,
+                            receipt: Some(receipt.clone()),
+                        };
+                        if tx.blocking_send(batch).is_err() {
+                            error!("Failed to send spooled batch to gRPC client: channel closed");
+                            break;
+                        }
+                        receipts.push_back((spool, receipt));
+                    }
+                    Ok(None) => std::thread::sleep(std::time::Duration::from_millis(100)),
+                    Err(e) => {
+                        error!("Failed to read spool: {}", e);
+                        std::thread::sleep(std::time::Duration::from_secs(1));
+                    }
+                }
+            });
+        }
+
+        // Spawn Reloader
+        let pipeline_ref = &pipeline;
+        if pipeline_ref.needs_reload() {
+            let reload_interval = std::time::Duration::from_secs(conf.reload_interval.max(1));
+            s.spawn(move || loop {
+                std::thread::sleep(reload_interval);
+                pipeline_ref.reload();
+            });
+        }
+
         // Spawn Metrics Updater
         let queue_ref = &queue;
+        let lane_ref = lane.as_ref();
         let listener_ref = &listener;
         s.spawn(move || loop {
             std::thread::sleep(std::time::Duration::from_secs(1));
             queue_ref.update_metrics();
+            if let Some(lane) = lane_ref {
+                lane.queue().update_metrics();
+            }
             listener_ref.update_metrics();
         });
 
         // Spawn Metrics Logger
         let queue_ref = &queue;
         let listener_ref = &listener;
+        let pipeline_ref = &pipeline;
+        let correlator_ref = &correlator;
+        let holdback_ref = holdback.as_ref();
+        let spool_ref = spool.as_ref();
+        let express_spool_ref = express_spool.as_ref();
+        let outbox_ref = &outbox;
+        let compressor_ref = &compressor;
+        let lane_ref = lane.as_ref();
         s.spawn(move || {
             loop {
                 std::thread::sleep(std::time::Duration::from_secs(5));
@@ -284,6 +872,78 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     queue_ref.get_total_sent_events(),
                     queue_ref.get_queue_size()
                 );
+                if queue_ref.get_total_evicted() > 0 || queue_ref.get_total_summarized() > 0 || queue_ref.get_total_dropped_metrics() > 0 {
+                    warn!(
+                        "Queue overload metrics: evicted={} summarized={} dropped_metrics={}",
+                        queue_ref.get_total_evicted(),
+                        queue_ref.get_total_summarized(),
+                        queue_ref.get_total_dropped_metrics()
+                    );
+                }
+                if let Some(lane) = lane_ref {
+                    info!(
+                        "Priority lane metrics: routed={} sent={} queue_size={}",
+                        lane.get_total_routed(),
+                        lane.queue().get_total_sent_events(),
+                        lane.queue().get_queue_size()
+                    );
+                }
+                if let Some(spool) = spool_ref {
+                    info!(
+                        "Spool metrics: pending_batches={} bytes={} spooled={} drained={} dropped={} corrupt={}",
+                        spool.get_spooled_batches(),
+                        spool.get_spooled_bytes(),
+                        spool.get_total_spooled(),
+                        spool.get_total_drained(),
+                        spool.get_total_dropped(),
+                        spool.get_total_corrupt()
+                    );
+                }
+                if let Some(spool) = express_spool_ref {
+                    info!(
+                        "Express spool metrics: pending_batches={} bytes={} spooled={} drained={} dropped={} corrupt={}",
+                        spool.get_spooled_batches(),
+                        spool.get_spooled_bytes(),
+                        spool.get_total_spooled(),
+                        spool.get_total_drained(),
+                        spool.get_total_dropped(),
+                        spool.get_total_corrupt()
+                    );
+                }
+                info!(
+                    "Delivery metrics: unacked_batches={} unacked_bytes={} acked={} resent={}",
+                    outbox_ref.get_unacked_batches(),
+                    outbox_ref.get_unacked_bytes(),
+                    outbox_ref.get_total_acked(),
+                    outbox_ref.get_total_resent()
+                );
+                if compressor_ref.current() != compression::Compression::None {
+                    info!(
+                        "Compression metrics: encoding={} uncompressed_bytes={} compressed_bytes={}",
+                        compressor_ref.current().name(),
+                        compressor_ref.get_total_uncompressed(),
+                        compressor_ref.get_total_compressed()
+                    );
+                }
+                pipeline_ref.log_metrics();
+                if correlator_ref.mode() != correlate::CorrelationMode::None {
+                    info!(
+                        "Correlation metrics: open_sessions={} correlated={} closed_by_flow_end={} expired={}",
+                        correlator_ref.get_open_sessions(),
+                        correlator_ref.get_total_correlated(),
+                        correlator_ref.get_total_flow_closed(),
+                        correlator_ref.get_total_expired()
+                    );
+                }
+                if let Some(holdback) = holdback_ref {
+                    info!(
+                        "Flow hold-back metrics: held={} enriched={} timed_out={} released_early={}",
+                        holdback.get_held(),
+                        holdback.get_total_enriched(),
+                        holdback.get_total_timed_out(),
+                        holdback.get_total_released_early()
+                    );
+                }
             }
         });
 
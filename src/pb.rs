@@ -1 +1,3 @@
+// Generated code; not every message (e.g. `AlertSummary`) is used by the client.
+#![allow(dead_code)]
 tonic::include_proto!("pb");
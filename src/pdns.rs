@@ -0,0 +1,205 @@
+use crate::pb::Metric;
+use crate::types::SuricataAlert;
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Answers are kept at least this long, so alerts that arrive shortly after a
+// short-TTL answer expired can still be annotated.
+const MIN_TTL: Duration = Duration::from_secs(300);
+// Most names remembered per address; the soonest to expire is replaced.
+const MAX_NAMES_PER_ADDRESS: usize = 4;
+// Share of the cache evicted at once when it is full and nothing has expired.
+const EVICT_DIVISOR: usize = 8;
+
+/// Bounded, TTL-aware passive DNS cache built from Suricata `dns` answer
+/// records, used to annotate alert addresses with the names they were
+/// recently resolved from.
+pub struct PassiveDns {
+    max_entries: usize,
+    cache: DashMap<IpAddr, Vec<(String, Instant)>>,
+    // Tracked separately: DashMap::len locks every shard, so it can't be
+    // called while holding an entry.
+    size: AtomicUsize,
+    // Metrics
+    total_answers: AtomicI64,
+    total_evicted: AtomicI64,
+}
+
+impl PassiveDns {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries,
+            cache: DashMap::new(),
+            size: AtomicUsize::new(0),
+            total_answers: AtomicI64::new(0),
+            total_evicted: AtomicI64::new(0),
+        }
+    }
+
+    /// Records the A/AAAA answers of a `dns` event. Addresses are mapped to
+    /// the queried name rather than to intermediate CNAME targets.
+    pub fn observe(&self, data: &SuricataAlert) {
+        let dns = match data.dns.as_ref() {
+            Some(dns) if data.event_type.as_deref() == Some("dns") => dns,
+            _ => return,
+        };
+        if dns.kind.as_deref().is_some_and(|k| k != "answer") {
+            return;
+        }
+
+        let query = dns
+            .rrname
+            .as_deref()
+            .or_else(|| dns.query.iter().flatten().find_map(|q| q.rrname.as_deref()));
+        let top_level = (
+            dns.rrname.as_deref(),
+            dns.rrtype.as_deref(),
+            dns.rdata.as_deref(),
+            dns.ttl,
+        );
+        let answers = dns.answers.iter().flatten().map(|a| {
+            (
+                a.rrname.as_deref(),
+                a.rrtype.as_deref(),
+                a.rdata.as_deref(),
+                a.ttl,
+            )
+        });
+
+        let now = Instant::now();
+        for (rrname, rrtype, rdata, ttl) in std::iter::once(top_level).chain(answers) {
+            if !matches!(rrtype, Some("A") | Some("AAAA")) {
+                continue;
+            }
+            let (Some(name), Some(ip)) = (query.or(rrname), rdata.and_then(|r| r.parse().ok()))
+            else {
+                continue;
+            };
+            let ttl = Duration::from_secs(ttl.unwrap_or(0).max(0) as u64).max(MIN_TTL);
+            self.insert(ip, name, now + ttl);
+        }
+    }
+
+    fn insert(&self, ip: IpAddr, name: &str, expires_at: Instant) {
+        self.total_answers.fetch_add(1, Ordering::Relaxed);
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        loop {
+            match self.cache.entry(ip) {
+                Entry::Occupied(mut entry) => {
+                    let names = entry.get_mut();
+                    if let Some(entry) = names.iter_mut().find(|(n, _)| *n == name) {
+                        entry.1 = entry.1.max(expires_at);
+                    } else if names.len() < MAX_NAMES_PER_ADDRESS {
+                        names.push((name, expires_at));
+                    } else if let Some(oldest) =
+                        names.iter_mut().min_by_key(|(_, expires)| *expires)
+                    {
+                        *oldest = (name, expires_at);
+                    }
+                    return;
+                }
+                Entry::Vacant(entry) => {
+                    if self.size.fetch_add(1, Ordering::Relaxed) < self.max_entries {
+                        entry.insert(vec![(name, expires_at)]);
+                        return;
+                    }
+                    self.size.fetch_sub(1, Ordering::Relaxed);
+                }
+            }
+            // The entry is released here, as making room locks every shard.
+            self.make_room(Instant::now());
+        }
+    }
+
+    /// Removes expired addresses and, if the cache is still full, the ones
+    /// whose answers expire soonest.
+    fn make_room(&self, now: Instant) {
+        self.expire_at(now);
+        if self.size.load(Ordering::Relaxed) < self.max_entries {
+            return;
+        }
+
+        let mut expiries: Vec<Instant> = self
+            .cache
+            .iter()
+            .filter_map(|entry| latest_expiry(entry.value()))
+            .collect();
+        if expiries.is_empty() {
+            return;
+        }
+        let count = (self.max_entries / EVICT_DIVISOR).clamp(1, expiries.len());
+        let (_, cutoff, _) = expiries.select_nth_unstable(count - 1);
+        let cutoff = *cutoff;
+
+        let mut evicted = 0;
+        self.cache.retain(|_, names| {
+            let keep = evicted >= count || latest_expiry(names) > Some(cutoff);
+            if !keep {
+                evicted += 1;
+            }
+            keep
+        });
+        self.size.fetch_sub(evicted, Ordering::Relaxed);
+        self.total_evicted
+            .fetch_add(evicted as i64, Ordering::Relaxed);
+    }
+
+    /// Sets the src/dst hostnames of the metric from unexpired answers.
+    pub fn annotate(&self, metric: &mut Metric) {
+        let now = Instant::now();
+        metric.event_src_hostnames = self.lookup(metric.snort_src_address.as_deref(), now);
+        metric.event_dst_hostnames = self.lookup(metric.snort_dst_address.as_deref(), now);
+    }
+
+    fn lookup(&self, ip: Option<&str>, now: Instant) -> Vec<String> {
+        let ip: Option<IpAddr> = ip.and_then(|ip| ip.parse().ok());
+        match ip.and_then(|ip| self.cache.get(&ip)) {
+            Some(names) => names
+                .iter()
+                .filter(|(_, expires)| *expires > now)
+                .map(|(name, _)| name.clone())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Removes expired answers and addresses left without any.
+    pub fn expire(&self) {
+        self.expire_at(Instant::now());
+    }
+
+    fn expire_at(&self, now: Instant) {
+        let mut removed = 0;
+        self.cache.retain(|_, names| {
+            names.retain(|(_, expires)| *expires > now);
+            if names.is_empty() {
+                removed += 1;
+            }
+            !names.is_empty()
+        });
+        self.size.fetch_sub(removed, Ordering::Relaxed);
+    }
+
+    pub fn get_cache_size(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn get_total_answers(&self) -> i64 {
+        self.total_answers.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_evicted(&self) -> i64 {
+        self.total_evicted.load(Ordering::Relaxed)
+    }
+}
+
+fn latest_expiry(names: &[(String, Instant)]) -> Option<Instant> {
+    names.iter().map(|(_, expires)| *expires).max()
+}
@@ -0,0 +1,536 @@
+use crate::anonymize::Anonymizer;
+use crate::assets::AssetTagger;
+use crate::attack::AttackMapper;
+use crate::community_id::community_id;
+use crate::config::ClientConfig;
+use crate::filter::{FilterCounters, SignatureFilter};
+use crate::geoip::GeoIp;
+use crate::ioc::IocSet;
+use crate::pb::SensorEvent;
+use crate::pdns::PassiveDns;
+use crate::plugins::{PluginCounters, PluginSet};
+use crate::privacy::{PrivacyCounters, PrivacyPolicy};
+use crate::processor;
+use crate::reload::{LoadResult, Reloadable};
+use crate::rules::RuleSet;
+use crate::scripting::{ScriptCounters, ScriptSet};
+use crate::threshold::{RateLimiter, ThresholdRules};
+use crate::types::SuricataAlert;
+use log::info;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// The optional filtering and enrichment stages a parsed EVE record goes
+/// through before it is queued. Stages are enabled by configuration; file
+/// backed stages are reloaded by `reload()`.
+pub struct Pipeline {
+    target_roles: bool,
+    community_id_seed: u16,
+    rules: Option<Reloadable<RuleSet>>,
+    attack: Reloadable<AttackMapper>,
+    filter: Option<Reloadable<SignatureFilter>>,
+    // Outlive reloads of the filter
+    filter_counters: FilterCounters,
+    thresholds: Option<Reloadable<ThresholdRules>>,
+    // Outlives reloads of the thresholds
+    limiter: RateLimiter,
+    geoip: Option<Reloadable<GeoIp>>,
+    assets: Option<Reloadable<AssetTagger>>,
+    iocs: Option<Reloadable<IocSet>>,
+    pdns: Option<PassiveDns>,
+    privacy: Option<Reloadable<PrivacyPolicy>>,
+    // Outlive reloads of the privacy policy
+    privacy_counters: PrivacyCounters,
+    scripts: Option<Reloadable<ScriptSet>>,
+    // Outlive reloads of the scripts
+    script_counters: ScriptCounters,
+    plugins: Option<Reloadable<PluginSet>>,
+    // Outlive reloads of the plugins
+    plugin_counters: PluginCounters,
+    anonymizer: Option<Reloadable<Anonymizer>>,
+}
+
+impl Pipeline {
+    pub fn new(conf: &ClientConfig) -> LoadResult<Self> {
+        // Optional rule enrichment from the local ruleset
+        let rules = match &conf.rules_path {
+            Some(rules_path) => {
+                let rules_path = PathBuf::from(rules_path);
+                let reference_config = conf.reference_config.as_ref().map(PathBuf::from);
+                let mut watched = vec![rules_path.clone()];
+                watched.extend(reference_config.clone());
+                let rules = Reloadable::load("ruleset", watched, move || {
+                    RuleSet::load(&rules_path, reference_config.as_deref())
+                })?;
+                let ruleset = rules.get();
+                info!(
+                    "Loaded {} rules (version {})",
+                    ruleset.rule_count(),
+                    ruleset.version()
+                );
+                Some(rules)
+            }
+            None => None,
+        };
+
+        // ATT&CK mapping; alert and rule metadata are used even without a
+        // table or bundle
+        let table = conf.attack_table_path.as_ref().map(PathBuf::from);
+        let bundle = conf.attack_bundle_path.as_ref().map(PathBuf::from);
+        let watched = table.iter().chain(bundle.iter()).cloned().collect();
+        let attack = Reloadable::load("ATT&CK mapping", watched, move || {
+            AttackMapper::load(table.as_deref(), bundle.as_deref())
+        })?;
+
+        // Optional signature include/exclude filter
+        let filter = match &conf.filter_path {
+            Some(filter_path) => {
+                let filter_path = PathBuf::from(filter_path);
+                let watched = vec![filter_path.clone()];
+                Some(Reloadable::load("filter", watched, move || {
+                    SignatureFilter::load(&filter_path)
+                })?)
+            }
+            None => None,
+        };
+
+        // Optional threshold.config-style rate limiting
+        let thresholds = match &conf.threshold_path {
+            Some(threshold_path) => {
+                let threshold_path = PathBuf::from(threshold_path);
+                let watched = vec![threshold_path.clone()];
+                Some(Reloadable::load("thresholds", watched, move || {
+                    ThresholdRules::load(&threshold_path)
+                })?)
+            }
+            None => None,
+        };
+
+        // Optional GeoIP/ASN enrichment from local MaxMind databases
+        let geoip = if conf.geoip_city_path.is_some() || conf.geoip_asn_path.is_some() {
+            let city_path = conf.geoip_city_path.as_ref().map(PathBuf::from);
+            let asn_path = conf.geoip_asn_path.as_ref().map(PathBuf::from);
+            let watched = city_path.iter().chain(asn_path.iter()).cloned().collect();
+            Some(Reloadable::load("geoip", watched, move || {
+                GeoIp::load(city_path.as_deref(), asn_path.as_deref())
+            })?)
+        } else {
+            None
+        };
+
+        // Optional home-network direction and asset inventory tagging
+        let assets = if conf.home_net.is_some() || conf.asset_inventory_path.is_some() {
+            let home_net = conf.home_net.clone().unwrap_or_default();
+            let inventory = conf.asset_inventory_path.as_ref().map(PathBuf::from);
+            let watched = inventory.iter().cloned().collect();
+            let assets = Reloadable::load("asset inventory", watched, move || {
+                AssetTagger::load(&home_net, inventory.as_deref())
+            })?;
+            info!("Loaded {} assets", assets.get().asset_count());
+            Some(assets)
+        } else {
+            None
+        };
+
+        // Optional threat-intel IOC matching
+        let iocs = match &conf.ioc_path {
+            Some(ioc_path) => {
+                let ioc_path = PathBuf::from(ioc_path);
+                let watched = vec![ioc_path.clone()];
+                let iocs = Reloadable::load("IOC feeds", watched, move || IocSet::load(&ioc_path))?;
+                info!("Loaded {} indicators", iocs.get().indicator_count());
+                Some(iocs)
+            }
+            None => None,
+        };
+
+        // Optional passive DNS annotation from observed dns answers
+        let pdns = match conf.pdns_max_entries {
+            0 => None,
+            max_entries => Some(PassiveDns::new(max_entries)),
+        };
+
+        // Optional payload, URL and filename redaction
+        let privacy = match &conf.privacy_path {
+            Some(privacy_path) => {
+                let privacy_path = PathBuf::from(privacy_path);
+                let watched = vec![privacy_path.clone()];
+                Some(Reloadable::load("privacy policy", watched, move || {
+                    PrivacyPolicy::load(&privacy_path)
+                })?)
+            }
+            None => None,
+        };
+
+        // Optional site-specific Rhai scripts
+        let scripts = match &conf.scripts_path {
+            Some(scripts_path) => {
+                let scripts_path = PathBuf::from(scripts_path);
+                let timeout = Duration::from_millis(conf.script_timeout_ms);
+                let watched = vec![scripts_path.clone()];
+                let scripts = Reloadable::load("scripts", watched, move || {
+                    ScriptSet::load(&scripts_path, timeout)
+                })?;
+                info!("Loaded {} scripts", scripts.get().script_count());
+                Some(scripts)
+            }
+            None => None,
+        };
+
+        // Optional WebAssembly enrichment plugins
+        let plugins = match &conf.plugins_path {
+            Some(plugins_path) => {
+                let plugins_path = PathBuf::from(plugins_path);
+                let fuel = conf.plugin_fuel;
+                let max_output_bytes = conf.plugin_max_output_bytes;
+                let watched = vec![plugins_path.clone()];
+                let plugins = Reloadable::load("plugins", watched, move || {
+                    PluginSet::load(&plugins_path, fuel, max_output_bytes)
+                })?;
+                info!("Loaded {} plugins", plugins.get().plugin_count());
+                Some(plugins)
+            }
+            None => None,
+        };
+
+        // Optional prefix-preserving address pseudonymization
+        let anonymizer = match &conf.anonymize_key_path {
+            Some(key_path) => {
+                let key_path = PathBuf::from(key_path);
+                let exempt = conf.anonymize_exempt.clone().unwrap_or_default();
+                let watched = vec![key_path.clone()];
+                Some(Reloadable::load("anonymization key", watched, move || {
+                    Anonymizer::load(&key_path, &exempt)
+                })?)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            target_roles: conf.target_roles,
+            community_id_seed: conf.community_id_seed,
+            rules,
+            attack,
+            filter,
+            filter_counters: FilterCounters::new(),
+            thresholds,
+            limiter: RateLimiter::new(),
+            geoip,
+            assets,
+            iocs,
+            pdns,
+            privacy,
+            privacy_counters: PrivacyCounters::new(),
+            scripts,
+            script_counters: ScriptCounters::new(),
+            plugins,
+            plugin_counters: PluginCounters::new(),
+            anonymizer,
+        })
+    }
+
+    /// Runs a parsed EVE record through the enabled stages. Returns the event
+    /// to queue (with its single metric attached), or `None` if the record is
+    /// not an alert or was filtered or rate limited.
+    pub fn process(&self, alert: &mut SuricataAlert) -> Option<SensorEvent> {
+        if let Some(pdns) = &self.pdns {
+            pdns.observe(alert);
+        }
+        alert.alert.as_ref()?;
+
+        if let Some(filter) = &self.filter {
+            if !self.filter_counters.allows(&filter.get(), alert) {
+                return None;
+            }
+        }
+        let suppressed_count = match &self.thresholds {
+            Some(thresholds) => self.limiter.check(&thresholds.get(), alert)?,
+            None => 0,
+        };
+
+        let redacted = self
+            .privacy
+            .as_ref()
+            .map(|privacy| self.privacy_counters.redact(&privacy.get(), alert));
+
+        let (mut event, mut metric) = processor::convert_suricata_alert_to_sensor_event(alert)?;
+
+        metric.event_community_id = alert
+            .community_id
+            .clone()
+            .or_else(|| community_id(alert, self.community_id_seed));
+        if self.target_roles {
+            processor::apply_target_roles(alert, &mut metric);
+        }
+        if suppressed_count > 0 {
+            metric.event_suppressed_count = Some(suppressed_count);
+        }
+        if let Some(redacted) = redacted {
+            redacted.mark(&mut metric);
+        }
+        if let Some(pdns) = &self.pdns {
+            pdns.annotate(&mut metric);
+        }
+        if let Some(assets) = &self.assets {
+            assets.get().tag(&mut metric);
+        }
+        if let Some(geoip) = &self.geoip {
+            geoip.get().enrich(&mut metric);
+        }
+        if let Some(iocs) = &self.iocs {
+            iocs.get().match_alert(alert, &mut metric);
+        }
+        let ruleset = self.rules.as_ref().map(|rules| rules.get());
+        if let Some(ruleset) = &ruleset {
+            ruleset.enrich(&mut event);
+        }
+        let rule = ruleset.as_ref().and_then(|ruleset| {
+            ruleset.rule(
+                event.snort_rule_gid,
+                event.snort_rule_sid,
+                event.snort_rule_rev,
+            )
+        });
+        self.attack.get().map(alert, rule, &mut event);
+        if let Some(scripts) = &self.scripts {
+            if !self
+                .script_counters
+                .run(&scripts.get(), alert, &mut event, &mut metric)
+            {
+                return None;
+            }
+            processor::rehash(&mut event);
+        }
+        event.metrics.push(metric);
+
+        if let Some(plugins) = &self.plugins {
+            if !self.plugin_counters.run(&plugins.get(), &mut event) {
+                return None;
+            }
+            processor::rehash(&mut event);
+        }
+        // Last, so the stages above see the real addresses.
+        if let Some(anonymizer) = &self.anonymizer {
+            let anonymizer = anonymizer.get();
+            for metric in event.metrics.iter_mut() {
+                anonymizer.anonymize(metric);
+            }
+        }
+
+        Some(event)
+    }
+
+    /// Whether `reload()` has any work to do: a configured stage watches
+    /// files or keeps time-bounded state.
+    pub fn needs_reload(&self) -> bool {
+        self.rules.is_some()
+            || self.attack.is_watching()
+            || self.filter.is_some()
+            || self.thresholds.is_some()
+            || self.geoip.is_some()
+            || self.assets.is_some()
+            || self.iocs.is_some()
+            || self.pdns.is_some()
+            || self.privacy.is_some()
+            || self.scripts.is_some()
+            || self.plugins.is_some()
+            || self.anonymizer.is_some()
+    }
+
+    /// Reloads changed files and expires time-bounded state. Called
+    /// periodically from the reloader thread.
+    pub fn reload(&self) {
+        if let Some(rules) = &self.rules {
+            if rules.reload_if_changed() {
+                let ruleset = rules.get();
+                info!(
+                    "Ruleset now has {} rules (version {})",
+                    ruleset.rule_count(),
+                    ruleset.version()
+                );
+            }
+        }
+        self.attack.reload_if_changed();
+        if let Some(filter) = &self.filter {
+            filter.reload_if_changed();
+        }
+        if let Some(thresholds) = &self.thresholds {
+            if thresholds.reload_if_changed() {
+                info!(
+                    "Thresholds now have {} entries",
+                    thresholds.get().rule_count()
+                );
+            }
+            let pending = self.limiter.expire(&thresholds.get(), Instant::now());
+            if pending > 0 {
+                info!(
+                    "Expired threshold tracking with {} unreported suppressed alerts",
+                    pending
+                );
+            }
+        }
+        if let Some(geoip) = &self.geoip {
+            geoip.reload_if_changed();
+        }
+        if let Some(assets) = &self.assets {
+            if assets.reload_if_changed() {
+                info!(
+                    "Asset inventory now has {} assets",
+                    assets.get().asset_count()
+                );
+            }
+        }
+        if let Some(iocs) = &self.iocs {
+            if iocs.reload_if_changed() {
+                info!(
+                    "IOC feeds now have {} indicators",
+                    iocs.get().indicator_count()
+                );
+            }
+        }
+        if let Some(pdns) = &self.pdns {
+            pdns.expire();
+        }
+        if let Some(privacy) = &self.privacy {
+            privacy.reload_if_changed();
+        }
+        if let Some(scripts) = &self.scripts {
+            if scripts.reload_if_changed() {
+                info!("Now running {} scripts", scripts.get().script_count());
+            }
+        }
+        if let Some(plugins) = &self.plugins {
+            if plugins.reload_if_changed() {
+                info!("Now running {} plugins", plugins.get().plugin_count());
+            }
+        }
+        if let Some(anonymizer) = &self.anonymizer {
+            anonymizer.reload_if_changed();
+        }
+    }
+
+    pub fn log_metrics(&self) {
+        if let Some(rules) = &self.rules {
+            info!(
+                "Ruleset metrics: rules={} reloads={} reload_errors={}",
+                rules.get().rule_count(),
+                rules.get_total_reloads(),
+                rules.get_total_reload_errors()
+            );
+        }
+        info!(
+            "ATT&CK metrics: mapped={} reloads={} reload_errors={}",
+            self.attack.get().get_total_mapped(),
+            self.attack.get_total_reloads(),
+            self.attack.get_total_reload_errors()
+        );
+        if let Some(filter) = &self.filter {
+            let counters: Vec<String> = self
+                .filter_counters
+                .get_rule_counters(&filter.get())
+                .into_iter()
+                .map(|(name, hits, drops)| format!("{}={}/{}", name, hits, drops))
+                .collect();
+            info!(
+                "Filter metrics: dropped={} reloads={} reload_errors={} rule_hits/drops=[{}]",
+                self.filter_counters.get_total_dropped(),
+                filter.get_total_reloads(),
+                filter.get_total_reload_errors(),
+                counters.join(" ")
+            );
+        }
+        if let Some(thresholds) = &self.thresholds {
+            info!(
+                "Threshold metrics: suppressed={} expired_suppressed={} tracked={} reloads={} reload_errors={}",
+                self.limiter.get_total_suppressed(),
+                self.limiter.get_total_expired_suppressed(),
+                self.limiter.get_tracked_count(),
+                thresholds.get_total_reloads(),
+                thresholds.get_total_reload_errors()
+            );
+        }
+        if let Some(geoip) = &self.geoip {
+            let current = geoip.get();
+            info!(
+                "GeoIP metrics: lookups={} cache_hits={} reloads={} reload_errors={}",
+                current.get_total_lookups(),
+                current.get_total_cache_hits(),
+                geoip.get_total_reloads(),
+                geoip.get_total_reload_errors()
+            );
+        }
+        if let Some(iocs) = &self.iocs {
+            let current = iocs.get();
+            info!(
+                "IOC metrics: indicators={} matches={} reloads={} reload_errors={}",
+                current.indicator_count(),
+                current.get_total_matches(),
+                iocs.get_total_reloads(),
+                iocs.get_total_reload_errors()
+            );
+        }
+        if let Some(pdns) = &self.pdns {
+            info!(
+                "Passive DNS metrics: cached_addresses={} answers={} evicted={}",
+                pdns.get_cache_size(),
+                pdns.get_total_answers(),
+                pdns.get_total_evicted()
+            );
+        }
+        if let Some(privacy) = &self.privacy {
+            let counters: Vec<String> = self
+                .privacy_counters
+                .get_counters(&privacy.get())
+                .into_iter()
+                .map(|(name, count)| format!("{}={}", name, count))
+                .collect();
+            info!(
+                "Privacy metrics: redacted={} reloads={} reload_errors={} [{}]",
+                self.privacy_counters.get_total_redacted(),
+                privacy.get_total_reloads(),
+                privacy.get_total_reload_errors(),
+                counters.join(" ")
+            );
+        }
+        if let Some(scripts) = &self.scripts {
+            let counters: Vec<String> = self
+                .script_counters
+                .get_script_counters(&scripts.get())
+                .into_iter()
+                .map(|(name, runs, drops, errors, timeouts)| {
+                    format!("{}={}/{}/{}/{}", name, runs, drops, errors, timeouts)
+                })
+                .collect();
+            info!(
+                "Script metrics: reloads={} reload_errors={} runs/drops/errors/timeouts=[{}]",
+                scripts.get_total_reloads(),
+                scripts.get_total_reload_errors(),
+                counters.join(" ")
+            );
+        }
+        if let Some(plugins) = &self.plugins {
+            let counters: Vec<String> = self
+                .plugin_counters
+                .get_plugin_counters(&plugins.get())
+                .into_iter()
+                .map(|(name, runs, drops, errors, out_of_fuel)| {
+                    format!("{}={}/{}/{}/{}", name, runs, drops, errors, out_of_fuel)
+                })
+                .collect();
+            info!(
+                "Plugin metrics: reloads={} reload_errors={} runs/drops/errors/out_of_fuel=[{}]",
+                plugins.get_total_reloads(),
+                plugins.get_total_reload_errors(),
+                counters.join(" ")
+            );
+        }
+        if let Some(anonymizer) = &self.anonymizer {
+            info!(
+                "Anonymization metrics: anonymized={} reloads={} reload_errors={}",
+                anonymizer.get().get_total_anonymized(),
+                anonymizer.get_total_reloads(),
+                anonymizer.get_total_reload_errors()
+            );
+        }
+    }
+}
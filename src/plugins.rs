@@ -0,0 +1,232 @@
+use crate::pb::SensorEvent;
+use dashmap::DashMap;
+use log::warn;
+use prost::Message;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use wasmtime::{
+    Config, Engine, InstancePre, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap,
+};
+
+// Largest linear memory a plugin instance may grow to.
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+// `enrich` return values other than a packed (ptr, len) pair.
+const VERDICT_KEEP: i64 = 0;
+const VERDICT_DROP: i64 = -1;
+
+enum Verdict {
+    Keep,
+    Drop,
+    Replace(Box<SensorEvent>),
+}
+
+struct Plugin {
+    name: String,
+    pre: InstancePre<StoreLimits>,
+    // Only the first failure of a plugin is logged.
+    failure_logged: AtomicBool,
+}
+
+/// WebAssembly enrichment plugins run on each event after scripts.
+///
+/// A plugin is a `.wasm` module without imports that exports:
+/// - `memory`,
+/// - `alloc(len: i32) -> i32`, returning a buffer of `len` bytes,
+/// - `enrich(ptr: i32, len: i32) -> i64`, called with the protobuf-encoded
+///   `SensorEvent` (including its metric) written to a buffer from `alloc`.
+///
+/// `enrich` returns `0` to keep the event unchanged, `-1` to drop it, or
+/// `(out_ptr << 32) | out_len` locating the encoded replacement event in
+/// memory. Every event gets a fresh instance limited by fuel and memory, so
+/// plugins keep no state between events. A plugin that traps or returns an
+/// invalid event leaves the event unchanged.
+pub struct PluginSet {
+    engine: Engine,
+    plugins: Vec<Plugin>,
+    fuel: u64,
+    max_output_bytes: usize,
+}
+
+impl PluginSet {
+    /// `path` is a `.wasm` file or a directory of them.
+    pub fn load(
+        path: &Path,
+        fuel: u64,
+        max_output_bytes: usize,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut files = if path.is_dir() {
+            fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|e| e == "wasm"))
+                .collect()
+        } else {
+            vec![path.to_path_buf()]
+        };
+        files.sort();
+
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)?;
+        let linker = Linker::new(&engine);
+
+        let mut plugins = Vec::new();
+        for file in files {
+            let invalid = |e: wasmtime::Error| format!("{}: {}", file.display(), e);
+            let module = Module::from_binary(&engine, &fs::read(&file)?).map_err(invalid)?;
+            for export in ["memory", "alloc", "enrich"] {
+                if module.get_export(export).is_none() {
+                    return Err(format!("{}: missing export {:?}", file.display(), export).into());
+                }
+            }
+            plugins.push(Plugin {
+                name: file
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                pre: linker.instantiate_pre(&module).map_err(invalid)?,
+                failure_logged: AtomicBool::new(false),
+            });
+        }
+
+        Ok(Self {
+            engine,
+            plugins,
+            fuel,
+            max_output_bytes,
+        })
+    }
+
+    pub fn plugin_count(&self) -> usize {
+        self.plugins.len()
+    }
+
+    fn run_plugin(&self, plugin: &Plugin, event: &SensorEvent) -> wasmtime::Result<Verdict> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY_BYTES)
+            .build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(self.fuel)?;
+
+        let instance = plugin.pre.instantiate(&mut store)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| wasmtime::Error::msg("export memory is not a memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let enrich = instance.get_typed_func::<(i32, i32), i64>(&mut store, "enrich")?;
+
+        let input = event.encode_to_vec();
+        let ptr = alloc.call(&mut store, input.len() as i32)?;
+        memory.write(&mut store, ptr as u32 as usize, &input)?;
+
+        let verdict = enrich.call(&mut store, (ptr, input.len() as i32))?;
+        match verdict {
+            VERDICT_KEEP => Ok(Verdict::Keep),
+            VERDICT_DROP => Ok(Verdict::Drop),
+            packed => {
+                let out_ptr = (packed as u64 >> 32) as usize;
+                let out_len = (packed as u64 & 0xffff_ffff) as usize;
+                // Checked before allocating, so a plugin can't make the host
+                // allocate more than it could have written.
+                if out_len > self.max_output_bytes {
+                    return Err(wasmtime::Error::msg(format!(
+                        "output of {} bytes exceeds the limit of {}",
+                        out_len, self.max_output_bytes
+                    )));
+                }
+                if out_ptr
+                    .checked_add(out_len)
+                    .is_none_or(|end| end > memory.data_size(&store))
+                {
+                    return Err(wasmtime::Error::msg("output out of bounds"));
+                }
+                let mut output = vec![0; out_len];
+                memory.read(&store, out_ptr, &mut output)?;
+                Ok(Verdict::Replace(Box::new(SensorEvent::decode(
+                    output.as_slice(),
+                )?)))
+            }
+        }
+    }
+}
+
+/// Per-plugin counters of a `PluginSet`.
+///
+/// Counters are keyed by plugin name and kept apart from the plugins so that
+/// they survive a reload.
+#[derive(Default)]
+pub struct PluginCounters {
+    plugins: DashMap<String, RunCounters>,
+}
+
+#[derive(Default)]
+struct RunCounters {
+    runs: AtomicI64,
+    drops: AtomicI64,
+    errors: AtomicI64,
+    out_of_fuel: AtomicI64,
+}
+
+impl PluginCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the plugins on the event. Returns false if a plugin dropped it.
+    pub fn run(&self, plugins: &PluginSet, event: &mut SensorEvent) -> bool {
+        for plugin in &plugins.plugins {
+            let result = plugins.run_plugin(plugin, event);
+            self.record(&plugin.name, &result);
+            match result {
+                Ok(Verdict::Keep) => {}
+                Ok(Verdict::Replace(replacement)) => *event = *replacement,
+                Ok(Verdict::Drop) => return false,
+                Err(e) => {
+                    if !plugin.failure_logged.swap(true, Ordering::Relaxed) {
+                        warn!("Plugin {} failed: {:#}", plugin.name, e);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    fn record(&self, name: &str, result: &wasmtime::Result<Verdict>) {
+        let count = |counters: &RunCounters| {
+            counters.runs.fetch_add(1, Ordering::Relaxed);
+            let counter = match result {
+                Ok(Verdict::Keep | Verdict::Replace(_)) => return,
+                Ok(Verdict::Drop) => &counters.drops,
+                Err(e) if e.downcast_ref::<Trap>() == Some(&Trap::OutOfFuel) => {
+                    &counters.out_of_fuel
+                }
+                Err(_) => &counters.errors,
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+        };
+        match self.plugins.get(name) {
+            Some(counters) => count(&counters),
+            None => count(&self.plugins.entry(name.to_string()).or_default()),
+        }
+    }
+
+    /// Per-plugin (name, runs, drops, errors, out_of_fuel) counters of the
+    /// plugins of the set, in run order.
+    pub fn get_plugin_counters(&self, plugins: &PluginSet) -> Vec<(String, i64, i64, i64, i64)> {
+        plugins
+            .plugins
+            .iter()
+            .map(|plugin| match self.plugins.get(&plugin.name) {
+                Some(counters) => (
+                    plugin.name.clone(),
+                    counters.runs.load(Ordering::Relaxed),
+                    counters.drops.load(Ordering::Relaxed),
+                    counters.errors.load(Ordering::Relaxed),
+                    counters.out_of_fuel.load(Ordering::Relaxed),
+                ),
+                None => (plugin.name.clone(), 0, 0, 0, 0),
+            })
+            .collect()
+    }
+}
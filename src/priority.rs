@@ -0,0 +1,92 @@
+use crate::pb::SensorEvent;
+use crate::queue::EventBatchQueue;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+/// Which events skip the regular aggregation window.
+#[derive(Debug, Default)]
+pub struct PriorityRule {
+    // Severities from 1 up to this one match; 0 matches none
+    pub max_severity: i64,
+    pub sids: HashSet<i64>,
+    // Events that matched an IOC feed
+    pub ioc: bool,
+}
+
+impl PriorityRule {
+    /// `sids` is a comma-separated list such as `"2019401, 2024897"`.
+    pub fn new(
+        max_severity: i64,
+        sids: &str,
+        ioc: bool,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let sids = sids
+            .split(',')
+            .map(str::trim)
+            .filter(|sid| !sid.is_empty())
+            .map(|sid| {
+                sid.parse::<i64>()
+                    .map_err(|e| format!("invalid priority sid {:?}: {}", sid, e))
+            })
+            .collect::<Result<HashSet<_>, _>>()?;
+        Ok(Self {
+            max_severity,
+            sids,
+            ioc,
+        })
+    }
+
+    /// Whether any predicate is configured.
+    pub fn is_enabled(&self) -> bool {
+        self.max_severity > 0 || !self.sids.is_empty() || self.ioc
+    }
+
+    pub fn matches(&self, event: &SensorEvent) -> bool {
+        (event.snort_priority > 0 && event.snort_priority <= self.max_severity)
+            || self.sids.contains(&event.snort_rule_sid)
+            || (self.ioc
+                && event
+                    .metrics
+                    .iter()
+                    .any(|m| !m.event_ioc_matches.is_empty()))
+    }
+}
+
+/// Express path for events matching a [`PriorityRule`]. They are batched on
+/// their own queue with a short flush interval and sent on their own channel,
+/// which the client reads before the regular one.
+pub struct PriorityLane {
+    rule: PriorityRule,
+    queue: EventBatchQueue,
+    // Metrics
+    total_routed: AtomicI64,
+}
+
+impl PriorityLane {
+    pub fn new(rule: PriorityRule, queue: EventBatchQueue) -> Self {
+        Self {
+            rule,
+            queue,
+            total_routed: AtomicI64::new(0),
+        }
+    }
+
+    /// Adds the event to this lane's queue if it matches, and hands it back
+    /// otherwise.
+    pub fn add(&self, event: SensorEvent) -> Option<SensorEvent> {
+        if !self.rule.matches(&event) {
+            return Some(event);
+        }
+        self.total_routed.fetch_add(1, Ordering::Relaxed);
+        self.queue.add(event);
+        None
+    }
+
+    pub fn queue(&self) -> &EventBatchQueue {
+        &self.queue
+    }
+
+    pub fn get_total_routed(&self) -> i64 {
+        self.total_routed.load(Ordering::Relaxed)
+    }
+}
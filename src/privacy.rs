@@ -0,0 +1,385 @@
+use crate::pb::Metric;
+use crate::types::SuricataAlert;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use config::{Config, File};
+use dashmap::DashMap;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+// Patterns available to `redact` without defining them in the policy file.
+const BUILTIN_PATTERNS: &[(&str, &str)] = &[
+    (
+        "email",
+        r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}",
+    ),
+    (
+        "credentials",
+        r"(?i)(?:authorization:\s*(?:basic|bearer)\s+\S+|(?:password|passwd|pwd|pass|token|api_?key|secret)=[^&\s]+)",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadAction {
+    #[default]
+    Keep,
+    Strip,
+    Truncate,
+    Hash,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldAction {
+    #[default]
+    Keep,
+    Strip,
+    Redact,
+}
+
+/// On-disk privacy policy (TOML, YAML or JSON, picked by file extension).
+///
+/// The first `[[policies]]` entry listing the alert's category applies,
+/// otherwise `default` does. `redact` names patterns from `[patterns]` or the
+/// built-in `email` and `credentials` patterns; they are applied to the
+/// decoded payload and to URLs and filenames whose action is `redact`.
+#[derive(Debug, Deserialize)]
+struct PrivacyFile {
+    #[serde(default = "default_replacement")]
+    replacement: String,
+    #[serde(default)]
+    patterns: HashMap<String, String>,
+    #[serde(default)]
+    default: PolicyConfig,
+    #[serde(default)]
+    policies: Vec<PolicyConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PolicyConfig {
+    #[serde(default)]
+    category: Vec<String>,
+    #[serde(default)]
+    payload: PayloadAction,
+    #[serde(default)]
+    truncate_bytes: usize,
+    #[serde(default)]
+    redact: Vec<String>,
+    #[serde(default)]
+    url: FieldAction,
+    #[serde(default)]
+    filename: FieldAction,
+}
+
+fn default_replacement() -> String {
+    "[REDACTED]".to_string()
+}
+
+struct Pattern {
+    name: String,
+    text: regex::Regex,
+    bytes: regex::bytes::Regex,
+}
+
+struct Policy {
+    category: Vec<String>,
+    payload: PayloadAction,
+    truncate_bytes: usize,
+    redact: Vec<usize>,
+    url: FieldAction,
+    filename: FieldAction,
+}
+
+/// What `redact` changed, to be recorded on the outgoing metric.
+#[derive(Debug, Default)]
+pub struct Redacted {
+    pub fields: Vec<&'static str>,
+    pub payload_sha256: Option<String>,
+}
+
+impl Redacted {
+    pub fn mark(self, metric: &mut Metric) {
+        metric.event_redacted_fields = self.fields.iter().map(|f| f.to_string()).collect();
+        metric.event_payload_sha256 = self.payload_sha256;
+    }
+}
+
+/// Payload, URL and filename redaction applied to parsed alerts before they
+/// are converted, so no later stage or output sees the original values.
+pub struct PrivacyPolicy {
+    replacement: String,
+    patterns: Vec<Pattern>,
+    default: Policy,
+    policies: Vec<Policy>,
+}
+
+impl PrivacyPolicy {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let file: PrivacyFile = Config::builder()
+            .add_source(File::from(path))
+            .build()?
+            .try_deserialize()?;
+
+        let mut sources: Vec<(String, String)> = BUILTIN_PATTERNS
+            .iter()
+            .filter(|(name, _)| !file.patterns.contains_key(*name))
+            .map(|(name, pattern)| (name.to_string(), pattern.to_string()))
+            .collect();
+        let mut custom: Vec<_> = file.patterns.into_iter().collect();
+        custom.sort();
+        sources.extend(custom);
+
+        let mut patterns = Vec::new();
+        for (name, pattern) in sources {
+            let invalid = |e: regex::Error| format!("privacy pattern {:?}: {}", name, e);
+            patterns.push(Pattern {
+                text: regex::Regex::new(&pattern).map_err(invalid)?,
+                bytes: regex::bytes::Regex::new(&pattern).map_err(invalid)?,
+                name,
+            });
+        }
+
+        let compile = |config: PolicyConfig| -> Result<Policy, String> {
+            // An unset size would empty every payload
+            if config.payload == PayloadAction::Truncate && config.truncate_bytes == 0 {
+                return Err(format!(
+                    "privacy policy for {:?} truncates payloads without a truncate_bytes size",
+                    config.category
+                ));
+            }
+            let redact = config
+                .redact
+                .iter()
+                .map(|name| {
+                    patterns
+                        .iter()
+                        .position(|p| &p.name == name)
+                        .ok_or_else(|| format!("unknown privacy pattern {:?}", name))
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(Policy {
+                category: config.category,
+                payload: config.payload,
+                truncate_bytes: config.truncate_bytes,
+                redact,
+                url: config.url,
+                filename: config.filename,
+            })
+        };
+        let default = compile(file.default)?;
+        let policies = file
+            .policies
+            .into_iter()
+            .map(compile)
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            replacement: file.replacement,
+            patterns,
+            default,
+            policies,
+        })
+    }
+}
+
+/// Redaction counters of a `PrivacyPolicy`.
+///
+/// Pattern counters are keyed by pattern name and kept apart from the policy
+/// so that they survive a reload.
+#[derive(Default)]
+pub struct PrivacyCounters {
+    patterns: DashMap<String, AtomicI64>,
+    total_redacted: AtomicI64,
+    payloads_stripped: AtomicI64,
+    payloads_truncated: AtomicI64,
+    payloads_hashed: AtomicI64,
+    urls_redacted: AtomicI64,
+    filenames_redacted: AtomicI64,
+}
+
+impl PrivacyCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies the policy for the alert's category to its payload, HTTP URL
+    /// and filenames.
+    pub fn redact(&self, privacy: &PrivacyPolicy, data: &mut SuricataAlert) -> Redacted {
+        let category = data.alert.as_ref().map(|a| a.category.as_str());
+        let policy = privacy
+            .policies
+            .iter()
+            .find(|p| {
+                category.is_some_and(|c| p.category.iter().any(|v| v.eq_ignore_ascii_case(c)))
+            })
+            .unwrap_or(&privacy.default);
+        let mut redacted = Redacted::default();
+
+        if let Some(payload) = data.payload.take() {
+            data.payload = self.redact_payload(privacy, policy, payload, &mut redacted);
+        }
+        if let Some(http) = data.http.as_mut() {
+            if self.redact_field(privacy, policy, policy.url, &mut http.url) {
+                self.urls_redacted.fetch_add(1, Ordering::Relaxed);
+                redacted.fields.push("http_url");
+            }
+        }
+        let mut filenames = false;
+        for file in data.files.iter_mut().flatten() {
+            filenames |= self.redact_field(privacy, policy, policy.filename, &mut file.filename);
+        }
+        if filenames {
+            self.filenames_redacted.fetch_add(1, Ordering::Relaxed);
+            redacted.fields.push("filename");
+        }
+
+        if !redacted.fields.is_empty() {
+            self.total_redacted.fetch_add(1, Ordering::Relaxed);
+        }
+        redacted
+    }
+
+    fn redact_payload(
+        &self,
+        privacy: &PrivacyPolicy,
+        policy: &Policy,
+        payload: String,
+        redacted: &mut Redacted,
+    ) -> Option<String> {
+        if policy.payload == PayloadAction::Keep && policy.redact.is_empty() {
+            return Some(payload);
+        }
+        // A payload that cannot be decoded cannot be inspected, so it is
+        // not forwarded.
+        let mut decoded = match BASE64.decode(&payload) {
+            Ok(decoded) => decoded,
+            Err(_) => {
+                self.payloads_stripped.fetch_add(1, Ordering::Relaxed);
+                redacted.fields.push("payload");
+                return None;
+            }
+        };
+
+        match policy.payload {
+            PayloadAction::Strip => {
+                self.payloads_stripped.fetch_add(1, Ordering::Relaxed);
+                redacted.fields.push("payload");
+                return None;
+            }
+            PayloadAction::Hash => {
+                self.payloads_hashed.fetch_add(1, Ordering::Relaxed);
+                redacted.fields.push("payload");
+                redacted.payload_sha256 = Some(hex::encode(Sha256::digest(&decoded)));
+                return None;
+            }
+            PayloadAction::Keep | PayloadAction::Truncate => {}
+        }
+
+        // Patterns run before truncation so a secret cut at the boundary is
+        // still found.
+        let mut changed = false;
+        for &index in &policy.redact {
+            let pattern = &privacy.patterns[index];
+            let count = pattern.bytes.find_iter(&decoded).count();
+            if count > 0 {
+                self.record_matches(&pattern.name, count);
+                decoded = pattern
+                    .bytes
+                    .replace_all(&decoded, privacy.replacement.as_bytes())
+                    .into_owned();
+                changed = true;
+            }
+        }
+        if policy.payload == PayloadAction::Truncate && decoded.len() > policy.truncate_bytes {
+            self.payloads_truncated.fetch_add(1, Ordering::Relaxed);
+            decoded.truncate(policy.truncate_bytes);
+            changed = true;
+        }
+
+        if !changed {
+            return Some(payload);
+        }
+        redacted.fields.push("payload");
+        Some(BASE64.encode(decoded))
+    }
+
+    /// Returns true if the field was changed.
+    fn redact_field(
+        &self,
+        privacy: &PrivacyPolicy,
+        policy: &Policy,
+        action: FieldAction,
+        field: &mut Option<String>,
+    ) -> bool {
+        let value = match field.as_mut() {
+            Some(value) => value,
+            None => return false,
+        };
+        match action {
+            FieldAction::Keep => false,
+            FieldAction::Strip => {
+                *field = None;
+                true
+            }
+            FieldAction::Redact => {
+                let mut changed = false;
+                for &index in &policy.redact {
+                    let pattern = &privacy.patterns[index];
+                    let count = pattern.text.find_iter(value).count();
+                    if count > 0 {
+                        self.record_matches(&pattern.name, count);
+                        *value = pattern
+                            .text
+                            .replace_all(value, privacy.replacement.as_str())
+                            .into_owned();
+                        changed = true;
+                    }
+                }
+                changed
+            }
+        }
+    }
+
+    fn record_matches(&self, name: &str, count: usize) {
+        let count = count as i64;
+        match self.patterns.get(name) {
+            Some(matches) => matches.fetch_add(count, Ordering::Relaxed),
+            None => self
+                .patterns
+                .entry(name.to_string())
+                .or_default()
+                .fetch_add(count, Ordering::Relaxed),
+        };
+    }
+
+    pub fn get_total_redacted(&self) -> i64 {
+        self.total_redacted.load(Ordering::Relaxed)
+    }
+
+    /// (name, count) counters of redactions by kind and matches by pattern,
+    /// for the patterns of the policy.
+    pub fn get_counters(&self, privacy: &PrivacyPolicy) -> Vec<(String, i64)> {
+        let mut counters: Vec<(String, i64)> = [
+            ("payload_stripped", &self.payloads_stripped),
+            ("payload_truncated", &self.payloads_truncated),
+            ("payload_hashed", &self.payloads_hashed),
+            ("http_url", &self.urls_redacted),
+            ("filename", &self.filenames_redacted),
+        ]
+        .into_iter()
+        .map(|(name, counter)| (name.to_string(), counter.load(Ordering::Relaxed)))
+        .collect();
+        counters.extend(privacy.patterns.iter().map(|p| {
+            let matches = self
+                .patterns
+                .get(&p.name)
+                .map_or(0, |m| m.load(Ordering::Relaxed));
+            (format!("pattern_{}", p.name), matches)
+        }));
+        counters
+    }
+}
@@ -1,14 +1,11 @@
 use crate::pb::{Metric, SensorEvent};
-use crate::types::SuricataAlert;
+use crate::types::{AlertEndpoint, SuricataAlert};
 use sha2::{Digest, Sha256};
 
 pub fn convert_suricata_alert_to_sensor_event(
     data: &SuricataAlert,
 ) -> Option<(SensorEvent, Metric)> {
-    if data.alert.is_none() {
-        return None;
-    }
-    let alert = data.alert.as_ref().unwrap();
+    let alert = data.alert.as_ref()?;
 
     let tos = 0; // Default
 
@@ -36,6 +33,7 @@ pub fn convert_suricata_alert_to_sensor_event(
         event_read_at: data.metadata.read_at,
         event_sent_at: data.metadata.sent_at,
         event_received_at: data.metadata.received_at,
+        ..Default::default()
     };
 
     sensor_event.event_hash_sha256 = generate_hash_sha256(&sensor_event);
@@ -75,6 +73,7 @@ pub fn convert_suricata_alert_to_sensor_event(
     } else {
         None
     };
+    let snort_target = alert.target.as_ref().and_then(derive_target);
     let snort_tcp_flags = None; // Not available
     let snort_time_to_live = Some(0);
     let snort_vlan = Some(0);
@@ -95,38 +94,66 @@ pub fn convert_suricata_alert_to_sensor_event(
 
     let sensor_metric = Metric {
         snort_timestamp: data.timestamp.clone(),
-        snort_base64_data: snort_base64_data,
-        snort_client_bytes: snort_client_bytes,
-        snort_client_pkts: snort_client_pkts,
+        snort_base64_data,
+        snort_client_bytes,
+        snort_client_pkts,
         snort_dst_address: data.dest_ip.clone(),
-        snort_dst_port: snort_dst_port,
-        snort_dst_ap: snort_dst_ap,
+        snort_dst_port,
+        snort_dst_ap,
         snort_eth_dst: ether.and_then(|e| e.dest_mac.clone()),
         snort_eth_len: Some(snort_eth_len),
         snort_eth_src: ether.and_then(|e| e.src_mac.clone()),
         snort_eth_type: Some(snort_eth_type),
-        snort_flowstart_time: snort_flowstart_time,
-        snort_icmp_code: snort_icmp_code,
-        snort_icmp_type: snort_icmp_type,
+        snort_flowstart_time,
+        snort_icmp_code,
+        snort_icmp_type,
         snort_pkt_gen: Some(snort_pkt_gen),
-        snort_pkt_length: snort_pkt_length,
-        snort_pkt_number: snort_pkt_number,
-        snort_server_bytes: snort_server_bytes,
-        snort_server_pkts: snort_server_pkts,
+        snort_pkt_length,
+        snort_pkt_number,
+        snort_server_bytes,
+        snort_server_pkts,
         snort_src_address: data.src_ip.clone(),
-        snort_src_port: snort_src_port,
-        snort_src_ap: snort_src_ap,
-        snort_tcp_flags: snort_tcp_flags,
-        snort_tcp_len: snort_tcp_len,
-        snort_time_to_live: snort_time_to_live,
+        snort_src_port,
+        snort_src_ap,
+        snort_target,
+        snort_tcp_flags,
+        snort_tcp_len,
+        snort_time_to_live,
         snort_udp_length: snort_udp_len,
-        snort_vlan: snort_vlan,
+        snort_vlan,
         ..Default::default()
     };
 
     Some((sensor_event, sensor_metric))
 }
 
+/// Rewrites the metric's src/dst fields so that src is the attacker and dst is
+/// the victim, as identified by the rule's `target:` keyword. Suricata reports
+/// the packet direction in `src_ip`/`dest_ip`, which for responses is the
+/// opposite of the attack direction.
+pub fn apply_target_roles(data: &SuricataAlert, metric: &mut Metric) {
+    let target_ip = data
+        .alert
+        .as_ref()
+        .and_then(|a| a.target.as_ref())
+        .and_then(|t| t.ip.as_ref());
+
+    if target_ip.is_some() && target_ip == data.src_ip.as_ref() {
+        std::mem::swap(&mut metric.snort_src_address, &mut metric.snort_dst_address);
+        std::mem::swap(&mut metric.snort_src_port, &mut metric.snort_dst_port);
+        std::mem::swap(&mut metric.snort_src_ap, &mut metric.snort_dst_ap);
+        std::mem::swap(&mut metric.snort_eth_src, &mut metric.snort_eth_dst);
+    }
+}
+
+fn derive_target(target: &AlertEndpoint) -> Option<String> {
+    let ip = target.ip.as_ref()?;
+    match target.port {
+        Some(port) => Some(format!("{}:{}", ip, port)),
+        None => Some(ip.clone()),
+    }
+}
+
 fn parse_timestamp(_ts: &str) -> i64 {
     // Go format: "2006-01-02T15:04:05.000000-0700"
     // Rust chrono can parse this.
@@ -138,6 +165,15 @@ fn parse_timestamp(_ts: &str) -> i64 {
     0
 }
 
+/// Recomputes the event hash over the event fields, as they are after later
+/// stages changed them, so that events that differ are not merged.
+pub fn rehash(event: &mut SensorEvent) {
+    let metrics = std::mem::take(&mut event.metrics);
+    event.event_hash_sha256.clear();
+    event.event_hash_sha256 = generate_hash_sha256(event);
+    event.metrics = metrics;
+}
+
 fn generate_hash_sha256(payload: &SensorEvent) -> String {
     let mut hasher = Sha256::new();
     hasher.update(format!("{:?}", payload)); // Debug format is not exactly the same as Go's String(), but close enough for unique hash?
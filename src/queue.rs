@@ -1,22 +1,338 @@
-use crate::pb::SensorEvent;
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicI64, Ordering};
+use crate::pb::{Metric, SensorEvent};
+use prost::Message;
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub struct SensorEventRecord {
     pub payload: SensorEvent,
-    pub created_at: i64,
-    pub updated_at: i64,
+    pub created_at: Instant,
+    pub updated_at: Instant,
+    // Estimated encoded size of the record
+    pub bytes: usize,
+}
+
+/// When queued events are flushed into a batch.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlushPolicy {
+    /// A record is flushed once no event was merged into it for this long.
+    /// Zero flushes everything on every call.
+    pub interval: Duration,
+    /// A record is flushed this long after its first event, even if events
+    /// keep being merged into it.
+    pub max_age: Duration,
+    /// Everything is flushed once this many metrics are queued. Zero means
+    /// no limit.
+    pub max_events: usize,
+    /// Everything is flushed once the queued events' encoded size reaches
+    /// this many bytes. Zero means no limit.
+    pub max_bytes: usize,
+}
+
+impl FlushPolicy {
+    /// When the record is due for time-based flushing.
+    fn deadline(&self, record: &SensorEventRecord) -> Instant {
+        let idle = record.updated_at + self.interval;
+        if self.max_age.is_zero() {
+            idle
+        } else {
+            idle.min(record.created_at + self.max_age)
+        }
+    }
+}
+
+/// What the queue does when one of its memory caps is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    /// Evict the oldest events; full events keep their newest metrics
+    #[default]
+    EvictOldest,
+    /// Evict events of the lowest-severity signatures first, oldest first
+    EvictLowestSeverity,
+    /// Collapse events into one summary event per signature
+    Summarize,
+}
+
+/// Caps on what the queue holds. Zero means no cap. The record and byte
+/// caps are split evenly across shards.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryLimits {
+    pub max_records: usize,
+    /// Metrics merged into one event
+    pub max_record_metrics: usize,
+    /// Estimated encoded size of all queued events
+    pub max_bytes: usize,
+    pub overflow: OverflowPolicy,
+}
+
+/// What a cap cost while adding an event.
+#[derive(Default)]
+struct Overflow {
+    evicted: usize,
+    summarized: usize,
+    dropped_metrics: usize,
+}
+
+/// The order a shard keeps its records in for eviction, if it has a cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum EvictionOrder {
+    #[default]
+    None,
+    Oldest,
+    LowestSeverity,
+}
+
+type EvictionKey = (Reverse<i64>, Instant, String);
+
+#[derive(Default)]
+struct Shard {
+    records: HashMap<String, SensorEventRecord>,
+    // One entry per record, keyed by a deadline no later than the record's.
+    // Merges only move a deadline later, so an entry that pops early is
+    // pushed back with the record's current deadline instead of being
+    // updated on every merge.
+    expiry: BinaryHeap<Reverse<(Instant, String)>>,
+    // Expiry entries left by evicted records, per key. They pop before the
+    // entry of a record inserted again under the same key.
+    stale: HashMap<String, usize>,
+    order: EvictionOrder,
+    // Records other than summaries in eviction order: lowest severity
+    // (highest `snort_priority`) first when evicting by severity, then
+    // oldest first. Severity and age do not change on merges.
+    eviction: BTreeSet<EvictionKey>,
+    // Totals of the records
+    metrics: usize,
+    bytes: usize,
+}
+
+impl Shard {
+    fn new(order: EvictionOrder) -> Self {
+        Self {
+            order,
+            ..Default::default()
+        }
+    }
+
+    fn eviction_key(&self, key: &str, record: &SensorEventRecord) -> Option<EvictionKey> {
+        let priority = match self.order {
+            EvictionOrder::None => return None,
+            _ if key.starts_with(SUMMARY_PREFIX) => return None,
+            EvictionOrder::Oldest => 0,
+            EvictionOrder::LowestSeverity => record.payload.snort_priority,
+        };
+        Some((Reverse(priority), record.created_at, key.to_string()))
+    }
+
+    fn insert(&mut self, key: String, record: SensorEventRecord, policy: &FlushPolicy) {
+        self.metrics += record.payload.metrics.len();
+        self.bytes += record.bytes;
+        // Without an interval everything is flushed on every call
+        if !policy.interval.is_zero() {
+            self.expiry
+                .push(Reverse((policy.deadline(&record), key.clone())));
+        }
+        if let Some(eviction_key) = self.eviction_key(&key, &record) {
+            self.eviction.insert(eviction_key);
+        }
+        self.records.insert(key, record);
+    }
+
+    fn remove(&mut self, key: &str) -> Option<SensorEventRecord> {
+        let record = self.records.remove(key)?;
+        self.metrics -= record.payload.metrics.len();
+        self.bytes -= record.bytes;
+        if let Some(eviction_key) = self.eviction_key(key, &record) {
+            self.eviction.remove(&eviction_key);
+        }
+        Some(record)
+    }
+
+    /// Removes a record before its expiry entry pops, leaving that entry to
+    /// be skipped.
+    fn discard(&mut self, key: &str) -> Option<SensorEventRecord> {
+        let record = self.remove(key)?;
+        // Records only have expiry entries when the heap is in use
+        if !self.expiry.is_empty() {
+            *self.stale.entry(key.to_string()).or_default() += 1;
+        }
+        Some(record)
+    }
+
+    /// Whether a popped expiry entry belongs to an evicted record.
+    fn is_stale(&mut self, key: &str) -> bool {
+        match self.stale.get_mut(key) {
+            Some(count) => {
+                *count -= 1;
+                if *count == 0 {
+                    self.stale.remove(key);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Merges the event into the record with the same key, or inserts it,
+    /// keeping at most `max_metrics` metrics per record.
+    fn merge(
+        &mut self,
+        key: String,
+        mut event: SensorEvent,
+        now: Instant,
+        policy: &FlushPolicy,
+        limits: &MemoryLimits,
+        overflow: &mut Overflow,
+    ) {
+        let record = match self.records.get_mut(&key) {
+            Some(record) => record,
+            None => {
+                let excess = limits.excess(event.metrics.len());
+                drop_metrics(&mut event, excess, limits.overflow);
+                event.event_metrics_count = event.metrics.len() as i64;
+                overflow.dropped_metrics += excess;
+                let record = SensorEventRecord {
+                    bytes: event.encoded_len(),
+                    payload: event,
+                    created_at: now,
+                    updated_at: now,
+                };
+                self.insert(key, record, policy);
+                return;
+            }
+        };
+
+        let (metrics_before, bytes_before) = (record.payload.metrics.len(), record.bytes);
+        // Append metrics from new event to existing record
+        record.bytes += event.metrics.iter().map(metric_bytes).sum::<usize>();
+        record.payload.metrics.append(&mut event.metrics);
+        let excess = limits.excess(record.payload.metrics.len());
+        record.bytes -= drop_metrics(&mut record.payload, excess, limits.overflow);
+        record.payload.event_dropped_metrics += event.event_dropped_metrics;
+        record.payload.event_metrics_count = record.payload.metrics.len() as i64;
+        if event.event_last_seen.is_some() {
+            record.payload.event_last_seen = event.event_last_seen.take();
+        }
+        record.updated_at = now;
+        overflow.dropped_metrics += excess;
+
+        self.metrics = self.metrics + record.payload.metrics.len() - metrics_before;
+        self.bytes = self.bytes + record.bytes - bytes_before;
+    }
+
+    /// Folds the event into the summary of its signature: the first event
+    /// with one metric, counting the other alerts in `event_dropped_metrics`.
+    fn summarize(
+        &mut self,
+        mut event: SensorEvent,
+        now: Instant,
+        policy: &FlushPolicy,
+        overflow: &mut Overflow,
+    ) {
+        overflow.summarized += 1;
+        let key = summary_key(&event);
+        let alerts = event.metrics.len() as i64 + event.event_dropped_metrics;
+        if let Some(summary) = self.records.get_mut(&key) {
+            summary.payload.event_dropped_metrics += alerts;
+            summary.updated_at = now;
+            return;
+        }
+        event.metrics.truncate(1);
+        event.event_metrics_count = event.metrics.len() as i64;
+        event.event_dropped_metrics = alerts - event.event_metrics_count;
+        let record = SensorEventRecord {
+            bytes: event.encoded_len(),
+            payload: event,
+            created_at: now,
+            updated_at: now,
+        };
+        self.insert(key, record, policy);
+    }
+
+    /// The record to evict first, and its `snort_priority` when evicting by
+    /// severity.
+    fn victim(&self) -> Option<(String, i64)> {
+        self.eviction
+            .first()
+            .map(|(Reverse(priority), _, key)| (key.clone(), *priority))
+    }
+
+    fn evict(&mut self, key: &str, overflow: &mut Overflow) {
+        if let Some(record) = self.discard(key) {
+            overflow.evicted += 1;
+            overflow.dropped_metrics += record.payload.metrics.len();
+        }
+    }
+}
+
+impl MemoryLimits {
+    /// Metrics of a record over the per-record cap.
+    fn excess(&self, metrics: usize) -> usize {
+        match self.max_record_metrics {
+            0 => 0,
+            max => metrics.saturating_sub(max),
+        }
+    }
+}
+
+/// Removes `excess` metrics from the event, the oldest ones when evicting
+/// oldest and the newest otherwise. Returns their estimated size.
+fn drop_metrics(event: &mut SensorEvent, excess: usize, overflow: OverflowPolicy) -> usize {
+    if excess == 0 {
+        return 0;
+    }
+    let dropped: Vec<Metric> = match overflow {
+        OverflowPolicy::EvictOldest => event.metrics.drain(..excess).collect(),
+        _ => {
+            let keep = event.metrics.len() - excess;
+            event.metrics.drain(keep..).collect()
+        }
+    };
+    event.event_dropped_metrics += excess as i64;
+    dropped.iter().map(metric_bytes).sum()
+}
+
+/// Encoded size of a metric within its event.
+fn metric_bytes(metric: &Metric) -> usize {
+    let len = metric.encoded_len();
+    1 + prost::encoding::encoded_len_varint(len as u64) + len
+}
+
+// Summaries are keyed apart from event hashes, which are hex digests.
+const SUMMARY_PREFIX: &str = "summary:";
+
+fn summary_key(event: &SensorEvent) -> String {
+    format!(
+        "{}{}:{}:{}",
+        SUMMARY_PREFIX, event.snort_rule_gid, event.snort_rule_sid, event.snort_rule_rev
+    )
 }
 
 pub struct EventBatchQueue {
-    // We use Mutex<HashMap> instead of DashMap because we want to swap the entire map
-    // efficiently when processing batches.
-    queue: Arc<Mutex<HashMap<String, SensorEventRecord>>>,
-    delta: u64,
+    // Records are spread over shards by event hash so that workers rarely
+    // contend on the same lock. A hash always maps to the same shard, so
+    // events still merge. We use Mutex<HashMap> instead of DashMap because we
+    // want to swap a whole shard efficiently when processing batches.
+    shards: Vec<Mutex<Shard>>,
+    hasher: RandomState,
+    policy: FlushPolicy,
+    limits: MemoryLimits,
+    // Per-shard shares of the record and byte caps
+    shard_max_records: usize,
+    shard_max_bytes: usize,
+    // Totals over all shards, checked against the flush limits
+    queued_metrics: AtomicUsize,
+    queued_bytes: AtomicUsize,
     // Metrics
+    total_evicted: AtomicI64,
+    total_summarized: AtomicI64,
+    total_dropped_metrics: AtomicI64,
     latest_event_per_sec: Arc<AtomicI64>,
     event_this_sec: Arc<AtomicI64>,
     latest_batch_per_sec: Arc<AtomicI64>,
@@ -26,10 +342,26 @@ pub struct EventBatchQueue {
 }
 
 impl EventBatchQueue {
-    pub fn new(delta_seconds: u64) -> Self {
+    /// `shards` is rounded up to a power of two.
+    pub fn new(policy: FlushPolicy, limits: MemoryLimits, shards: usize) -> Self {
+        let shards = shards.max(1).next_power_of_two();
+        let order = match limits.overflow {
+            _ if limits.max_records == 0 && limits.max_bytes == 0 => EvictionOrder::None,
+            OverflowPolicy::EvictLowestSeverity => EvictionOrder::LowestSeverity,
+            OverflowPolicy::EvictOldest | OverflowPolicy::Summarize => EvictionOrder::Oldest,
+        };
         Self {
-            queue: Arc::new(Mutex::new(HashMap::new())),
-            delta: delta_seconds,
+            shards: (0..shards).map(|_| Mutex::new(Shard::new(order))).collect(),
+            hasher: RandomState::new(),
+            policy,
+            limits,
+            shard_max_records: limits.max_records.div_ceil(shards),
+            shard_max_bytes: limits.max_bytes.div_ceil(shards),
+            queued_metrics: AtomicUsize::new(0),
+            queued_bytes: AtomicUsize::new(0),
+            total_evicted: AtomicI64::new(0),
+            total_summarized: AtomicI64::new(0),
+            total_dropped_metrics: AtomicI64::new(0),
             latest_event_per_sec: Arc::new(AtomicI64::new(0)),
             event_this_sec: Arc::new(AtomicI64::new(0)),
             latest_batch_per_sec: Arc::new(AtomicI64::new(0)),
@@ -39,81 +371,157 @@ impl EventBatchQueue {
         }
     }
 
-    pub fn add(&self, mut event: SensorEvent) {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
+    pub fn add(&self, event: SensorEvent) {
+        let now = Instant::now();
+        let mut overflow = Overflow::default();
 
         // Check if event already exists
         // We use the event hash as the key
         let key = event.event_hash_sha256.clone();
 
         {
-            let mut queue = self.queue.lock().unwrap();
-            queue
-                .entry(key)
-                .and_modify(|record| {
-                    // Append metrics from new event to existing record
-                    record.payload.metrics.append(&mut event.metrics);
-                    record.updated_at = now;
-                })
-                .or_insert_with(|| SensorEventRecord {
-                    payload: event,
-                    created_at: now,
-                    updated_at: now,
-                });
+            let shard = self.hasher.hash_one(&key) as usize & (self.shards.len() - 1);
+            let mut queue = self.shards[shard].lock().unwrap();
+            let (metrics_before, bytes_before) = (queue.metrics, queue.bytes);
+            self.add_to_shard(&mut queue, key, event, now, &mut overflow);
+
+            // Updated under the shard lock so a concurrent flush never
+            // subtracts more than was added.
+            adjust(&self.queued_metrics, metrics_before, queue.metrics);
+            adjust(&self.queued_bytes, bytes_before, queue.bytes);
         }
 
         self.event_this_sec.fetch_add(1, Ordering::Relaxed);
+        if overflow.evicted > 0 || overflow.summarized > 0 || overflow.dropped_metrics > 0 {
+            self.total_evicted
+                .fetch_add(overflow.evicted as i64, Ordering::Relaxed);
+            self.total_summarized
+                .fetch_add(overflow.summarized as i64, Ordering::Relaxed);
+            self.total_dropped_metrics
+                .fetch_add(overflow.dropped_metrics as i64, Ordering::Relaxed);
+        }
+    }
+
+    fn add_to_shard(
+        &self,
+        queue: &mut Shard,
+        key: String,
+        event: SensorEvent,
+        now: Instant,
+        overflow: &mut Overflow,
+    ) {
+        let (policy, limits) = (&self.policy, &self.limits);
+
+        if !queue.records.contains_key(&key)
+            && self.shard_max_records > 0
+            && queue.records.len() >= self.shard_max_records
+        {
+            match limits.overflow {
+                OverflowPolicy::EvictOldest => {
+                    if let Some((victim, _)) = queue.victim() {
+                        queue.evict(&victim, overflow);
+                    }
+                }
+                OverflowPolicy::EvictLowestSeverity => {
+                    if let Some((victim, priority)) = queue.victim() {
+                        if event.snort_priority > priority {
+                            // The new event is the least severe
+                            overflow.dropped_metrics += event.metrics.len();
+                            return;
+                        }
+                        queue.evict(&victim, overflow);
+                    }
+                }
+                // Summaries may go past the record cap; there is one per
+                // signature at most.
+                OverflowPolicy::Summarize => return queue.summarize(event, now, policy, overflow),
+            }
+        }
+        queue.merge(key, event, now, policy, limits, overflow);
+
+        while self.shard_max_bytes > 0 && queue.bytes > self.shard_max_bytes {
+            match limits.overflow {
+                OverflowPolicy::EvictOldest | OverflowPolicy::EvictLowestSeverity => {
+                    match queue.victim() {
+                        Some((victim, _)) => queue.evict(&victim, overflow),
+                        None => break,
+                    }
+                }
+                OverflowPolicy::Summarize => {
+                    // Collapse the oldest event that is not a summary yet
+                    let oldest = queue.victim().map(|(key, _)| key);
+                    match oldest.and_then(|key| queue.discard(&key)) {
+                        Some(record) => queue.summarize(record.payload, now, policy, overflow),
+                        None => break,
+                    }
+                }
+            }
+        }
     }
 
     pub fn process_batch(&self) -> Vec<SensorEvent> {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
+        let now = Instant::now();
+        let policy = &self.policy;
 
         let mut batch = Vec::new();
         let mut total_metrics_count = 0;
+        let mut total_bytes = 0;
 
-        // Efficiently swap the entire map if delta is 0 (immediate processing)
-        // or if we just want to process everything.
-        // For non-zero delta, we still need to iterate, but we can do it efficiently.
+        let full = (policy.max_events > 0
+            && self.queued_metrics.load(Ordering::Relaxed) >= policy.max_events)
+            || (policy.max_bytes > 0
+                && self.queued_bytes.load(Ordering::Relaxed) >= policy.max_bytes);
 
-        if self.delta == 0 {
-            // O(1) swap strategy
-            let mut queue = self.queue.lock().unwrap();
-            if queue.is_empty() {
-                return batch;
+        // Shards are drained one at a time; there is no global lock.
+        for shard in &self.shards {
+            let mut queue = shard.lock().unwrap();
+            if queue.records.is_empty() {
+                continue;
             }
 
-            // Take the entire map content
-            let old_queue = std::mem::take(&mut *queue);
-            drop(queue); // Release lock immediately
+            let flushed = if policy.interval.is_zero() || full {
+                // O(1) swap strategy
+                let order = queue.order;
+                let old_queue = std::mem::replace(&mut *queue, Shard::new(order));
+                drop(queue); // Release lock immediately
+                old_queue.records.into_values().collect()
+            } else {
+                // Time-based batching only looks at records whose deadline
+                // has passed
+                let mut flushed = Vec::new();
+                while let Some(Reverse((deadline, _))) = queue.expiry.peek() {
+                    if *deadline > now {
+                        break;
+                    }
+                    let Reverse((_, key)) = queue.expiry.pop().unwrap();
+                    if queue.is_stale(&key) {
+                        continue;
+                    }
+                    let deadline = match queue.records.get(&key) {
+                        Some(record) => policy.deadline(record),
+                        None => continue,
+                    };
+                    if deadline <= now {
+                        flushed.extend(queue.remove(&key));
+                    } else {
+                        queue.expiry.push(Reverse((deadline, key)));
+                    }
+                }
+                drop(queue);
+                flushed
+            };
 
             // Process outside the lock
-            for (_, record) in old_queue {
-                total_metrics_count += record.payload.metrics.len() as i64;
+            for record in flushed {
+                total_metrics_count += record.payload.metrics.len();
+                total_bytes += record.bytes;
                 batch.push(record.payload);
             }
-        } else {
-            // Standard iteration for time-based batching
-            let mut queue = self.queue.lock().unwrap();
-            let mut keys_to_remove = Vec::new();
-
-            for (key, record) in queue.iter() {
-                if now > record.updated_at + self.delta as i64 {
-                    batch.push(record.payload.clone());
-                    keys_to_remove.push(key.clone());
-                    total_metrics_count += record.payload.metrics.len() as i64;
-                }
-            }
-
-            for key in keys_to_remove {
-                queue.remove(&key);
-            }
         }
+        self.queued_metrics
+            .fetch_sub(total_metrics_count, Ordering::Relaxed);
+        self.queued_bytes.fetch_sub(total_bytes, Ordering::Relaxed);
+        let total_metrics_count = total_metrics_count as i64;
 
         if !batch.is_empty() {
             self.batch_this_sec.fetch_add(1, Ordering::Relaxed);
@@ -155,7 +563,30 @@ impl EventBatchQueue {
         self.total_processed_events.load(Ordering::Relaxed)
     }
 
+    pub fn get_total_evicted(&self) -> i64 {
+        self.total_evicted.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_summarized(&self) -> i64 {
+        self.total_summarized.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_dropped_metrics(&self) -> i64 {
+        self.total_dropped_metrics.load(Ordering::Relaxed)
+    }
+
     pub fn get_queue_size(&self) -> usize {
-        self.queue.lock().unwrap().len()
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().records.len())
+            .sum()
+    }
+}
+
+fn adjust(total: &AtomicUsize, before: usize, after: usize) {
+    if after >= before {
+        total.fetch_add(after - before, Ordering::Relaxed);
+    } else {
+        total.fetch_sub(before - after, Ordering::Relaxed);
     }
 }
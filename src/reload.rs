@@ -0,0 +1,125 @@
+use log::{error, info};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+pub type LoadResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+type Loader<T> = Box<dyn Fn() -> LoadResult<T> + Send + Sync>;
+
+/// A value loaded from files or directories on disk that is swapped in place
+/// when any of the watched paths change.
+///
+/// Readers take a cheap `Arc` snapshot with `get()`; the reloader thread in
+/// `main` polls `reload_if_changed()`. A failed reload keeps the previous value.
+pub struct Reloadable<T> {
+    name: &'static str,
+    paths: Vec<PathBuf>,
+    loader: Loader<T>,
+    current: RwLock<Arc<T>>,
+    fingerprint: Mutex<Vec<(PathBuf, SystemTime, u64)>>,
+    // Metrics
+    total_reloads: AtomicI64,
+    total_reload_errors: AtomicI64,
+}
+
+impl<T> Reloadable<T> {
+    pub fn load<F>(name: &'static str, paths: Vec<PathBuf>, loader: F) -> LoadResult<Self>
+    where
+        F: Fn() -> LoadResult<T> + Send + Sync + 'static,
+    {
+        let fingerprint = fingerprint(&paths)?;
+        let value = loader()?;
+        info!("Loaded {} from {:?}", name, paths);
+
+        Ok(Self {
+            name,
+            paths,
+            loader: Box::new(loader),
+            current: RwLock::new(Arc::new(value)),
+            fingerprint: Mutex::new(fingerprint),
+            total_reloads: AtomicI64::new(0),
+            total_reload_errors: AtomicI64::new(0),
+        })
+    }
+
+    pub fn get(&self) -> Arc<T> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Whether any file is watched for changes.
+    pub fn is_watching(&self) -> bool {
+        !self.paths.is_empty()
+    }
+
+    /// Reloads the value if any watched file was added, removed or modified
+    /// since the last load attempt. Returns true on reload.
+    pub fn reload_if_changed(&self) -> bool {
+        let mut last = self.fingerprint.lock().unwrap();
+        let current = match fingerprint(&self.paths) {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Failed to stat {} at {:?}: {}", self.name, self.paths, e);
+                self.total_reload_errors.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+        };
+        if current == *last {
+            return false;
+        }
+
+        match (self.loader)() {
+            Ok(value) => {
+                *self.current.write().unwrap() = Arc::new(value);
+                *last = current;
+                self.total_reloads.fetch_add(1, Ordering::Relaxed);
+                info!("Reloaded {} from {:?}", self.name, self.paths);
+                true
+            }
+            Err(e) => {
+                // Remember the fingerprint so a broken file is reported once,
+                // not on every poll.
+                *last = current;
+                self.total_reload_errors.fetch_add(1, Ordering::Relaxed);
+                error!(
+                    "Failed to reload {} from {:?}: {}. Keeping previous version",
+                    self.name, self.paths, e
+                );
+                false
+            }
+        }
+    }
+
+    pub fn get_total_reloads(&self) -> i64 {
+        self.total_reloads.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_reload_errors(&self) -> i64 {
+        self.total_reload_errors.load(Ordering::Relaxed)
+    }
+}
+
+/// Lists (path, mtime, len) for every watched file and for every file directly
+/// inside a watched directory.
+fn fingerprint(paths: &[PathBuf]) -> std::io::Result<Vec<(PathBuf, SystemTime, u64)>> {
+    let mut entries = Vec::new();
+    for path in paths {
+        let metadata = fs::metadata(path)?;
+        if metadata.is_dir() {
+            let start = entries.len();
+            for entry in fs::read_dir(path)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                if metadata.is_file() {
+                    entries.push((entry.path(), metadata.modified()?, metadata.len()));
+                }
+            }
+            entries[start..].sort();
+        } else {
+            entries.push((path.clone(), metadata.modified()?, metadata.len()));
+        }
+    }
+    Ok(entries)
+}
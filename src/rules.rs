@@ -0,0 +1,219 @@
+use crate::pb::{RuleReference, SensorEvent};
+use log::warn;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default)]
+pub struct Rule {
+    pub gid: i64,
+    pub sid: i64,
+    pub rev: i64,
+    pub text: String,
+    pub references: Vec<RuleReference>,
+    pub flowbits: Vec<String>,
+    pub classtype: Option<String>,
+    // `metadata:` key/value pairs, e.g. ("mitre_technique_id", "T1071").
+    pub metadata: Vec<(String, String)>,
+}
+
+/// The ruleset Suricata is running, indexed by (gid, sid, rev).
+#[derive(Debug, Default)]
+pub struct RuleSet {
+    rules: HashMap<(i64, i64, i64), Rule>,
+    // SHA-256 over the names and contents of all loaded rule files.
+    version: String,
+}
+
+impl RuleSet {
+    /// Loads every `*.rules` file in `rules_path` (or `rules_path` itself if it
+    /// is a file). `reference_config` is Suricata's `reference.config`, used to
+    /// turn `reference:` entries into URLs.
+    pub fn load(
+        rules_path: &Path,
+        reference_config: Option<&Path>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let reference_urls = match reference_config {
+            Some(path) => parse_reference_config(&fs::read_to_string(path)?),
+            None => HashMap::new(),
+        };
+
+        let mut files: Vec<PathBuf> = if rules_path.is_dir() {
+            fs::read_dir(rules_path)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "rules"))
+                .collect()
+        } else {
+            vec![rules_path.to_path_buf()]
+        };
+        files.sort();
+
+        let mut hasher = Sha256::new();
+        let mut rules = HashMap::new();
+        for file in &files {
+            let content = fs::read_to_string(file)?;
+            hasher.update(file.file_name().unwrap_or_default().as_encoded_bytes());
+            hasher.update(content.as_bytes());
+
+            for line in join_continued_lines(&content) {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                match parse_rule(line, &reference_urls) {
+                    Some(rule) => {
+                        rules.insert((rule.gid, rule.sid, rule.rev), rule);
+                    }
+                    None => warn!("Skipping unparsable rule in {}: {}", file.display(), line),
+                }
+            }
+        }
+
+        Ok(Self {
+            rules,
+            version: hex::encode(hasher.finalize()),
+        })
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn rule(&self, gid: i64, sid: i64, rev: i64) -> Option<&Rule> {
+        self.rules.get(&(gid, sid, rev))
+    }
+
+    /// Attaches the rule text, references and flowbits of the rule that fired.
+    /// Events whose gid:sid:rev is not in the ruleset are left untouched.
+    pub fn enrich(&self, event: &mut SensorEvent) {
+        let key = (
+            event.snort_rule_gid,
+            event.snort_rule_sid,
+            event.snort_rule_rev,
+        );
+        if let Some(rule) = self.rules.get(&key) {
+            event.snort_rule_text = Some(rule.text.clone());
+            event.snort_rule_references = rule.references.clone();
+            event.snort_rule_flowbits = rule.flowbits.clone();
+            event.snort_ruleset_version = Some(self.version.clone());
+        }
+    }
+}
+
+/// Joins lines ending in a backslash with the following line, as Suricata does.
+fn join_continued_lines(content: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for line in content.lines() {
+        match line.trim_end().strip_suffix('\\') {
+            Some(head) => current.push_str(head),
+            None => {
+                current.push_str(line);
+                lines.push(std::mem::take(&mut current));
+            }
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Parses `config reference: <system> <url prefix>` lines.
+fn parse_reference_config(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("config reference:"))
+        .filter_map(|rest| {
+            let mut parts = rest.split_whitespace();
+            Some((parts.next()?.to_lowercase(), parts.next()?.to_string()))
+        })
+        .collect()
+}
+
+fn parse_rule(line: &str, reference_urls: &HashMap<String, String>) -> Option<Rule> {
+    let start = line.find('(')?;
+    let end = line.rfind(')')?;
+    if end <= start {
+        return None;
+    }
+
+    let mut rule = Rule {
+        gid: 1,
+        text: line.to_string(),
+        ..Default::default()
+    };
+    let mut sid = None;
+
+    for option in split_options(&line[start + 1..end]) {
+        let (key, value) = match option.split_once(':') {
+            Some((k, v)) => (k.trim(), v.trim()),
+            None => (option.trim(), ""),
+        };
+        match key {
+            "sid" => sid = value.parse().ok(),
+            "gid" => rule.gid = value.parse().ok()?,
+            "rev" => rule.rev = value.parse().ok()?,
+            "flowbits" => rule.flowbits.push(value.to_string()),
+            "classtype" => rule.classtype = Some(value.to_string()),
+            "metadata" => {
+                for entry in value.split(',') {
+                    if let Some((k, v)) = entry.trim().split_once(char::is_whitespace) {
+                        rule.metadata.push((k.to_string(), v.trim().to_string()));
+                    }
+                }
+            }
+            "reference" => {
+                if let Some((system, id)) = value.split_once(',') {
+                    let system = system.trim().to_lowercase();
+                    let id = id.trim().to_string();
+                    let url = reference_urls
+                        .get(&system)
+                        .map(|prefix| format!("{}{}", prefix, id));
+                    rule.references.push(RuleReference { system, id, url });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    rule.sid = sid?;
+    Some(rule)
+}
+
+/// Splits the option section of a rule on `;`, honoring quoted strings and
+/// backslash escapes.
+fn split_options(body: &str) -> Vec<&str> {
+    let mut options = Vec::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in body.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                let option = body[start..i].trim();
+                if !option.is_empty() {
+                    options.push(option);
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let tail = body[start..].trim();
+    if !tail.is_empty() {
+        options.push(tail);
+    }
+    options
+}
@@ -0,0 +1,243 @@
+use crate::pb::{Metric, SensorEvent};
+use crate::types::SuricataAlert;
+use dashmap::DashMap;
+use log::{debug, warn};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::serde::{from_dynamic, to_dynamic};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use std::cell::Cell;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::time::{Duration, Instant};
+
+// Operation budget per script run, on top of the wall-clock timeout.
+const MAX_OPERATIONS: u64 = 1_000_000;
+// Operations between checks of the wall-clock timeout, as reading the clock
+// on every operation would dominate short scripts.
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+thread_local! {
+    // Deadline of the script running on this thread, checked by the
+    // engine's progress callback.
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+struct Script {
+    name: String,
+    ast: AST,
+    // Only the first failure of a script is logged.
+    failure_logged: AtomicBool,
+}
+
+/// Rhai scripts run on each alert after enrichment.
+///
+/// Scripts see the parsed record as the constant `alert` and can modify the
+/// outgoing `event` and `metric` object maps, whose keys are the protobuf
+/// field names. A script that evaluates to `false` drops the event. Scripts
+/// run in file name order; a script that fails or times out leaves the event
+/// as it was before it ran.
+///
+/// Scripts are sandboxed: `import` and `eval` are unavailable, and runs are
+/// bounded in time, operations, call depth and data sizes.
+pub struct ScriptSet {
+    engine: Engine,
+    scripts: Vec<Script>,
+    timeout: Duration,
+}
+
+impl ScriptSet {
+    /// `path` is a `.rhai` file or a directory of them.
+    pub fn load(
+        path: &Path,
+        timeout: Duration,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut files = if path.is_dir() {
+            fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|e| e == "rhai"))
+                .collect()
+        } else {
+            vec![path.to_path_buf()]
+        };
+        files.sort();
+
+        let engine = sandboxed_engine();
+        let mut scripts = Vec::new();
+        for file in files {
+            let source = fs::read_to_string(&file)?;
+            let ast = engine
+                .compile(&source)
+                .map_err(|e| format!("{}: {}", file.display(), e))?;
+            scripts.push(Script {
+                name: file
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                ast,
+                failure_logged: AtomicBool::new(false),
+            });
+        }
+
+        Ok(Self {
+            engine,
+            scripts,
+            timeout,
+        })
+    }
+
+    pub fn script_count(&self) -> usize {
+        self.scripts.len()
+    }
+
+    fn run_script(
+        &self,
+        script: &Script,
+        alert: &Dynamic,
+        event: &mut SensorEvent,
+        metric: &mut Metric,
+    ) -> Result<bool, Box<EvalAltResult>> {
+        let mut scope = Scope::new();
+        scope.push_constant_dynamic("alert", alert.clone());
+        scope.push_dynamic("event", to_dynamic(&*event)?);
+        scope.push_dynamic("metric", to_dynamic(&*metric)?);
+
+        DEADLINE.with(|d| d.set(Some(Instant::now() + self.timeout)));
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &script.ast);
+        DEADLINE.with(|d| d.set(None));
+
+        if result?.as_bool() == Ok(false) {
+            return Ok(false);
+        }
+        // Convert both before assigning so a bad field leaves the event as is.
+        let new_event = from_dynamic(&scope.get_value::<Dynamic>("event").unwrap_or_default())?;
+        let new_metric = from_dynamic(&scope.get_value::<Dynamic>("metric").unwrap_or_default())?;
+        *event = new_event;
+        *metric = new_metric;
+        Ok(true)
+    }
+}
+
+/// Per-script counters of a `ScriptSet`.
+///
+/// Counters are keyed by script name and kept apart from the scripts so that
+/// they survive a reload.
+#[derive(Default)]
+pub struct ScriptCounters {
+    scripts: DashMap<String, RunCounters>,
+}
+
+#[derive(Default)]
+struct RunCounters {
+    runs: AtomicI64,
+    drops: AtomicI64,
+    errors: AtomicI64,
+    timeouts: AtomicI64,
+}
+
+impl ScriptCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the scripts on the event. Returns false if a script dropped it.
+    pub fn run(
+        &self,
+        scripts: &ScriptSet,
+        data: &SuricataAlert,
+        event: &mut SensorEvent,
+        metric: &mut Metric,
+    ) -> bool {
+        if scripts.scripts.is_empty() {
+            return true;
+        }
+        let alert = match to_dynamic(data) {
+            Ok(alert) => alert,
+            Err(e) => {
+                debug!("Cannot pass alert to scripts: {}", e);
+                return true;
+            }
+        };
+
+        for script in &scripts.scripts {
+            let result = scripts.run_script(script, &alert, event, metric);
+            self.record(&script.name, &result);
+            match result {
+                Ok(true) => {}
+                Ok(false) => return false,
+                Err(e) => {
+                    if !script.failure_logged.swap(true, Ordering::Relaxed) {
+                        warn!("Script {} failed: {}", script.name, e);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    fn record(&self, name: &str, result: &Result<bool, Box<EvalAltResult>>) {
+        let count = |counters: &RunCounters| {
+            counters.runs.fetch_add(1, Ordering::Relaxed);
+            let counter = match result {
+                Ok(true) => return,
+                Ok(false) => &counters.drops,
+                // Running out of operations counts as a timeout too.
+                Err(e) => match **e {
+                    EvalAltResult::ErrorTerminated(..)
+                    | EvalAltResult::ErrorTooManyOperations(..) => &counters.timeouts,
+                    _ => &counters.errors,
+                },
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+        };
+        match self.scripts.get(name) {
+            Some(counters) => count(&counters),
+            None => count(&self.scripts.entry(name.to_string()).or_default()),
+        }
+    }
+
+    /// Per-script (name, runs, drops, errors, timeouts) counters of the
+    /// scripts of the set, in run order.
+    pub fn get_script_counters(&self, scripts: &ScriptSet) -> Vec<(String, i64, i64, i64, i64)> {
+        scripts
+            .scripts
+            .iter()
+            .map(|script| match self.scripts.get(&script.name) {
+                Some(counters) => (
+                    script.name.clone(),
+                    counters.runs.load(Ordering::Relaxed),
+                    counters.drops.load(Ordering::Relaxed),
+                    counters.errors.load(Ordering::Relaxed),
+                    counters.timeouts.load(Ordering::Relaxed),
+                ),
+                None => (script.name.clone(), 0, 0, 0, 0),
+            })
+            .collect()
+    }
+}
+
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(64 * 1024);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(10_000);
+    engine.on_print(|s| debug!("script: {}", s));
+    engine.on_debug(|s, _, _| debug!("script: {}", s));
+    engine.on_progress(|operations| {
+        if operations % DEADLINE_CHECK_INTERVAL != 0 {
+            return None;
+        }
+        let expired = DEADLINE
+            .with(|d| d.get())
+            .is_some_and(|d| Instant::now() > d);
+        expired.then(|| Dynamic::from("timeout"))
+    });
+    engine
+}
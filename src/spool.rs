@@ -0,0 +1,422 @@
+use crate::pb::SensorEvent;
+use log::warn;
+use prost::Message;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SEGMENT_EXTENSION: &str = "seg";
+const CURSOR_FILE: &str = "cursor";
+// Record header: payload length (u32), CRC-32 of the write time and payload
+// (u32), write time in Unix seconds (u64), all little-endian.
+const HEADER_BYTES: usize = 16;
+const MAX_SEGMENT_BYTES: u64 = 16 * 1024 * 1024;
+
+struct Segment {
+    seq: u64,
+    bytes: u64,
+    // Records not read yet
+    records: usize,
+    // Write time of the newest record
+    newest: u64,
+}
+
+struct Reader {
+    seq: u64,
+    file: File,
+    offset: u64,
+}
+
+#[derive(Default)]
+struct State {
+    // Oldest first. While `writer` is open, the last one is written to.
+    segments: VecDeque<Segment>,
+    writer: Option<File>,
+    reader: Option<Reader>,
+    // Numbers keep growing so that the cursor never points past new segments
+    next_seq: u64,
+    // Read position after each batch read but not yet delivered, oldest first
+    in_flight: VecDeque<(u64, u64)>,
+}
+
+/// Disk spool that batches fall back to while the server cannot take them.
+///
+/// Batches are appended to numbered segment files in `dir` as CRC-checked
+/// records and read back in order. The read position survives restarts; it
+/// only moves past a batch once that was delivered, so batches on their way
+/// out at a restart are read again.
+/// When the spool outgrows `max_bytes` its oldest segment is deleted, and
+/// records older than `max_age` are dropped when they are read. Corrupt
+/// records end their segment.
+pub struct Spool {
+    dir: PathBuf,
+    max_bytes: u64,
+    max_age: Duration,
+    segment_bytes: u64,
+    state: Mutex<State>,
+    // Metrics
+    total_spooled: AtomicI64,
+    total_drained: AtomicI64,
+    total_dropped: AtomicI64,
+    total_corrupt: AtomicI64,
+}
+
+impl Spool {
+    pub fn open(
+        dir: &Path,
+        max_bytes: u64,
+        max_age: Duration,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        fs::create_dir_all(dir)?;
+        let (cursor_seq, cursor_offset) = read_cursor(dir);
+
+        let mut seqs: Vec<u64> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == SEGMENT_EXTENSION))
+            .filter_map(|p| p.file_stem()?.to_str()?.parse().ok())
+            .collect();
+        seqs.sort_unstable();
+
+        let spool = Self {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            max_age,
+            segment_bytes: (max_bytes / 8).clamp(1, MAX_SEGMENT_BYTES),
+            state: Mutex::new(State::default()),
+            total_spooled: AtomicI64::new(0),
+            total_drained: AtomicI64::new(0),
+            total_dropped: AtomicI64::new(0),
+            total_corrupt: AtomicI64::new(0),
+        };
+
+        let mut state = State {
+            next_seq: seqs.last().copied().unwrap_or(0).max(cursor_seq) + 1,
+            ..Default::default()
+        };
+        for seq in seqs {
+            let path = spool.segment_path(seq);
+            if seq < cursor_seq {
+                // Read before the last shutdown
+                fs::remove_file(&path)?;
+                continue;
+            }
+            let start = if seq == cursor_seq { cursor_offset } else { 0 };
+            let mut segment = spool.scan(seq, start)?;
+            if segment.records == 0 {
+                fs::remove_file(&path)?;
+                continue;
+            }
+            if start > 0 {
+                state.reader = Some(Reader {
+                    seq,
+                    file: File::open(&path)?,
+                    offset: start,
+                });
+            }
+            segment.bytes = fs::metadata(&path)?.len();
+            state.segments.push_back(segment);
+        }
+        *spool.state.lock().unwrap() = state;
+        Ok(spool)
+    }
+
+    /// Counts the valid records of a segment from `start`, truncating it at
+    /// the first invalid one, e.g. a record cut short by a crash.
+    fn scan(&self, seq: u64, start: u64) -> io::Result<Segment> {
+        let path = self.segment_path(seq);
+        let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut segment = Segment {
+            seq,
+            bytes: 0,
+            records: 0,
+            newest: 0,
+        };
+        let mut offset = start;
+        while let Some((written_at, payload)) = read_record(&mut file)? {
+            segment.records += 1;
+            segment.newest = written_at;
+            offset += (HEADER_BYTES + payload.len()) as u64;
+        }
+        if offset < file.metadata()?.len() {
+            warn!(
+                "Spool segment {} is corrupt after {} bytes",
+                path.display(),
+                offset
+            );
+            self.total_corrupt.fetch_add(1, Ordering::Relaxed);
+            file.set_len(offset)?;
+        }
+        Ok(segment)
+    }
+
+    /// Appends a batch to the spool.
+    pub fn push(&self, batch: &[SensorEvent]) -> io::Result<()> {
+        let mut payload = Vec::new();
+        for event in batch {
+            event
+                .encode_length_delimited(&mut payload)
+                .map_err(io::Error::other)?;
+        }
+        let written_at = unix_now();
+        let mut record = Vec::with_capacity(HEADER_BYTES + payload.len());
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&checksum(written_at, &payload).to_le_bytes());
+        record.extend_from_slice(&written_at.to_le_bytes());
+        record.extend_from_slice(&payload);
+
+        let mut state = self.state.lock().unwrap();
+        let full = state
+            .segments
+            .back()
+            .is_some_and(|s| s.bytes >= self.segment_bytes);
+        if state.writer.is_none() || full {
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            let file = OpenOptions::new()
+                .create_new(true)
+                .append(true)
+                .open(self.segment_path(seq))?;
+            state.writer = Some(file);
+            state.segments.push_back(Segment {
+                seq,
+                bytes: 0,
+                records: 0,
+                newest: 0,
+            });
+        }
+
+        let writer = state.writer.as_mut().unwrap();
+        writer.write_all(&record)?;
+        writer.sync_data()?;
+        let segment = state.segments.back_mut().unwrap();
+        segment.bytes += record.len() as u64;
+        segment.records += 1;
+        segment.newest = written_at;
+        self.total_spooled.fetch_add(1, Ordering::Relaxed);
+
+        // Make room by deleting the oldest segments
+        while state.segments.len() > 1
+            && state.segments.iter().map(|s| s.bytes).sum::<u64>() > self.max_bytes
+        {
+            self.drop_front(&mut state)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the oldest unread batch. Call `delivered` once it has been
+    /// delivered; batches are delivered in the order they were read.
+    pub fn pop(&self) -> io::Result<Option<Vec<SensorEvent>>> {
+        let mut state = self.state.lock().unwrap();
+        let oldest_allowed = unix_now().saturating_sub(self.max_age.as_secs());
+        loop {
+            // Segments before it are read, but may still have batches in flight
+            let index = match state.segments.iter().position(|s| s.records > 0) {
+                Some(index) => index,
+                None => return Ok(None),
+            };
+            let (seq, records, newest) = {
+                let segment = &state.segments[index];
+                (segment.seq, segment.records, segment.newest)
+            };
+            if newest < oldest_allowed && !self.max_age.is_zero() {
+                // Everything left in the segment is too old
+                self.total_dropped
+                    .fetch_add(records as i64, Ordering::Relaxed);
+                state.segments[index].records = 0;
+                self.remove_read(&mut state)?;
+                continue;
+            }
+
+            if state.reader.as_ref().map(|r| r.seq) != Some(seq) {
+                state.reader = Some(Reader {
+                    seq,
+                    file: File::open(self.segment_path(seq))?,
+                    offset: 0,
+                });
+            }
+            let reader = state.reader.as_mut().unwrap();
+            reader.file.seek(SeekFrom::Start(reader.offset))?;
+            let record = read_record(&mut reader.file)?;
+            let batch = record
+                .as_ref()
+                .and_then(|(_, payload)| decode_batch(payload).ok());
+            let (written_at, batch) = match (record, batch) {
+                (Some((written_at, payload)), Some(batch)) => {
+                    reader.offset += (HEADER_BYTES + payload.len()) as u64;
+                    (written_at, batch)
+                }
+                _ => {
+                    warn!("Spool segment {} has a corrupt record", seq);
+                    self.total_corrupt.fetch_add(1, Ordering::Relaxed);
+                    self.total_dropped
+                        .fetch_add(records as i64, Ordering::Relaxed);
+                    state.segments[index].records = 0;
+                    if index + 1 == state.segments.len() {
+                        // Append to a new segment, after the corruption
+                        state.writer = None;
+                    }
+                    self.remove_read(&mut state)?;
+                    continue;
+                }
+            };
+            let offset = reader.offset;
+            state.segments[index].records -= 1;
+
+            if written_at < oldest_allowed && !self.max_age.is_zero() {
+                self.total_dropped.fetch_add(1, Ordering::Relaxed);
+                if state.in_flight.is_empty() {
+                    write_cursor(&self.dir, seq, offset)?;
+                    self.remove_read(&mut state)?;
+                }
+                continue;
+            }
+            state.in_flight.push_back((seq, offset));
+            self.total_drained.fetch_add(1, Ordering::Relaxed);
+            return Ok(Some(batch));
+        }
+    }
+
+    /// Marks the oldest batch from `pop` that was not delivered yet as
+    /// delivered, moving the read position kept across restarts past it.
+    pub fn delivered(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some((seq, offset)) = state.in_flight.pop_front() {
+            write_cursor(&self.dir, seq, offset)?;
+            self.remove_read(&mut state)?;
+        }
+        Ok(())
+    }
+
+    /// True if no batch is spooled or on its way out, so new batches can
+    /// bypass the spool without overtaking older ones.
+    pub fn is_idle(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.in_flight.is_empty() && state.segments.iter().all(|s| s.records == 0)
+    }
+
+    /// Deletes the oldest segments once they are read, no batch of theirs is
+    /// in flight and they are no longer written to.
+    fn remove_read(&self, state: &mut State) -> io::Result<()> {
+        while let Some(front) = state.segments.front() {
+            let is_writing = state.writer.is_some() && state.segments.len() == 1;
+            let in_flight = state.in_flight.iter().any(|(seq, _)| *seq == front.seq);
+            if front.records > 0 || is_writing || in_flight {
+                break;
+            }
+            self.drop_front(state)?;
+        }
+        Ok(())
+    }
+
+    fn drop_front(&self, state: &mut State) -> io::Result<()> {
+        let segment = match state.segments.pop_front() {
+            Some(segment) => segment,
+            None => return Ok(()),
+        };
+        if state.segments.is_empty() {
+            state.writer = None;
+        }
+        if state.reader.as_ref().is_some_and(|r| r.seq == segment.seq) {
+            state.reader = None;
+        }
+        self.total_dropped
+            .fetch_add(segment.records as i64, Ordering::Relaxed);
+        fs::remove_file(self.segment_path(segment.seq))
+    }
+
+    fn segment_path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("{:020}.{}", seq, SEGMENT_EXTENSION))
+    }
+
+    pub fn get_spooled_batches(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.segments.iter().map(|s| s.records).sum()
+    }
+
+    pub fn get_spooled_bytes(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.segments.iter().map(|s| s.bytes).sum()
+    }
+
+    pub fn get_total_spooled(&self) -> i64 {
+        self.total_spooled.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_drained(&self) -> i64 {
+        self.total_drained.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_dropped(&self) -> i64 {
+        self.total_dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_corrupt(&self) -> i64 {
+        self.total_corrupt.load(Ordering::Relaxed)
+    }
+}
+
+/// Reads the next record. Returns `None` at the end of the segment or at a
+/// truncated or corrupt record.
+fn read_record(file: &mut File) -> io::Result<Option<(u64, Vec<u8>)>> {
+    let mut header = [0u8; HEADER_BYTES];
+    match file.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+    let crc = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    let written_at = u64::from_le_bytes(header[8..16].try_into().unwrap());
+
+    let mut payload = Vec::new();
+    file.take(len as u64).read_to_end(&mut payload)?;
+    if payload.len() != len || checksum(written_at, &payload) != crc {
+        return Ok(None);
+    }
+    Ok(Some((written_at, payload)))
+}
+
+fn decode_batch(mut payload: &[u8]) -> Result<Vec<SensorEvent>, prost::DecodeError> {
+    let mut batch = Vec::new();
+    while !payload.is_empty() {
+        batch.push(SensorEvent::decode_length_delimited(&mut payload)?);
+    }
+    Ok(batch)
+}
+
+fn checksum(written_at: u64, payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&written_at.to_le_bytes());
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+/// The segment and offset reading continues from, `(0, 0)` if unknown.
+fn read_cursor(dir: &Path) -> (u64, u64) {
+    fs::read_to_string(dir.join(CURSOR_FILE))
+        .ok()
+        .and_then(|s| {
+            let (seq, offset) = s.trim().split_once(' ')?;
+            Some((seq.parse().ok()?, offset.parse().ok()?))
+        })
+        .unwrap_or((0, 0))
+}
+
+fn write_cursor(dir: &Path, seq: u64, offset: u64) -> io::Result<()> {
+    // Written to a temporary file first so a crash never leaves half a cursor
+    let tmp = dir.join(format!("{}.tmp", CURSOR_FILE));
+    fs::write(&tmp, format!("{} {}\n", seq, offset))?;
+    fs::rename(tmp, dir.join(CURSOR_FILE))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
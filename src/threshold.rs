@@ -0,0 +1,244 @@
+use crate::types::SuricataAlert;
+use dashmap::DashMap;
+use log::warn;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ThresholdType {
+    // Forward the first `count` alerts per window.
+    Limit,
+    // Forward every `count`th alert within a window.
+    Threshold,
+    // Forward once per window, after `count` alerts.
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Track {
+    Src,
+    Dst,
+    Both,
+    Rule,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ThresholdRule {
+    kind: ThresholdType,
+    track: Track,
+    count: u64,
+    window: Duration,
+}
+
+#[derive(Debug)]
+struct TrackerState {
+    window_start: Instant,
+    last_seen: Instant,
+    count: u64,
+    suppressed: i64,
+}
+
+/// Entries of a Suricata `threshold.config`-style file.
+///
+/// Supports `threshold` and `event_filter` entries; `gen_id 0, sig_id 0`
+/// applies to every signature without a more specific entry.
+pub struct ThresholdRules {
+    rules: HashMap<(i64, i64), ThresholdRule>,
+    // Tracker state idle for longer than this is dropped by `expire()`.
+    idle_timeout: Duration,
+}
+
+impl ThresholdRules {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let content = fs::read_to_string(path)?;
+        let mut rules = HashMap::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_line(line) {
+                // Would never forward an alert once the count is reached
+                Some((_, rule)) if rule.window.is_zero() => {
+                    return Err(format!("threshold entry without a time window: {}", line).into());
+                }
+                Some((key, rule)) => {
+                    rules.insert(key, rule);
+                }
+                None => warn!("Skipping unsupported threshold entry: {}", line),
+            }
+        }
+
+        let longest_window = rules.values().map(|r| r.window).max().unwrap_or_default();
+
+        Ok(Self {
+            rules,
+            idle_timeout: (longest_window * 2).max(Duration::from_secs(60)),
+        })
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+}
+
+/// Rate limiting of alerts by `ThresholdRules`.
+///
+/// Alerts that are held back are counted and the count is reported on the
+/// next alert forwarded for the same signature and tracked address. The
+/// tracker state is kept apart from the rules so that it survives a reload.
+#[derive(Default)]
+pub struct RateLimiter {
+    state: DashMap<(i64, i64, String), TrackerState>,
+    total_suppressed: AtomicI64,
+    // Suppressed alerts of expired tracker state, never reported on an alert
+    total_expired_suppressed: AtomicI64,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records one occurrence of the alert. Returns `None` if it should be
+    /// suppressed, otherwise the number of alerts suppressed for the same
+    /// signature and tracked address since the last one forwarded.
+    pub fn check(&self, rules: &ThresholdRules, data: &SuricataAlert) -> Option<i64> {
+        let alert = match data.alert.as_ref() {
+            Some(alert) => alert,
+            None => return Some(0),
+        };
+        let rule = match rules
+            .rules
+            .get(&(alert.gid, alert.signature_id))
+            .or_else(|| rules.rules.get(&(alert.gid, 0)))
+            .or_else(|| rules.rules.get(&(0, 0)))
+        {
+            Some(rule) => rule,
+            None => return Some(0),
+        };
+
+        let src = data.src_ip.as_deref().unwrap_or_default();
+        let dst = data.dest_ip.as_deref().unwrap_or_default();
+        let tracked = match rule.track {
+            Track::Src => src.to_string(),
+            Track::Dst => dst.to_string(),
+            Track::Both => format!("{}|{}", src, dst),
+            Track::Rule => String::new(),
+        };
+
+        let now = Instant::now();
+        let mut entry = self
+            .state
+            .entry((alert.gid, alert.signature_id, tracked))
+            .or_insert_with(|| TrackerState {
+                window_start: now,
+                last_seen: now,
+                count: 0,
+                suppressed: 0,
+            });
+
+        if now.duration_since(entry.window_start) >= rule.window {
+            entry.window_start = now;
+            entry.count = 0;
+        }
+        entry.last_seen = now;
+        entry.count += 1;
+
+        let forward = match rule.kind {
+            ThresholdType::Limit => entry.count <= rule.count,
+            ThresholdType::Threshold => {
+                if entry.count >= rule.count {
+                    entry.count = 0;
+                    entry.window_start = now;
+                    true
+                } else {
+                    false
+                }
+            }
+            ThresholdType::Both => entry.count == rule.count,
+        };
+
+        if forward {
+            Some(std::mem::take(&mut entry.suppressed))
+        } else {
+            entry.suppressed += 1;
+            self.total_suppressed.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+
+    /// Drops tracker state for keys idle at `now` for more than two of the
+    /// longest windows. Returns the suppressed alerts of dropped keys that
+    /// were never reported, which are also counted in the metrics.
+    pub fn expire(&self, rules: &ThresholdRules, now: Instant) -> i64 {
+        let mut pending = 0;
+        self.state.retain(|_, entry| {
+            let keep = now.saturating_duration_since(entry.last_seen) < rules.idle_timeout;
+            if !keep {
+                pending += entry.suppressed;
+            }
+            keep
+        });
+        self.total_expired_suppressed
+            .fetch_add(pending, Ordering::Relaxed);
+        pending
+    }
+
+    pub fn get_total_suppressed(&self) -> i64 {
+        self.total_suppressed.load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_expired_suppressed(&self) -> i64 {
+        self.total_expired_suppressed.load(Ordering::Relaxed)
+    }
+
+    pub fn get_tracked_count(&self) -> usize {
+        self.state.len()
+    }
+}
+
+/// Parses e.g.
+/// `threshold gen_id 1, sig_id 2002087, type threshold, track by_src, count 10, seconds 60`.
+fn parse_line(line: &str) -> Option<((i64, i64), ThresholdRule)> {
+    let rest = line
+        .strip_prefix("threshold")
+        .or_else(|| line.strip_prefix("event_filter"))?;
+
+    let mut options = HashMap::new();
+    for part in rest.split(',') {
+        let (key, value) = part.trim().split_once(char::is_whitespace)?;
+        options.insert(key, value.trim());
+    }
+
+    let kind = match *options.get("type")? {
+        "limit" => ThresholdType::Limit,
+        "threshold" => ThresholdType::Threshold,
+        "both" => ThresholdType::Both,
+        _ => return None,
+    };
+    let track = match *options.get("track")? {
+        "by_src" => Track::Src,
+        "by_dst" => Track::Dst,
+        "by_both" => Track::Both,
+        "by_rule" => Track::Rule,
+        _ => return None,
+    };
+    let count: u64 = options.get("count")?.parse().ok().filter(|c| *c > 0)?;
+    let seconds: u64 = options.get("seconds")?.parse().ok()?;
+    let gid = options.get("gen_id")?.parse().ok()?;
+    let sid = options.get("sig_id")?.parse().ok()?;
+
+    Some((
+        (gid, sid),
+        ThresholdRule {
+            kind,
+            track,
+            count,
+            window: Duration::from_secs(seconds),
+        },
+    ))
+}
@@ -78,11 +78,16 @@ pub struct SuricataAlert {
     pub tx_id: Option<i64>,
     pub alert: Option<Alert>,
     pub http: Option<HTTP>,
+    pub tls: Option<Tls>,
+    pub dns: Option<Dns>,
     pub files: Option<Vec<FileInfo>>,
     #[serde(rename = "app_proto")]
     pub app_proto: Option<String>,
     pub direction: Option<String>,
     pub flow: Option<Flow>,
+    pub tcp: Option<Tcp>,
+    #[serde(rename = "community_id")]
+    pub community_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -104,6 +109,15 @@ pub struct Alert {
     pub category: String,
     pub severity: i64,
     pub metadata: Option<SuricataMetadata>,
+    // Only present when the rule uses the `target:` keyword.
+    pub source: Option<AlertEndpoint>,
+    pub target: Option<AlertEndpoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertEndpoint {
+    pub ip: Option<String>,
+    pub port: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -122,8 +136,17 @@ pub struct SuricataMetadata {
     pub signature_severity: Option<Vec<String>>,
     #[serde(rename = "updated_at")]
     pub updated_at: Option<Vec<String>>,
+    #[serde(rename = "mitre_tactic_id")]
+    pub mitre_tactic_id: Option<Vec<String>>,
+    #[serde(rename = "mitre_tactic_name")]
+    pub mitre_tactic_name: Option<Vec<String>>,
+    #[serde(rename = "mitre_technique_id")]
+    pub mitre_technique_id: Option<Vec<String>>,
+    #[serde(rename = "mitre_technique_name")]
+    pub mitre_technique_name: Option<Vec<String>>,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HTTP {
     pub hostname: Option<String>,
@@ -139,6 +162,43 @@ pub struct HTTP {
     pub length: Option<i64>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tls {
+    pub sni: Option<String>,
+    pub subject: Option<String>,
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dns {
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    // EVE version 1 and 2 (dns records) put the query name at the top level,
+    // version 3 and alert metadata use a list of queries.
+    pub rrname: Option<String>,
+    #[serde(alias = "queries")]
+    pub query: Option<Vec<DnsQuery>>,
+    // Version 1 logs one answer record per event at the top level.
+    pub rrtype: Option<String>,
+    pub rdata: Option<String>,
+    pub ttl: Option<i64>,
+    pub answers: Option<Vec<DnsAnswer>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsAnswer {
+    pub rrname: Option<String>,
+    pub rrtype: Option<String>,
+    pub rdata: Option<String>,
+    pub ttl: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsQuery {
+    pub rrname: Option<String>,
+    pub rrtype: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileInfo {
     pub filename: Option<String>,
@@ -148,6 +208,9 @@ pub struct FileInfo {
     pub size: Option<i64>,
     #[serde(rename = "tx_id")]
     pub tx_id: Option<i64>,
+    pub md5: Option<String>,
+    pub sha1: Option<String>,
+    pub sha256: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -169,4 +232,20 @@ pub struct Flow {
     pub src_port: Option<i64>,
     #[serde(rename = "dest_port")]
     pub dest_port: Option<i64>,
+    // Only in flow records
+    pub end: Option<String>,
+    pub age: Option<i64>,
+    pub state: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tcp {
+    #[serde(rename = "tcp_flags")]
+    pub tcp_flags: Option<String>,
+    #[serde(rename = "tcp_flags_ts")]
+    pub tcp_flags_ts: Option<String>,
+    #[serde(rename = "tcp_flags_tc")]
+    pub tcp_flags_tc: Option<String>,
+    pub state: Option<String>,
 }
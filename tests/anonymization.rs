@@ -0,0 +1,183 @@
+use sensor_suricata_service_rust::anonymize::Anonymizer;
+use sensor_suricata_service_rust::pb::Metric;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+// Key and address pairs from the Crypto-PAn reference implementation's sample.
+const SAMPLE_KEY: [u8; 32] = [
+    21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143, 131, 121,
+    121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+];
+
+fn key_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("{}-{}.key", name, std::process::id()));
+    fs::write(&path, hex::encode(SAMPLE_KEY)).unwrap();
+    path
+}
+
+#[test]
+fn matches_reference_and_preserves_prefixes() {
+    let path = key_file("anonymize");
+    let anonymizer = Anonymizer::load(&path, "[192.0.2.0/24]").expect("should load");
+
+    let mut metric = Metric {
+        snort_src_address: Some("128.11.68.132".to_string()),
+        snort_dst_address: Some("129.118.74.4".to_string()),
+        snort_src_ap: Some("128.11.68.132:51000".to_string()),
+        snort_dst_ap: Some("2001:db8::1:443".to_string()),
+        snort_target: Some("192.0.2.10:80".to_string()),
+        snort_eth_src: Some("00:1b:21:3a:4f:5e".to_string()),
+        ..Default::default()
+    };
+    anonymizer.anonymize(&mut metric);
+
+    assert_eq!(metric.snort_src_address.as_deref(), Some("135.242.180.132"));
+    assert_eq!(metric.snort_dst_address.as_deref(), Some("134.136.186.123"));
+    assert_eq!(
+        metric.snort_src_ap.as_deref(),
+        Some("135.242.180.132:51000")
+    );
+    // Exempt addresses are left unchanged.
+    assert_eq!(metric.snort_target.as_deref(), Some("192.0.2.10:80"));
+
+    let anonymized = metric.snort_eth_src.as_deref().unwrap();
+    assert_ne!(anonymized, "00:1b:21:3a:4f:5e");
+    assert_eq!(
+        anonymizer.deanonymize(anonymized).as_deref(),
+        Some("00:1b:21:3a:4f:5e")
+    );
+    let anonymized = metric.snort_dst_ap.as_deref().unwrap();
+    assert_eq!(
+        anonymizer.deanonymize_ap(anonymized).as_deref(),
+        Some("2001:db8::1:443")
+    );
+    assert_eq!(
+        anonymizer.deanonymize("141.167.8.160").as_deref(),
+        Some("141.223.7.43")
+    );
+
+    // Addresses in the same /120 keep a common /120 prefix.
+    let mut a = Metric {
+        snort_src_address: Some("2001:db8:1:2::10".to_string()),
+        snort_dst_address: Some("2001:db8:1:2::ff".to_string()),
+        ..Default::default()
+    };
+    anonymizer.anonymize(&mut a);
+    let src: std::net::Ipv6Addr = a.snort_src_address.unwrap().parse().unwrap();
+    let dst: std::net::Ipv6Addr = a.snort_dst_address.unwrap().parse().unwrap();
+    assert_eq!(u128::from(src) >> 8, u128::from(dst) >> 8);
+    assert_ne!(src, dst);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn maps_the_address_of_ipv6_address_and_port_fields() {
+    let path = key_file("anonymize-ap");
+    let anonymizer = Anonymizer::load(&path, "").expect("should load");
+
+    let mut metric = Metric {
+        snort_dst_address: Some("2001:db8::1".to_string()),
+        snort_dst_ap: Some("2001:db8::1:443".to_string()),
+        snort_src_address: Some("2001:db8::2".to_string()),
+        snort_src_ap: Some("[2001:db8::2]:51000".to_string()),
+        ..Default::default()
+    };
+    anonymizer.anonymize(&mut metric);
+
+    let address = metric.snort_dst_address.unwrap();
+    assert_ne!(address, "2001:db8::1");
+    assert_eq!(
+        metric.snort_dst_ap.as_deref(),
+        Some(format!("{}:443", address).as_str())
+    );
+    let address = metric.snort_src_address.unwrap();
+    assert_eq!(
+        metric.snort_src_ap.as_deref(),
+        Some(format!("[{}]:51000", address).as_str())
+    );
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn keeps_the_port_of_ipv6_targets_and_restores_them() {
+    let path = key_file("anonymize-target");
+    let anonymizer = Anonymizer::load(&path, "").expect("should load");
+
+    let mut metric = Metric {
+        snort_src_address: Some("2001:db8::1:2".to_string()),
+        snort_dst_ap: Some("2001:db8::1:443".to_string()),
+        snort_target: Some("2001:db8::1:443".to_string()),
+        ..Default::default()
+    };
+    anonymizer.anonymize(&mut metric);
+    let target = metric.snort_target.clone().unwrap();
+    assert!(target.ends_with(":443"));
+    assert_eq!(metric.snort_dst_ap.as_deref(), Some(target.as_str()));
+    assert_eq!(
+        anonymizer.deanonymize_ap(&target).as_deref(),
+        Some("2001:db8::1:443")
+    );
+
+    // The tool reads `*_ap` and `snort_target` values as `ip:port`.
+    let line = format!(
+        r#"{{"snort_src_address":"{}","snort_dst_ap":"{}","snortTarget": "{}"}}"#,
+        metric.snort_src_address.unwrap(),
+        metric.snort_dst_ap.unwrap(),
+        target
+    );
+    let mut child = Command::new(env!("CARGO_BIN_EXE_deanonymize"))
+        .arg("--key-path")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    writeln!(child.stdin.take().unwrap(), "{}", line).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim_end(),
+        r#"{"snort_src_address":"2001:db8::1:2","snort_dst_ap":"2001:db8::1:443","snortTarget": "2001:db8::1:443"}"#
+    );
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn keeps_pseudonyms_out_of_exempt_ranges() {
+    let path = key_file("anonymize-exempt");
+    // 128.11.68.132 would otherwise map to this exempt address.
+    let anonymizer = Anonymizer::load(&path, "[135.242.180.132/32]").expect("should load");
+
+    let mut metric = Metric {
+        snort_src_address: Some("128.11.68.132".to_string()),
+        snort_dst_address: Some("135.242.180.132".to_string()),
+        event_community_id: Some("1:LQU9qZlK+B5F3KDmev6m5PMibrg=".to_string()),
+        event_src_hostnames: vec!["host.example".to_string()],
+        event_dst_hostnames: vec!["peer.example".to_string()],
+        ..Default::default()
+    };
+    anonymizer.anonymize(&mut metric);
+
+    let src = metric.snort_src_address.unwrap();
+    assert_ne!(src, "135.242.180.132");
+    assert_eq!(
+        anonymizer.deanonymize(&src).as_deref(),
+        Some("128.11.68.132")
+    );
+    assert_eq!(metric.snort_dst_address.as_deref(), Some("135.242.180.132"));
+    assert_eq!(
+        anonymizer.deanonymize("135.242.180.132").as_deref(),
+        Some("135.242.180.132")
+    );
+
+    // Both would let a recipient recover the real addresses.
+    assert_eq!(metric.event_community_id, None);
+    assert!(metric.event_src_hostnames.is_empty());
+    assert!(metric.event_dst_hostnames.is_empty());
+
+    fs::remove_file(&path).unwrap();
+}
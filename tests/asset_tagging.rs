@@ -0,0 +1,93 @@
+use sensor_suricata_service_rust::assets::AssetTagger;
+use sensor_suricata_service_rust::pb::Metric;
+use std::fs;
+
+fn metric(src: &str, dst: &str) -> Metric {
+    Metric {
+        snort_src_address: Some(src.to_string()),
+        snort_dst_address: Some(dst.to_string()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn tags_direction_and_most_specific_asset() {
+    let path = std::env::temp_dir().join(format!("assets-{}.csv", std::process::id()));
+    fs::write(
+        &path,
+        concat!(
+            "cidr,hostname,owner,criticality,zone\n",
+            "10.1.0.0/16,office-lan,it,low,office\n",
+            "10.1.2.3,db01,dba-team,critical,dc\n",
+            "fd00::/8,v6-lab,,,\n",
+        ),
+    )
+    .unwrap();
+
+    let tagger = AssetTagger::load("[10.0.0.0/8, fd00::/8]", Some(&path)).expect("should load");
+    assert_eq!(tagger.asset_count(), 3);
+
+    let mut inbound = metric("198.51.100.7", "10.1.2.3");
+    tagger.tag(&mut inbound);
+    assert_eq!(inbound.event_direction.as_deref(), Some("inbound"));
+    assert!(inbound.event_src_asset.is_none());
+    let db = inbound.event_dst_asset.expect("host entry wins");
+    assert_eq!(db.hostname, "db01");
+    assert_eq!(db.criticality.as_deref(), Some("critical"));
+
+    let mut outbound = metric("10.1.9.9", "198.51.100.7");
+    tagger.tag(&mut outbound);
+    assert_eq!(outbound.event_direction.as_deref(), Some("outbound"));
+    assert_eq!(outbound.event_src_asset.unwrap().hostname, "office-lan");
+
+    let mut internal = metric("10.200.0.1", "fd00::1");
+    tagger.tag(&mut internal);
+    assert_eq!(internal.event_direction.as_deref(), Some("internal"));
+    // In HOME_NET but not in the inventory.
+    assert!(internal.event_src_asset.is_none());
+    assert_eq!(internal.event_dst_asset.unwrap().owner, None);
+
+    let mut external = metric("198.51.100.7", "203.0.113.1");
+    tagger.tag(&mut external);
+    assert_eq!(external.event_direction.as_deref(), Some("external"));
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn carves_negated_ranges_out_of_home_net() {
+    let path = std::env::temp_dir().join(format!("assets-quoted-{}.csv", std::process::id()));
+    fs::write(
+        &path,
+        concat!(
+            "# exported from the CMDB\n",
+            "10.0.0.0/8,\"lab, shared\",\"ops, security\",,lab\n",
+        ),
+    )
+    .unwrap();
+
+    let tagger = AssetTagger::load("[10.0.0.0/8, !10.9.0.0/16, 10.9.1.0/24]", Some(&path))
+        .expect("should load");
+    let mut guests = metric("10.9.8.7", "10.1.1.1");
+    tagger.tag(&mut guests);
+    assert_eq!(guests.event_direction.as_deref(), Some("inbound"));
+    assert!(guests.event_src_asset.is_none());
+    let asset = guests.event_dst_asset.unwrap();
+    assert_eq!(asset.hostname, "lab, shared");
+    assert_eq!(asset.owner.as_deref(), Some("ops, security"));
+    assert_eq!(asset.zone.as_deref(), Some("lab"));
+
+    // A more specific entry brings a range back in.
+    let mut printers = metric("10.9.1.5", "10.1.1.1");
+    tagger.tag(&mut printers);
+    assert_eq!(printers.event_direction.as_deref(), Some("internal"));
+
+    for home_net in ["[10.0.0.0/8,![10.9.0.0/16]]", "[$HOME_NET,!10.9.0.0/16]"] {
+        let error = AssetTagger::load(home_net, None)
+            .err()
+            .expect("should be rejected");
+        assert!(error.to_string().contains("not supported"), "{}", error);
+    }
+
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,115 @@
+use sensor_suricata_service_rust::attack::AttackMapper;
+use sensor_suricata_service_rust::processor::convert_suricata_alert_to_sensor_event;
+use sensor_suricata_service_rust::rules::RuleSet;
+use sensor_suricata_service_rust::types::SuricataAlert;
+use std::fs;
+
+fn alert(sid: i64, metadata: &str) -> SuricataAlert {
+    let json = format!(
+        r#"{{
+            "timestamp": "2025-12-15T07:46:41.123456+0000",
+            "event_type": "alert",
+            "alert": {{
+                "action": "allowed",
+                "gid": 1,
+                "signature_id": {},
+                "rev": 1,
+                "signature": "x",
+                "category": "A Network Trojan was detected",
+                "severity": 1,
+                "metadata": {{ {} }}
+            }}
+        }}"#,
+        sid, metadata
+    );
+    let mut bytes = json.into_bytes();
+    simd_json::from_slice(&mut bytes).expect("should parse")
+}
+
+fn entries(entries: &[sensor_suricata_service_rust::pb::AttackEntry]) -> Vec<(&str, Option<&str>)> {
+    entries
+        .iter()
+        .map(|e| (e.id.as_str(), e.name.as_deref()))
+        .collect()
+}
+
+#[test]
+fn maps_from_metadata_rules_and_table() {
+    let dir = std::env::temp_dir().join(format!("attack-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("local.rules"),
+        concat!(
+            "alert tcp any any -> any any (msg:\"a\"; classtype:trojan-activity; ",
+            "metadata:mitre_tactic_id TA0011, mitre_technique_id T1071_001, ",
+            "mitre_technique_name Web_Protocols; sid:1001; rev:1;)\n",
+            "alert tcp any any -> any any (msg:\"b\"; classtype:trojan-activity; sid:1002; rev:1;)\n",
+        ),
+    )
+    .unwrap();
+    fs::write(
+        dir.join("attack.csv"),
+        "key,tactics,techniques\nsid:1002,TA0010,T1041\nclasstype:trojan-activity,TA0011,bogus\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("bundle.json"),
+        r#"{"type": "bundle", "objects": [
+            {"type": "x-mitre-tactic", "name": "Command and Control",
+             "external_references": [{"source_name": "mitre-attack", "external_id": "TA0011"}]},
+            {"type": "attack-pattern", "name": "Exfiltration Over C2 Channel",
+             "external_references": [{"source_name": "mitre-attack", "external_id": "T1041"}]},
+            {"type": "attack-pattern", "name": "Old", "revoked": true,
+             "external_references": [{"source_name": "mitre-attack", "external_id": "T1043"}]}
+        ]}"#,
+    )
+    .unwrap();
+
+    let rules = RuleSet::load(&dir.join("local.rules"), None).unwrap();
+    let mapper = AttackMapper::load(
+        Some(&dir.join("attack.csv")),
+        Some(&dir.join("bundle.json")),
+    )
+    .unwrap();
+
+    // Rule metadata, then the classtype row; names from the bundle win.
+    let data = alert(1001, "");
+    let (mut event, _) = convert_suricata_alert_to_sensor_event(&data).unwrap();
+    mapper.map(&data, rules.rule(1, 1001, 1), &mut event);
+    assert_eq!(
+        entries(&event.event_attack_tactics),
+        vec![("TA0011", Some("Command and Control"))]
+    );
+    assert_eq!(
+        entries(&event.event_attack_techniques),
+        vec![("T1071.001", Some("Web Protocols"))]
+    );
+
+    // Alert metadata first, then the sid and classtype rows.
+    let data = alert(
+        1002,
+        r#""mitre_technique_id": ["t1043"], "mitre_technique_name": ["Commonly_Used_Port"]"#,
+    );
+    let (mut event, _) = convert_suricata_alert_to_sensor_event(&data).unwrap();
+    mapper.map(&data, rules.rule(1, 1002, 1), &mut event);
+    assert_eq!(
+        entries(&event.event_attack_tactics),
+        vec![("TA0010", None), ("TA0011", Some("Command and Control"))]
+    );
+    assert_eq!(
+        entries(&event.event_attack_techniques),
+        vec![
+            ("T1043", Some("Commonly Used Port")),
+            ("T1041", Some("Exfiltration Over C2 Channel"))
+        ]
+    );
+
+    // Without a ruleset nothing matches the classtype row.
+    let data = alert(9999, "");
+    let (mut event, _) = convert_suricata_alert_to_sensor_event(&data).unwrap();
+    mapper.map(&data, None, &mut event);
+    assert!(event.event_attack_tactics.is_empty());
+    assert_eq!(mapper.get_total_mapped(), 2);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,182 @@
+use sensor_suricata_service_rust::pb::{Metric, SensorEvent};
+use sensor_suricata_service_rust::queue::{EventBatchQueue, FlushPolicy, MemoryLimits};
+use std::thread::sleep;
+use std::time::Duration;
+
+fn event(hash: &str) -> SensorEvent {
+    SensorEvent {
+        event_hash_sha256: hash.to_string(),
+        event_metrics_count: 1,
+        metrics: vec![Metric {
+            snort_timestamp: "2025-12-15T07:46:41.123456+0000".to_string(),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn flushes_immediately_without_interval() {
+    let queue = EventBatchQueue::new(FlushPolicy::default(), MemoryLimits::default(), 4);
+    queue.add(event("a"));
+    queue.add(event("a"));
+    queue.add(event("b"));
+
+    let mut batch = queue.process_batch();
+    batch.sort_by(|x, y| x.event_hash_sha256.cmp(&y.event_hash_sha256));
+    assert_eq!(batch.len(), 2);
+    assert_eq!(batch[0].event_metrics_count, 2);
+    assert_eq!(batch[0].metrics.len(), 2);
+    assert_eq!(queue.get_total_sent_events(), 3);
+    assert_eq!(queue.get_queue_size(), 0);
+}
+
+#[test]
+fn holds_events_for_the_interval() {
+    let queue = EventBatchQueue::new(
+        FlushPolicy {
+            interval: Duration::from_millis(1000),
+            ..Default::default()
+        },
+        MemoryLimits::default(),
+        4,
+    );
+    queue.add(event("a"));
+    assert!(queue.process_batch().is_empty());
+
+    // Steps of 600ms leave 400ms of slack either side of the interval.
+    sleep(Duration::from_millis(600));
+    queue.add(event("a"));
+    sleep(Duration::from_millis(600));
+    // Merging restarted the interval.
+    assert!(queue.process_batch().is_empty());
+
+    sleep(Duration::from_millis(600));
+    let batch = queue.process_batch();
+    assert_eq!(batch.len(), 1);
+    assert_eq!(batch[0].event_metrics_count, 2);
+}
+
+#[test]
+fn flushes_updated_events_at_max_age() {
+    let queue = EventBatchQueue::new(
+        FlushPolicy {
+            interval: Duration::from_secs(60),
+            max_age: Duration::from_millis(100),
+            ..Default::default()
+        },
+        MemoryLimits::default(),
+        4,
+    );
+    queue.add(event("a"));
+    queue.add(event("b"));
+    sleep(Duration::from_millis(120));
+    queue.add(event("a"));
+
+    let batch = queue.process_batch();
+    assert_eq!(batch.len(), 2);
+    assert_eq!(queue.get_queue_size(), 0);
+}
+
+#[test]
+fn flushes_early_on_event_and_byte_limits() {
+    let queue = EventBatchQueue::new(
+        FlushPolicy {
+            interval: Duration::from_secs(60),
+            max_events: 3,
+            ..Default::default()
+        },
+        MemoryLimits::default(),
+        4,
+    );
+    queue.add(event("a"));
+    queue.add(event("b"));
+    assert!(queue.process_batch().is_empty());
+    queue.add(event("a"));
+    assert_eq!(queue.process_batch().len(), 2);
+
+    let queue = EventBatchQueue::new(
+        FlushPolicy {
+            interval: Duration::from_secs(60),
+            max_bytes: 200,
+            ..Default::default()
+        },
+        MemoryLimits::default(),
+        4,
+    );
+    queue.add(event("a"));
+    assert!(queue.process_batch().is_empty());
+    for _ in 0..5 {
+        queue.add(event("b"));
+    }
+    assert_eq!(queue.process_batch().len(), 2);
+}
+
+#[test]
+fn merges_across_shards_from_many_workers() {
+    let queue = EventBatchQueue::new(
+        FlushPolicy {
+            interval: Duration::from_secs(60),
+            ..Default::default()
+        },
+        MemoryLimits::default(),
+        16,
+    );
+    std::thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                for i in 0..100 {
+                    queue.add(event(&format!("hash-{}", i % 10)));
+                }
+            });
+        }
+    });
+    assert_eq!(queue.get_queue_size(), 10);
+
+    let queue = EventBatchQueue::new(FlushPolicy::default(), MemoryLimits::default(), 16);
+    std::thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                for i in 0..100 {
+                    queue.add(event(&format!("hash-{}", i % 10)));
+                }
+            });
+        }
+    });
+    let batch = queue.process_batch();
+    assert_eq!(batch.len(), 10);
+    assert!(batch.iter().all(|e| e.event_metrics_count == 80));
+    assert_eq!(queue.get_total_sent_events(), 800);
+}
+
+#[test]
+fn flushes_only_expired_records() {
+    let queue = EventBatchQueue::new(
+        FlushPolicy {
+            interval: Duration::from_millis(100),
+            max_age: Duration::from_millis(250),
+            ..Default::default()
+        },
+        MemoryLimits::default(),
+        4,
+    );
+    for i in 0..1000 {
+        queue.add(event(&format!("old-{}", i)));
+    }
+    queue.add(event("busy"));
+    sleep(Duration::from_millis(120));
+    queue.add(event("new"));
+    queue.add(event("busy"));
+
+    assert_eq!(queue.process_batch().len(), 1000);
+    assert_eq!(queue.get_queue_size(), 2);
+    assert!(queue.process_batch().is_empty());
+
+    // "busy" keeps merging but is flushed at its max age; "new" goes idle.
+    sleep(Duration::from_millis(70));
+    queue.add(event("busy"));
+    sleep(Duration::from_millis(70));
+    let batch = queue.process_batch();
+    assert_eq!(batch.len(), 2);
+    assert_eq!(queue.get_queue_size(), 0);
+}
@@ -0,0 +1,73 @@
+use sensor_suricata_service_rust::community_id::community_id;
+use sensor_suricata_service_rust::types::SuricataAlert;
+
+fn flow(proto: &str, src: &str, dst: &str, ports: &str) -> SuricataAlert {
+    let json = format!(
+        r#"{{"timestamp": "2025-12-15T07:46:41.123456+0000", "proto": "{}", "src_ip": "{}", "dest_ip": "{}", {}}}"#,
+        proto, src, dst, ports
+    );
+    let mut bytes = json.into_bytes();
+    simd_json::from_slice(&mut bytes).expect("should parse")
+}
+
+#[test]
+fn computes_community_id_v1() {
+    let tcp = flow(
+        "TCP",
+        "128.232.110.120",
+        "66.35.250.204",
+        r#""src_port": 34855, "dest_port": 80"#,
+    );
+    let reply = flow(
+        "TCP",
+        "66.35.250.204",
+        "128.232.110.120",
+        r#""src_port": 80, "dest_port": 34855"#,
+    );
+    assert_eq!(
+        community_id(&tcp, 0).as_deref(),
+        Some("1:LQU9qZlK+B5F3KDmev6m5PMibrg=")
+    );
+    assert_eq!(community_id(&reply, 0), community_id(&tcp, 0));
+    assert_ne!(community_id(&tcp, 1), community_id(&tcp, 0));
+
+    // Echo request and reply hash alike; unpaired types keep their direction.
+    let request = flow(
+        "ICMP",
+        "192.168.0.89",
+        "192.168.0.1",
+        r#""icmp_type": 8, "icmp_code": 0"#,
+    );
+    let answer = flow(
+        "ICMP",
+        "192.168.0.1",
+        "192.168.0.89",
+        r#""icmp_type": 0, "icmp_code": 0"#,
+    );
+    assert_eq!(community_id(&request, 0), community_id(&answer, 0));
+    let unreachable = flow(
+        "ICMP",
+        "192.168.0.1",
+        "192.168.0.89",
+        r#""icmp_type": 3, "icmp_code": 1"#,
+    );
+    let unreachable_back = flow(
+        "ICMP",
+        "192.168.0.89",
+        "192.168.0.1",
+        r#""icmp_type": 3, "icmp_code": 1"#,
+    );
+    assert_ne!(
+        community_id(&unreachable, 0),
+        community_id(&unreachable_back, 0)
+    );
+
+    let v6 = flow(
+        "IPv6-ICMP",
+        "fe80::200:86ff:fe05:80da",
+        "fe80::260:97ff:fe07:69ea",
+        r#""icmp_type": 135, "icmp_code": 0"#,
+    );
+    assert!(community_id(&v6, 0).unwrap().starts_with("1:"));
+    assert!(community_id(&flow("TCP", "10.0.0.1", "fe80::1", "\"a\": 1"), 0).is_none());
+}
@@ -0,0 +1,177 @@
+use sensor_suricata_service_rust::pb::SensorEvent;
+use sensor_suricata_service_rust::spool::Spool;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const DAY: Duration = Duration::from_secs(86400);
+
+fn dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("spool-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn batch(hashes: &[&str]) -> Vec<SensorEvent> {
+    hashes
+        .iter()
+        .map(|hash| SensorEvent {
+            event_hash_sha256: hash.to_string(),
+            event_metrics_count: 1,
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn pop(spool: &Spool) -> Option<Vec<String>> {
+    let batch = spool.pop().unwrap()?;
+    spool.delivered().unwrap();
+    Some(batch.into_iter().map(|e| e.event_hash_sha256).collect())
+}
+
+fn segments(dir: &PathBuf) -> Vec<PathBuf> {
+    let mut segments: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "seg"))
+        .collect();
+    segments.sort();
+    segments
+}
+
+#[test]
+fn drains_in_order_across_restarts() {
+    let dir = dir("restart");
+    let spool = Spool::open(&dir, 1 << 20, DAY).unwrap();
+    assert!(spool.is_idle());
+    spool.push(&batch(&["a", "b"])).unwrap();
+    spool.push(&batch(&["c"])).unwrap();
+    spool.push(&batch(&["d"])).unwrap();
+    assert!(!spool.is_idle());
+    assert_eq!(spool.get_spooled_batches(), 3);
+
+    assert_eq!(pop(&spool).unwrap(), ["a", "b"]);
+    drop(spool);
+
+    // The read position survives a restart.
+    let spool = Spool::open(&dir, 1 << 20, DAY).unwrap();
+    assert_eq!(spool.get_spooled_batches(), 2);
+    spool.push(&batch(&["e"])).unwrap();
+    assert_eq!(pop(&spool).unwrap(), ["c"]);
+    assert_eq!(pop(&spool).unwrap(), ["d"]);
+    assert_eq!(pop(&spool).unwrap(), ["e"]);
+    assert_eq!(pop(&spool), None);
+    assert!(spool.is_idle());
+    drop(spool);
+
+    let spool = Spool::open(&dir, 1 << 20, DAY).unwrap();
+    assert_eq!(spool.get_spooled_batches(), 0);
+    spool.push(&batch(&["f"])).unwrap();
+    drop(spool);
+    let spool = Spool::open(&dir, 1 << 20, DAY).unwrap();
+    assert_eq!(pop(&spool).unwrap(), ["f"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reads_undelivered_batches_again_after_a_restart() {
+    let dir = dir("undelivered");
+    // Segments of 100 bytes, so each batch gets its own.
+    let spool = Spool::open(&dir, 800, DAY).unwrap();
+    let a = "a".repeat(100);
+    let b = "b".repeat(100);
+    spool.push(&batch(&[&a])).unwrap();
+    spool.push(&batch(&[&b])).unwrap();
+    spool.push(&batch(&["c"])).unwrap();
+    assert_eq!(segments(&dir).len(), 3);
+
+    // Read segments are kept while their batches are on their way out.
+    assert!(spool.pop().unwrap().is_some());
+    assert!(spool.pop().unwrap().is_some());
+    assert_eq!(segments(&dir).len(), 3);
+    assert!(!spool.is_idle());
+    spool.delivered().unwrap();
+    assert_eq!(segments(&dir).len(), 2);
+    drop(spool);
+
+    // Only the delivered batch is skipped after a restart.
+    let spool = Spool::open(&dir, 800, DAY).unwrap();
+    assert_eq!(spool.get_spooled_batches(), 2);
+    assert_eq!(pop(&spool).unwrap(), [b]);
+    assert_eq!(pop(&spool).unwrap(), ["c"]);
+    assert_eq!(pop(&spool), None);
+    assert!(spool.is_idle());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn skips_corrupt_and_truncated_records() {
+    let dir = dir("corrupt");
+    let spool = Spool::open(&dir, 1 << 20, DAY).unwrap();
+    spool.push(&batch(&["a"])).unwrap();
+    spool.push(&batch(&["b"])).unwrap();
+    drop(spool);
+
+    // Flip a byte of the second record and cut the file short, as a crash
+    // mid-write would.
+    let path = segments(&dir).pop().unwrap();
+    let mut bytes = fs::read(&path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    bytes.push(0x01);
+    fs::write(&path, &bytes).unwrap();
+
+    let spool = Spool::open(&dir, 1 << 20, DAY).unwrap();
+    assert_eq!(spool.get_total_corrupt(), 1);
+    assert_eq!(spool.get_spooled_batches(), 1);
+    spool.push(&batch(&["c"])).unwrap();
+    assert_eq!(pop(&spool).unwrap(), ["a"]);
+    assert_eq!(pop(&spool).unwrap(), ["c"]);
+    assert_eq!(pop(&spool), None);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn enforces_size_and_age_limits() {
+    let dir = dir("limits");
+    // Segments of 100 bytes, at most 800 bytes in total.
+    let spool = Spool::open(&dir, 800, DAY).unwrap();
+    let hash = "0".repeat(64);
+    for _ in 0..20 {
+        spool.push(&batch(&[&hash])).unwrap();
+    }
+    assert!(spool.get_spooled_bytes() <= 800);
+    assert!(spool.get_total_dropped() > 0);
+    assert_eq!(
+        spool.get_spooled_batches() as i64 + spool.get_total_dropped(),
+        20
+    );
+    drop(spool);
+
+    // Batches older than the maximum age are dropped instead of read.
+    let spool = Spool::open(&dir, 1 << 20, Duration::from_secs(1)).unwrap();
+    std::thread::sleep(Duration::from_millis(2100));
+    assert_eq!(pop(&spool), None);
+    assert_eq!(spool.get_spooled_batches(), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn keeps_express_batches_in_a_nested_spool() {
+    let dir = dir("express");
+    let spool = Spool::open(&dir, 1 << 20, DAY).unwrap();
+    let express = Spool::open(&dir.join("express"), 1 << 20, DAY).unwrap();
+    spool.push(&batch(&["a"])).unwrap();
+    express.push(&batch(&["x"])).unwrap();
+    drop((spool, express));
+
+    // Each reopens with only its own batches.
+    let spool = Spool::open(&dir, 1 << 20, DAY).unwrap();
+    let express = Spool::open(&dir.join("express"), 1 << 20, DAY).unwrap();
+    assert_eq!(pop(&express).unwrap(), ["x"]);
+    assert_eq!(pop(&express), None);
+    assert_eq!(pop(&spool).unwrap(), ["a"]);
+    assert_eq!(pop(&spool), None);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,142 @@
+use sensor_suricata_service_rust::correlate::{CorrelationMode, Correlator};
+use sensor_suricata_service_rust::listener::flow_id;
+use sensor_suricata_service_rust::pb::SensorEvent;
+use sensor_suricata_service_rust::processor::convert_suricata_alert_to_sensor_event;
+use sensor_suricata_service_rust::types::SuricataAlert;
+use std::time::Duration;
+
+fn record(json: &str) -> SuricataAlert {
+    let mut bytes = json.as_bytes().to_vec();
+    simd_json::from_slice(&mut bytes).expect("should parse")
+}
+
+fn alert(flow_id: i64, sid: i64, second: u32, src_port: i64) -> (SuricataAlert, SensorEvent) {
+    let data = record(&format!(
+        r#"{{
+            "timestamp": "2025-12-15T07:46:{:02}.000000+0000",
+            "flow_id": {},
+            "event_type": "alert",
+            "proto": "TCP",
+            "src_ip": "10.0.0.1",
+            "src_port": {},
+            "dest_ip": "10.0.0.2",
+            "dest_port": 80,
+            "alert": {{
+                "action": "allowed",
+                "gid": 1,
+                "signature_id": {},
+                "rev": 1,
+                "signature": "x",
+                "category": "y",
+                "severity": 2
+            }}
+        }}"#,
+        second, flow_id, src_port, sid
+    ));
+    let (mut event, metric) = convert_suricata_alert_to_sensor_event(&data).unwrap();
+    event.metrics.push(metric);
+    (data, event)
+}
+
+fn flow_end(flow_id: i64) -> SuricataAlert {
+    record(&format!(
+        r#"{{"timestamp": "2025-12-15T07:47:00.000000+0000", "flow_id": {}, "event_type": "flow"}}"#,
+        flow_id
+    ))
+}
+
+#[test]
+fn merges_alerts_of_a_flow_and_signature_until_it_ends() {
+    let correlator = Correlator::new(CorrelationMode::FlowSignature, Duration::from_secs(60), 4);
+
+    for (flow_id, sid, second) in [(7, 1001, 1), (7, 1001, 5), (7, 1002, 6), (8, 1001, 7)] {
+        let (data, event) = alert(flow_id, sid, second, 40000);
+        assert!(correlator.add(&data, event).is_none());
+    }
+    assert_eq!(correlator.get_open_sessions(), 3);
+    assert_eq!(correlator.get_total_correlated(), 1);
+
+    // Records of other types or flows close nothing.
+    let (data, _) = alert(7, 1001, 8, 40000);
+    assert!(correlator.end_flow(&data).is_empty());
+    assert!(correlator.end_flow(&flow_end(9)).is_empty());
+
+    let mut closed = correlator.end_flow(&flow_end(7));
+    closed.sort_by_key(|e| e.snort_rule_sid);
+    assert_eq!(closed.len(), 2);
+    assert_eq!(closed[0].event_metrics_count, 2);
+    assert_eq!(closed[0].metrics.len(), 2);
+    assert_eq!(
+        closed[0].event_first_seen.as_deref(),
+        Some(closed[0].metrics[0].snort_timestamp.as_str())
+    );
+    assert_eq!(
+        closed[0].event_last_seen.as_deref(),
+        Some(closed[0].metrics[1].snort_timestamp.as_str())
+    );
+    assert_ne!(closed[0].event_first_seen, closed[0].event_last_seen);
+    assert_eq!(closed[1].event_metrics_count, 1);
+    assert_eq!(correlator.get_total_flow_closed(), 2);
+    assert_eq!(correlator.get_open_sessions(), 1);
+}
+
+#[test]
+fn merges_all_alerts_of_a_flow() {
+    let correlator = Correlator::new(CorrelationMode::Flow, Duration::from_secs(60), 4);
+
+    for flow_id in 1..=16 {
+        for (sid, second) in [(1001, 1), (1002, 2), (1003, 3)] {
+            let (data, event) = alert(flow_id, sid, second, 40000);
+            assert!(correlator.add(&data, event).is_none());
+        }
+    }
+    assert_eq!(correlator.get_open_sessions(), 16);
+    assert_eq!(correlator.get_total_correlated(), 32);
+
+    // The event keeps the signature of the first alert of the flow.
+    let closed = correlator.end_flow(&flow_end(7));
+    assert_eq!(closed.len(), 1);
+    assert_eq!(closed[0].snort_rule_sid, 1001);
+    assert_eq!(closed[0].event_metrics_count, 3);
+    assert_eq!(correlator.get_open_sessions(), 15);
+    assert_eq!(correlator.expire().len(), 0);
+}
+
+#[test]
+fn groups_by_tuple_and_expires_idle_sessions() {
+    let correlator = Correlator::new(CorrelationMode::Tuple, Duration::ZERO, 4);
+
+    // Different flows with the same 5-tuple share a session.
+    for (flow_id, src_port) in [(1, 40000), (2, 40000), (3, 40001)] {
+        let (data, event) = alert(flow_id, 1001, 1, src_port);
+        assert!(correlator.add(&data, event).is_none());
+    }
+    assert_eq!(correlator.get_open_sessions(), 2);
+
+    let mut expired = correlator.expire();
+    expired.sort_by_key(|e| e.event_metrics_count);
+    let counts: Vec<i64> = expired.iter().map(|e| e.event_metrics_count).collect();
+    assert_eq!(counts, [1, 2]);
+    assert_eq!(correlator.get_total_expired(), 2);
+    assert_eq!(correlator.get_open_sessions(), 0);
+    assert!(correlator.end_flow(&flow_end(1)).is_empty());
+}
+
+#[test]
+fn passes_events_through_without_correlation() {
+    let correlator = Correlator::new(CorrelationMode::None, Duration::from_secs(60), 4);
+    let (data, event) = alert(7, 1001, 1, 40000);
+    let event = correlator.add(&data, event).expect("should pass through");
+    assert_eq!(event.metrics.len(), 1);
+    assert_eq!(correlator.get_open_sessions(), 0);
+    assert!(correlator.end_flow(&flow_end(7)).is_empty());
+}
+
+#[test]
+fn routes_records_of_a_flow_by_flow_id() {
+    let alert = r#"{"timestamp":"2025-12-15T07:46:41.000000+0000","flow_id":1234567890123,"event_type":"alert","alert":{"signature_id":1001}}"#;
+    let flow = r#"{"timestamp":"2025-12-15T07:47:30.000000+0000","flow_id": 1234567890123,"event_type":"flow","flow":{"age":40}}"#;
+    assert_eq!(flow_id(alert), Some(1234567890123));
+    assert_eq!(flow_id(flow), flow_id(alert));
+    assert_eq!(flow_id(r#"{"event_type":"stats","stats":{}}"#), None);
+}
@@ -0,0 +1,140 @@
+use sensor_suricata_service_rust::holdback::FlowHoldback;
+use sensor_suricata_service_rust::pb::SensorEvent;
+use sensor_suricata_service_rust::processor::convert_suricata_alert_to_sensor_event;
+use sensor_suricata_service_rust::types::SuricataAlert;
+use std::time::Duration;
+
+fn record(json: &str) -> SuricataAlert {
+    let mut bytes = json.as_bytes().to_vec();
+    simd_json::from_slice(&mut bytes).expect("should parse")
+}
+
+fn alert(flow_id: Option<i64>) -> (SuricataAlert, SensorEvent) {
+    let flow_id = flow_id.map(|id| format!(r#""flow_id": {},"#, id));
+    let data = record(&format!(
+        r#"{{
+            "timestamp": "2025-12-15T07:46:41.000000+0000",
+            {}
+            "event_type": "alert",
+            "proto": "TCP",
+            "src_ip": "10.0.0.1",
+            "src_port": 40000,
+            "dest_ip": "10.0.0.2",
+            "dest_port": 80,
+            "flow": {{"pkts_toserver": 3, "pkts_toclient": 2, "bytes_toserver": 200, "bytes_toclient": 120}},
+            "alert": {{
+                "action": "allowed",
+                "gid": 1,
+                "signature_id": 1001,
+                "rev": 1,
+                "signature": "x",
+                "category": "y",
+                "severity": 2
+            }}
+        }}"#,
+        flow_id.unwrap_or_default()
+    ));
+    let (mut event, metric) = convert_suricata_alert_to_sensor_event(&data).unwrap();
+    event.metrics.push(metric);
+    (data, event)
+}
+
+fn flow_end(flow_id: i64) -> SuricataAlert {
+    record(&format!(
+        r#"{{
+            "timestamp": "2025-12-15T07:47:30.000000+0000",
+            "flow_id": {},
+            "event_type": "flow",
+            "flow": {{
+                "pkts_toserver": 40,
+                "pkts_toclient": 35,
+                "bytes_toserver": 5000,
+                "bytes_toclient": 90000,
+                "start": "2025-12-15T07:46:40.000000+0000",
+                "end": "2025-12-15T07:47:20.000000+0000",
+                "age": 40,
+                "state": "closed",
+                "reason": "timeout",
+                "alerted": true
+            }},
+            "tcp": {{"tcp_flags": "1b", "tcp_flags_ts": "1b", "tcp_flags_tc": "1b", "state": "closed"}}
+        }}"#,
+        flow_id
+    ))
+}
+
+#[test]
+fn merges_final_flow_statistics() {
+    let holdback = FlowHoldback::new(Duration::from_secs(60), 0);
+
+    for _ in 0..2 {
+        let (data, event) = alert(Some(7));
+        assert!(holdback.hold(&data, event).is_none());
+    }
+    let (data, event) = alert(None);
+    assert!(holdback.hold(&data, event).is_some());
+    assert_eq!(holdback.get_held(), 2);
+
+    // The alert itself and other flows release nothing.
+    let (data, _) = alert(Some(7));
+    assert!(holdback.release(&data).is_empty());
+    assert!(holdback.release(&flow_end(8)).is_empty());
+
+    let released = holdback.release(&flow_end(7));
+    assert_eq!(released.len(), 2);
+    let (data, event) = &released[0];
+    assert_eq!(data.event_type.as_deref(), Some("alert"));
+    let metric = &event.metrics[0];
+    assert_eq!(metric.snort_client_pkts, Some(40));
+    assert_eq!(metric.snort_server_pkts, Some(35));
+    assert_eq!(metric.snort_client_bytes, Some(5000));
+    assert_eq!(metric.snort_server_bytes, Some(90000));
+    assert_eq!(
+        metric.event_flow_end.as_deref(),
+        Some("2025-12-15T07:47:20.000000+0000")
+    );
+    assert_eq!(metric.event_flow_age, Some(40));
+    assert_eq!(metric.event_flow_state.as_deref(), Some("closed"));
+    assert_eq!(metric.event_flow_reason.as_deref(), Some("timeout"));
+    assert_eq!(metric.snort_tcp_flags.as_deref(), Some("1b"));
+    assert_eq!(holdback.get_total_enriched(), 2);
+    assert_eq!(holdback.get_held(), 0);
+}
+
+#[test]
+fn releases_unchanged_after_timeout() {
+    let holdback = FlowHoldback::new(Duration::ZERO, 0);
+    let (data, event) = alert(Some(7));
+    assert!(holdback.hold(&data, event).is_none());
+
+    let expired = holdback.expire();
+    assert_eq!(expired.len(), 1);
+    let metric = &expired[0].1.metrics[0];
+    assert_eq!(metric.snort_client_bytes, Some(200));
+    assert_eq!(metric.event_flow_end, None);
+    assert_eq!(holdback.get_total_timed_out(), 1);
+    assert!(holdback.release(&flow_end(7)).is_empty());
+}
+
+#[test]
+fn releases_oldest_flows_past_the_cap() {
+    let holdback = FlowHoldback::new(Duration::from_secs(60), 2);
+    for flow_id in [1, 2, 2] {
+        let (data, event) = alert(Some(flow_id));
+        assert!(holdback.hold(&data, event).is_none());
+    }
+    // The next record releases the oldest flows until the cap is met.
+    let (data, event) = alert(Some(3));
+    assert!(holdback.hold(&data, event).is_none());
+    let released = holdback.release(&data);
+    let flow_ids: Vec<_> = released.iter().map(|(data, _)| data.flow_id).collect();
+    assert_eq!(flow_ids, [Some(1), Some(2), Some(2)]);
+    assert_eq!(released[0].1.metrics[0].event_flow_end, None);
+    assert_eq!(holdback.get_held(), 1);
+    assert_eq!(holdback.get_total_released_early(), 3);
+
+    // Released flows no longer time out.
+    assert!(holdback.expire().is_empty());
+    assert_eq!(holdback.release(&flow_end(3)).len(), 1);
+    assert_eq!(holdback.get_total_enriched(), 1);
+}
@@ -0,0 +1,247 @@
+use sensor_suricata_service_rust::geoip::GeoIp;
+use sensor_suricata_service_rust::pb::Metric;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Just enough of the MaxMind DB format to write small IPv4 test databases.
+enum Value {
+    Str(&'static str),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F64(f64),
+    Map(Vec<(&'static str, Value)>),
+    Array(Vec<Value>),
+}
+
+fn control(out: &mut Vec<u8>, kind: u8, size: usize) {
+    let (first, extra): (u8, Vec<u8>) = match size {
+        0..=28 => (size as u8, vec![]),
+        29..=284 => (29, vec![(size - 29) as u8]),
+        _ => (30, ((size - 285) as u16).to_be_bytes().to_vec()),
+    };
+    if kind <= 7 {
+        out.push(kind << 5 | first);
+    } else {
+        out.push(first);
+        out.push(kind - 7);
+    }
+    out.extend(extra);
+}
+
+fn uint(out: &mut Vec<u8>, kind: u8, value: u64, width: usize) {
+    let bytes = value.to_be_bytes();
+    let start = bytes
+        .iter()
+        .position(|b| *b != 0)
+        .unwrap_or(8)
+        .max(8 - width);
+    control(out, kind, 8 - start);
+    out.extend(&bytes[start..]);
+}
+
+fn encode(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Str(s) => {
+            control(out, 2, s.len());
+            out.extend(s.as_bytes());
+        }
+        Value::F64(f) => {
+            control(out, 3, 8);
+            out.extend(f.to_be_bytes());
+        }
+        Value::U16(n) => uint(out, 5, *n as u64, 2),
+        Value::U32(n) => uint(out, 6, *n as u64, 4),
+        Value::U64(n) => uint(out, 9, *n, 8),
+        Value::Map(entries) => {
+            control(out, 7, entries.len());
+            for (key, value) in entries {
+                encode(out, &Value::Str(key));
+                encode(out, value);
+            }
+        }
+        Value::Array(values) => {
+            control(out, 11, values.len());
+            for value in values {
+                encode(out, value);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Record {
+    Empty,
+    Node(usize),
+    Data(usize),
+}
+
+fn write_mmdb(path: &Path, database_type: &'static str, networks: Vec<(&str, u32, Value)>) {
+    let mut nodes = vec![[Record::Empty; 2]];
+    let mut data = Vec::new();
+    for (network, prefix_len, value) in networks {
+        let ip = u32::from(network.parse::<std::net::Ipv4Addr>().unwrap());
+        let offset = data.len();
+        encode(&mut data, &value);
+        let mut node = 0;
+        for depth in 0..prefix_len {
+            let bit = (ip >> (31 - depth) & 1) as usize;
+            if depth == prefix_len - 1 {
+                nodes[node][bit] = Record::Data(offset);
+            } else {
+                node = match nodes[node][bit] {
+                    Record::Node(next) => next,
+                    _ => {
+                        nodes.push([Record::Empty; 2]);
+                        nodes[node][bit] = Record::Node(nodes.len() - 1);
+                        nodes.len() - 1
+                    }
+                };
+            }
+        }
+    }
+
+    let node_count = nodes.len();
+    let mut out = Vec::new();
+    for node in &nodes {
+        for record in node {
+            let value = match *record {
+                Record::Empty => node_count,
+                Record::Node(next) => next,
+                Record::Data(offset) => node_count + 16 + offset,
+            };
+            out.extend(&(value as u32).to_be_bytes()[1..]);
+        }
+    }
+    out.extend([0u8; 16]);
+    out.extend(data);
+    out.extend(b"\xab\xcd\xefMaxMind.com");
+    let metadata = Value::Map(vec![
+        ("node_count", Value::U32(node_count as u32)),
+        ("record_size", Value::U16(24)),
+        ("ip_version", Value::U16(4)),
+        ("database_type", Value::Str(database_type)),
+        ("languages", Value::Array(vec![Value::Str("en")])),
+        ("binary_format_major_version", Value::U16(2)),
+        ("binary_format_minor_version", Value::U16(0)),
+        ("build_epoch", Value::U64(1_700_000_000)),
+        ("description", Value::Map(vec![("en", Value::Str("test"))])),
+    ]);
+    encode(&mut out, &metadata);
+    fs::write(path, out).unwrap();
+}
+
+fn databases() -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("geoip-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let city = dir.join("city.mmdb");
+    write_mmdb(
+        &city,
+        "GeoLite2-City",
+        vec![(
+            "81.2.69.0",
+            24,
+            Value::Map(vec![
+                (
+                    "city",
+                    Value::Map(vec![(
+                        "names",
+                        Value::Map(vec![("en", Value::Str("London"))]),
+                    )]),
+                ),
+                (
+                    "country",
+                    Value::Map(vec![
+                        ("iso_code", Value::Str("GB")),
+                        (
+                            "names",
+                            Value::Map(vec![("en", Value::Str("United Kingdom"))]),
+                        ),
+                    ]),
+                ),
+                (
+                    "location",
+                    Value::Map(vec![
+                        ("latitude", Value::F64(51.5142)),
+                        ("longitude", Value::F64(-0.0931)),
+                    ]),
+                ),
+            ]),
+        )],
+    );
+    let asn = dir.join("asn.mmdb");
+    write_mmdb(
+        &asn,
+        "GeoLite2-ASN",
+        vec![
+            (
+                "81.2.69.0",
+                24,
+                Value::Map(vec![
+                    ("autonomous_system_number", Value::U32(20712)),
+                    (
+                        "autonomous_system_organization",
+                        Value::Str("Andrews & Arnold Ltd"),
+                    ),
+                ]),
+            ),
+            (
+                "8.8.8.0",
+                24,
+                Value::Map(vec![
+                    ("autonomous_system_number", Value::U32(15169)),
+                    ("autonomous_system_organization", Value::Str("Google LLC")),
+                ]),
+            ),
+        ],
+    );
+    (city, asn)
+}
+
+#[test]
+fn looks_up_city_and_asn() {
+    let (city, asn) = databases();
+    let geoip = GeoIp::load(Some(&city), Some(&asn)).expect("should load");
+
+    let london = geoip.lookup("81.2.69.160").expect("should be found");
+    assert_eq!(london.country_code.as_deref(), Some("GB"));
+    assert_eq!(london.country_name.as_deref(), Some("United Kingdom"));
+    assert_eq!(london.city.as_deref(), Some("London"));
+    assert_eq!(london.latitude, Some(51.5142));
+    assert_eq!(london.longitude, Some(-0.0931));
+    assert_eq!(london.asn, Some(20712));
+    assert_eq!(london.as_org.as_deref(), Some("Andrews & Arnold Ltd"));
+
+    // Only in the ASN database.
+    let google = geoip.lookup("8.8.8.8").expect("should be found");
+    assert_eq!(google.asn, Some(15169));
+    assert_eq!(google.country_code, None);
+
+    let mut metric = Metric {
+        snort_src_address: Some("10.0.0.1".to_string()),
+        snort_dst_address: Some("8.8.8.8".to_string()),
+        ..Default::default()
+    };
+    geoip.enrich(&mut metric);
+    // Private addresses are in neither database.
+    assert_eq!(metric.event_src_geo, None);
+    assert_eq!(metric.event_dst_geo.and_then(|geo| geo.asn), Some(15169));
+    assert_eq!(geoip.get_total_lookups(), 4);
+    assert_eq!(geoip.get_total_cache_hits(), 1);
+
+    // Only the configured database is used.
+    let asn_only = GeoIp::load(None, Some(&asn)).expect("should load");
+    let location = asn_only.lookup("81.2.69.160").expect("should be found");
+    assert_eq!(location.city, None);
+    assert_eq!(location.asn, Some(20712));
+
+    fs::remove_dir_all(city.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn fails_to_load_a_missing_database() {
+    let missing = std::env::temp_dir().join("geoip-missing.mmdb");
+    assert!(GeoIp::load(Some(&missing), None).is_err());
+    assert!(GeoIp::load(None, Some(&missing)).is_err());
+    assert!(GeoIp::load(None, None).unwrap().lookup("8.8.8.8").is_none());
+}
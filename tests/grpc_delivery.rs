@@ -0,0 +1,539 @@
+use prost::Message;
+use sensor_suricata_service_rust::client::{Batch, BatchChannels, Client, Outbox, Receipt};
+use sensor_suricata_service_rust::compression::{Compression, Compressor};
+use sensor_suricata_service_rust::pb::sensor_service_server::{SensorService, SensorServiceServer};
+use sensor_suricata_service_rust::pb::{BatchAck, SensorEvent, SensorEventBatch, SequencedBatch};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt};
+use tonic::codec::CompressionEncoding;
+use tonic::{Request, Response, Status, Streaming};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Default)]
+struct State {
+    // Calls left unimplemented, like on an older server
+    without_acks: bool,
+    without_batches: bool,
+    // Request compression the server accepts
+    accept: Vec<CompressionEncoding>,
+    ack: AtomicBool,
+    // Fails the acknowledged call once this many batches arrived; 0 never
+    drop_after: AtomicUsize,
+    sequences: Mutex<Vec<u64>>,
+    streams: Mutex<Vec<String>>,
+    events: Mutex<Vec<String>>,
+    // Events per `StreamBatches` message
+    batch_sizes: Mutex<Vec<usize>>,
+    // Encoded size of each `StreamDataAcked` message
+    message_bytes: Mutex<Vec<usize>>,
+}
+
+#[derive(Clone, Default)]
+struct MockServer(Arc<State>);
+
+#[tonic::async_trait]
+impl SensorService for MockServer {
+    async fn stream_data(
+        &self,
+        request: Request<Streaming<SensorEvent>>,
+    ) -> Result<Response<()>, Status> {
+        let mut stream = request.into_inner();
+        while let Some(event) = stream.message().await? {
+            self.0.events.lock().unwrap().push(event.event_hash_sha256);
+        }
+        Ok(Response::new(()))
+    }
+
+    async fn stream_batches(
+        &self,
+        request: Request<Streaming<SensorEventBatch>>,
+    ) -> Result<Response<()>, Status> {
+        if self.0.without_batches {
+            return Err(Status::unimplemented("StreamBatches"));
+        }
+        let mut stream = request.into_inner();
+        while let Some(batch) = stream.message().await? {
+            self.0.batch_sizes.lock().unwrap().push(batch.events.len());
+            for event in batch.events {
+                self.0.events.lock().unwrap().push(event.event_hash_sha256);
+            }
+        }
+        Ok(Response::new(()))
+    }
+
+    type StreamDataAckedStream = Pin<Box<dyn Stream<Item = Result<BatchAck, Status>> + Send>>;
+
+    async fn stream_data_acked(
+        &self,
+        request: Request<Streaming<SequencedBatch>>,
+    ) -> Result<Response<Self::StreamDataAckedStream>, Status> {
+        if self.0.without_acks {
+            return Err(Status::unimplemented("StreamDataAcked"));
+        }
+        let state = self.0.clone();
+        let mut stream = request.into_inner();
+        let acks = async_stream::stream! {
+            while let Some(batch) = stream.next().await {
+                let batch = batch?;
+                state.message_bytes.lock().unwrap().push(batch.encoded_len());
+                let received = {
+                    let mut sequences = state.sequences.lock().unwrap();
+                    sequences.push(batch.sequence);
+                    state.streams.lock().unwrap().push(batch.stream_id);
+                    sequences.len()
+                };
+                for event in batch.events {
+                    state.events.lock().unwrap().push(event.event_hash_sha256);
+                }
+                if received == state.drop_after.load(Ordering::Relaxed) {
+                    yield Err(Status::unavailable("connection dropped"));
+                    break;
+                }
+                if state.ack.load(Ordering::Relaxed) {
+                    yield Ok(BatchAck { sequence: batch.sequence });
+                }
+            }
+        };
+        Ok(Response::new(Box::pin(acks)))
+    }
+}
+
+async fn serve(state: &Arc<State>) -> u16 {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let incoming = async_stream::stream! {
+        loop {
+            yield listener.accept().await.map(|(stream, _)| stream);
+        }
+    };
+    let mut service = SensorServiceServer::new(MockServer(state.clone()));
+    for encoding in &state.accept {
+        service = service.accept_compressed(*encoding);
+    }
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_incoming(incoming),
+    );
+    port
+}
+
+fn batch(hashes: &[&str]) -> Vec<SensorEvent> {
+    hashes
+        .iter()
+        .map(|hash| SensorEvent {
+            event_hash_sha256: hash.to_string(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+async fn send(port: u16, outbox: &Arc<Outbox>, batches: Vec<impl Into<Batch>>) {
+    let compressor = Arc::new(Compressor::new(Compression::None));
+    try_send(port, outbox, batches, 0, &compressor)
+        .await
+        .unwrap();
+}
+
+/// Streams the given batches on a fresh connection until they are all sent.
+async fn try_send(
+    port: u16,
+    outbox: &Arc<Outbox>,
+    batches: Vec<impl Into<Batch>>,
+    message_max_bytes: usize,
+    compressor: &Arc<Compressor>,
+) -> Result<(), Error> {
+    let (tx, rx) = mpsc::channel(16);
+    for batch in batches {
+        tx.send(batch.into()).await.unwrap();
+    }
+    drop(tx);
+    let (_, express_rx) = mpsc::channel(1);
+    let mut client = Client::new(
+        "127.0.0.1",
+        port,
+        true,
+        message_max_bytes,
+        compressor.clone(),
+    )
+    .await?;
+    let rx = Arc::new(tokio::sync::Mutex::new(BatchChannels::new(express_rx, rx)));
+    client.stream_data(rx, outbox.clone()).await
+}
+
+#[tokio::test]
+async fn acknowledged_batches_are_released() {
+    let state = Arc::new(State {
+        ack: AtomicBool::new(true),
+        ..Default::default()
+    });
+    let port = serve(&state).await;
+    let outbox = Arc::new(Outbox::new("sensor1", 0));
+
+    send(
+        port,
+        &outbox,
+        vec![batch(&["a", "b"]), batch(&["c"]), batch(&["d"])],
+    )
+    .await;
+
+    assert_eq!(*state.sequences.lock().unwrap(), [1, 2, 3]);
+    assert_eq!(outbox.get_total_acked(), 3);
+    assert_eq!(outbox.get_unacked_batches(), 0);
+    assert_eq!(outbox.get_total_resent(), 0);
+}
+
+#[tokio::test]
+async fn unacknowledged_batches_are_resent_after_reconnect() {
+    let state = Arc::new(State::default());
+    let port = serve(&state).await;
+    let outbox = Arc::new(Outbox::new("sensor1", 0));
+
+    send(port, &outbox, vec![batch(&["a"]), batch(&["b"])]).await;
+    assert_eq!(outbox.get_unacked_batches(), 2);
+
+    // The next connection sends them again under their original numbers.
+    state.ack.store(true, Ordering::Relaxed);
+    send(port, &outbox, vec![batch(&["c"])]).await;
+
+    assert_eq!(*state.sequences.lock().unwrap(), [1, 2, 1, 2, 3]);
+    assert_eq!(*state.events.lock().unwrap(), ["a", "b", "a", "b", "c"]);
+    assert_eq!(outbox.get_total_resent(), 2);
+    assert_eq!(outbox.get_unacked_batches(), 0);
+}
+
+#[tokio::test]
+async fn receipts_are_delivered_once_acknowledged() {
+    let state = Arc::new(State::default());
+    let port = serve(&state).await;
+    let outbox = Arc::new(Outbox::new("sensor1", 0));
+    let receipts = [Receipt::new(), Receipt::new()];
+    let batches = |receipts: &[Receipt; 2]| {
+        vec![
+            Batch {
+                events: batch(&["a"]),
+                receipt: Some(receipts[0].clone()),
+            },
+            Batch {
+                events: batch(&["b"]),
+                receipt: Some(receipts[1].clone()),
+            },
+        ]
+    };
+
+    send(port, &outbox, batches(&receipts)).await;
+    assert!(receipts.iter().all(|r| !r.is_delivered()));
+
+    // Resent batches keep their receipts.
+    state.ack.store(true, Ordering::Relaxed);
+    send(port, &outbox, Vec::<Batch>::new()).await;
+    assert!(receipts.iter().all(|r| r.is_delivered()));
+
+    // Without acknowledgements a batch counts as delivered once it was sent.
+    let state = Arc::new(State {
+        without_acks: true,
+        ..Default::default()
+    });
+    let port = serve(&state).await;
+    let outbox = Arc::new(Outbox::new("sensor1", 0));
+    let receipts = [Receipt::new(), Receipt::new()];
+    send(port, &outbox, batches(&receipts)).await;
+    assert!(receipts.iter().all(|r| r.is_delivered()));
+}
+
+#[tokio::test]
+async fn each_process_numbers_batches_in_its_own_stream() {
+    let state = Arc::new(State {
+        ack: AtomicBool::new(true),
+        ..Default::default()
+    });
+    let port = serve(&state).await;
+
+    // A restarted client numbers its batches from 1 again, under a new
+    // stream id, so they are not mistaken for duplicates.
+    let first = Arc::new(Outbox::new("sensor1", 0));
+    send(port, &first, vec![batch(&["a"]), batch(&["b"])]).await;
+    let restarted = Arc::new(Outbox::new("sensor1", 0));
+    send(port, &restarted, vec![batch(&["c"])]).await;
+
+    assert!(first.stream_id().starts_with("sensor1-"));
+    assert_ne!(first.stream_id(), restarted.stream_id());
+    let received: Vec<(String, u64)> = state
+        .streams
+        .lock()
+        .unwrap()
+        .iter()
+        .cloned()
+        .zip(state.sequences.lock().unwrap().iter().copied())
+        .collect();
+    assert_eq!(
+        received,
+        [
+            (first.stream_id().to_string(), 1),
+            (first.stream_id().to_string(), 2),
+            (restarted.stream_id().to_string(), 1),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn keeps_batches_when_the_connection_drops_with_a_full_outbox() {
+    // Without acknowledgements the outbox fills up after 1000 batches, and
+    // the server then drops the call.
+    let state = Arc::new(State {
+        drop_after: AtomicUsize::new(1000),
+        ..Default::default()
+    });
+    let port = serve(&state).await;
+    let outbox = Arc::new(Outbox::new("sensor1", 0));
+
+    let (tx, rx) = mpsc::channel(1010);
+    for i in 0..1010 {
+        tx.send(batch(&[&i.to_string()]).into()).await.unwrap();
+    }
+    drop(tx);
+    let (_, express_rx) = mpsc::channel(1);
+    let rx = Arc::new(tokio::sync::Mutex::new(BatchChannels::new(express_rx, rx)));
+    let compressor = Arc::new(Compressor::new(Compression::None));
+    let mut client = Client::new("127.0.0.1", port, true, 0, compressor.clone())
+        .await
+        .unwrap();
+    assert!(client
+        .stream_data(rx.clone(), outbox.clone())
+        .await
+        .is_err());
+    assert!(outbox.get_unacked_batches() >= 1000);
+
+    // Every batch arrives on the next connection.
+    state.drop_after.store(0, Ordering::Relaxed);
+    state.ack.store(true, Ordering::Relaxed);
+    let mut client = Client::new("127.0.0.1", port, true, 0, compressor)
+        .await
+        .unwrap();
+    client.stream_data(rx, outbox.clone()).await.unwrap();
+
+    let mut events = state.events.lock().unwrap().clone();
+    events.sort_by_key(|e| e.parse::<usize>().unwrap());
+    events.dedup();
+    assert_eq!(events.len(), 1010);
+    assert_eq!(outbox.get_unacked_batches(), 0);
+}
+
+#[tokio::test]
+async fn stops_reading_batches_at_the_unacknowledged_byte_limit() {
+    let state = Arc::new(State {
+        drop_after: AtomicUsize::new(1),
+        ..Default::default()
+    });
+    let port = serve(&state).await;
+    // Any one batch reaches the limit.
+    let outbox = Arc::new(Outbox::new("sensor1", 20));
+
+    let (tx, rx) = mpsc::channel(3);
+    for hash in ["a", "b", "c"] {
+        tx.send(batch(&[hash]).into()).await.unwrap();
+    }
+    drop(tx);
+    let (_, express_rx) = mpsc::channel(1);
+    let rx = Arc::new(tokio::sync::Mutex::new(BatchChannels::new(express_rx, rx)));
+    let compressor = Arc::new(Compressor::new(Compression::None));
+    let mut client = Client::new("127.0.0.1", port, true, 0, compressor.clone())
+        .await
+        .unwrap();
+    assert!(client
+        .stream_data(rx.clone(), outbox.clone())
+        .await
+        .is_err());
+    assert_eq!(outbox.get_unacked_batches(), 1);
+    assert!(outbox.get_unacked_bytes() >= 20);
+    assert_eq!(*state.events.lock().unwrap(), ["a"]);
+
+    // Acknowledgements make room for the rest.
+    state.drop_after.store(0, Ordering::Relaxed);
+    state.ack.store(true, Ordering::Relaxed);
+    let mut client = Client::new("127.0.0.1", port, true, 0, compressor)
+        .await
+        .unwrap();
+    client.stream_data(rx, outbox.clone()).await.unwrap();
+    assert_eq!(*state.events.lock().unwrap(), ["a", "a", "b", "c"]);
+    assert_eq!(outbox.get_unacked_bytes(), 0);
+}
+
+#[tokio::test]
+async fn falls_back_to_batch_messages_without_acks() {
+    let state = Arc::new(State {
+        without_acks: true,
+        ..Default::default()
+    });
+    let port = serve(&state).await;
+    let outbox = Arc::new(Outbox::new("sensor1", 0));
+
+    send(port, &outbox, vec![batch(&["a", "b"]), batch(&["c"])]).await;
+    send(port, &outbox, vec![batch(&["d"])]).await;
+
+    assert_eq!(*state.events.lock().unwrap(), ["a", "b", "c", "d"]);
+    assert_eq!(*state.batch_sizes.lock().unwrap(), [2, 1, 1]);
+    assert!(state.sequences.lock().unwrap().is_empty());
+    assert_eq!(outbox.get_unacked_batches(), 0);
+}
+
+#[tokio::test]
+async fn splits_batches_over_the_message_budget() {
+    let state = Arc::new(State {
+        without_acks: true,
+        ..Default::default()
+    });
+    let port = serve(&state).await;
+    let outbox = Arc::new(Outbox::new("sensor1", 0));
+
+    // The first batch is split for StreamDataAcked before the server turns
+    // out not to implement it.
+    send(port, &outbox, vec![batch(&["z"])]).await;
+
+    // Each event takes 5 bytes and 11 are reserved for a sequence number,
+    // leaving room for two events per message.
+    let compressor = Arc::new(Compressor::new(Compression::None));
+    let batches = vec![batch(&["a", "b", "c", "d", "e"])];
+    try_send(port, &outbox, batches, 25, &compressor)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        *state.events.lock().unwrap(),
+        ["z", "a", "b", "c", "d", "e"]
+    );
+    assert_eq!(*state.batch_sizes.lock().unwrap(), [1, 2, 2, 1]);
+
+    // Acknowledged messages also carry the 24-byte stream id, which takes 26
+    // bytes encoded.
+    let state = Arc::new(State {
+        ack: AtomicBool::new(true),
+        ..Default::default()
+    });
+    let port = serve(&state).await;
+    let outbox = Arc::new(Outbox::new("sensor1", 0));
+    let batches = vec![batch(&["a", "b", "c", "d", "e"])];
+    try_send(port, &outbox, batches, 50, &compressor)
+        .await
+        .unwrap();
+
+    assert_eq!(*state.events.lock().unwrap(), ["a", "b", "c", "d", "e"]);
+    let message_bytes = state.message_bytes.lock().unwrap();
+    assert_eq!(message_bytes.len(), 3);
+    assert!(
+        message_bytes.iter().all(|bytes| *bytes <= 50),
+        "{:?}",
+        message_bytes
+    );
+}
+
+#[tokio::test]
+async fn falls_back_to_plain_stream_on_older_servers() {
+    let state = Arc::new(State {
+        without_acks: true,
+        without_batches: true,
+        ..Default::default()
+    });
+    let port = serve(&state).await;
+    let outbox = Arc::new(Outbox::new("sensor1", 0));
+
+    send(port, &outbox, vec![batch(&["a", "b"]), batch(&["c"])]).await;
+    send(port, &outbox, vec![batch(&["d"])]).await;
+
+    assert_eq!(*state.events.lock().unwrap(), ["a", "b", "c", "d"]);
+    assert!(state.batch_sizes.lock().unwrap().is_empty());
+    assert!(state.sequences.lock().unwrap().is_empty());
+    assert_eq!(outbox.get_unacked_batches(), 0);
+}
+
+fn alerts(count: usize) -> Vec<SensorEvent> {
+    (0..count)
+        .map(|i| SensorEvent {
+            event_hash_sha256: format!("{:064}", i),
+            snort_message: "ET POLICY Observed DNS Query to .cloud TLD".to_string(),
+            snort_classification: Some("Potentially Bad Traffic".to_string()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn counts_bytes_before_and_after_compression() {
+    for (compression, encoding) in [
+        (Compression::Gzip, CompressionEncoding::Gzip),
+        (Compression::Zstd, CompressionEncoding::Zstd),
+    ] {
+        let state = Arc::new(State {
+            accept: vec![encoding],
+            ack: AtomicBool::new(true),
+            ..Default::default()
+        });
+        let port = serve(&state).await;
+        let outbox = Arc::new(Outbox::new("sensor1", 0));
+        let compressor = Arc::new(Compressor::new(compression));
+
+        try_send(port, &outbox, vec![alerts(100)], 0, &compressor)
+            .await
+            .unwrap();
+
+        assert_eq!(state.events.lock().unwrap().len(), 100);
+        assert_eq!(compressor.current(), compression);
+        let (before, after) = (
+            compressor.get_total_uncompressed(),
+            compressor.get_total_compressed(),
+        );
+        assert!(after > 0 && after * 4 < before, "{} -> {}", before, after);
+    }
+
+    // Without compression both counts are the bytes on the wire.
+    let state = Arc::new(State::default());
+    let port = serve(&state).await;
+    let compressor = Arc::new(Compressor::new(Compression::None));
+    try_send(
+        port,
+        &Arc::new(Outbox::new("sensor1", 0)),
+        vec![alerts(10)],
+        0,
+        &compressor,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        compressor.get_total_uncompressed(),
+        compressor.get_total_compressed()
+    );
+}
+
+#[tokio::test]
+async fn renegotiates_compression_the_server_rejects() {
+    for (without_acks, without_batches) in [(false, false), (true, false), (true, true)] {
+        let state = Arc::new(State {
+            without_acks,
+            without_batches,
+            accept: vec![CompressionEncoding::Gzip],
+            ack: AtomicBool::new(true),
+            ..Default::default()
+        });
+        let port = serve(&state).await;
+        let outbox = Arc::new(Outbox::new("sensor1", 0));
+        let compressor = Arc::new(Compressor::new(Compression::Zstd));
+
+        // The rejected call fails; its batches go out on the next one.
+        assert!(
+            try_send(port, &outbox, vec![batch(&["a", "b"])], 0, &compressor)
+                .await
+                .is_err()
+        );
+        assert_eq!(compressor.current(), Compression::Gzip);
+        try_send(port, &outbox, vec![batch(&["c"])], 0, &compressor)
+            .await
+            .unwrap();
+
+        assert_eq!(*state.events.lock().unwrap(), ["a", "b", "c"]);
+        assert_eq!(outbox.get_unacked_batches(), 0);
+    }
+}
@@ -0,0 +1,129 @@
+use sensor_suricata_service_rust::ioc::IocSet;
+use sensor_suricata_service_rust::pb::Metric;
+use sensor_suricata_service_rust::types::SuricataAlert;
+use std::fs;
+
+#[test]
+fn matches_indicators_from_text_csv_and_stix_feeds() {
+    let dir = std::env::temp_dir().join(format!("ioc-feeds-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("blocklist.txt"),
+        "# botnet C2\n203.0.113.0/24\nevil.example.com\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("partner.csv"),
+        "indicator,confidence\n198.51.100.7,90\n44d88612fea8a8f36de82e1278abb02f,100\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("stix.json"),
+        r#"{
+            "type": "bundle",
+            "id": "bundle--1",
+            "objects": [
+                {
+                    "type": "indicator",
+                    "id": "indicator--1",
+                    "confidence": 75,
+                    "pattern": "[domain-name:value = 'bad.example.net' OR ipv4-addr:value = '192.0.2.44']",
+                    "pattern_type": "stix"
+                },
+                { "type": "malware", "id": "malware--1", "name": "x" }
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let iocs = IocSet::load(&dir).expect("should load");
+    assert_eq!(iocs.indicator_count(), 6);
+
+    let json = r#"{
+        "timestamp": "2025-12-15T07:46:41.123456+0000",
+        "event_type": "alert",
+        "src_ip": "203.0.113.50",
+        "dest_ip": "198.51.100.7",
+        "http": { "hostname": "cdn.evil.example.com" },
+        "tls": { "sni": "bad.example.net" },
+        "files": [ { "filename": "a.exe", "md5": "44D88612FEA8A8F36DE82E1278ABB02F" } ],
+        "alert": {
+            "action": "allowed",
+            "gid": 1,
+            "signature_id": 1,
+            "rev": 1,
+            "signature": "x",
+            "category": "x",
+            "severity": 1
+        }
+    }"#;
+    let mut bytes = json.as_bytes().to_vec();
+    let alert: SuricataAlert = simd_json::from_slice(&mut bytes).expect("should parse");
+
+    let mut metric = Metric::default();
+    iocs.match_alert(&alert, &mut metric);
+
+    let hits: Vec<_> = metric
+        .event_ioc_matches
+        .iter()
+        .map(|m| {
+            (
+                m.field.as_str(),
+                m.feed.as_str(),
+                m.indicator.as_str(),
+                m.confidence,
+            )
+        })
+        .collect();
+    assert_eq!(
+        hits,
+        vec![
+            ("src_ip", "blocklist", "203.0.113.50", 50),
+            ("dest_ip", "partner", "198.51.100.7", 90),
+            ("http_hostname", "blocklist", "evil.example.com", 50),
+            ("tls_sni", "stix", "bad.example.net", 75),
+            (
+                "file_hash",
+                "partner",
+                "44D88612FEA8A8F36DE82E1278ABB02F",
+                100
+            ),
+        ]
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn keeps_overlapping_networks_apart() {
+    let dir = std::env::temp_dir().join(format!("ioc-overlap-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.csv"), "10.0.0.0/24,90\n10.0.0.0/8,50\n").unwrap();
+    // The same prefix from a later feed only wins with a higher confidence.
+    fs::write(dir.join("b.csv"), "10.0.0.0/8,70\n10.0.0.0/24,80\n").unwrap();
+
+    let iocs = IocSet::load(&dir).expect("should load");
+    assert_eq!(iocs.indicator_count(), 2);
+
+    let hit = |ip: &str| {
+        let json = format!(
+            r#"{{"timestamp":"2025-12-15T07:46:41.123456+0000","event_type":"alert","src_ip":"{}",
+                "alert":{{"action":"allowed","gid":1,"signature_id":1,"rev":1,"signature":"x","category":"x","severity":1}}}}"#,
+            ip
+        );
+        let mut bytes = json.into_bytes();
+        let alert: SuricataAlert = simd_json::from_slice(&mut bytes).expect("should parse");
+        let mut metric = Metric::default();
+        iocs.match_alert(&alert, &mut metric);
+        metric
+            .event_ioc_matches
+            .iter()
+            .map(|m| (m.feed.clone(), m.confidence))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(hit("10.0.0.5"), vec![("a".to_string(), 90)]);
+    assert_eq!(hit("10.5.5.5"), vec![("b".to_string(), 70)]);
+    assert_eq!(hit("11.0.0.1"), vec![]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,23 +1,23 @@
-use sensor_suricata_service_rust::types;
+use sensor_suricata_service_rust::{processor, types};
 
 #[test]
 fn parses_suricata_eve_without_top_level_metadata() {
     // Real Suricata EVE JSON lines typically do NOT have our internal `metadata` field.
     let json = r#"{
-        \"timestamp\": \"2025-12-15T07:46:41.123456+0000\",
-        \"event_type\": \"alert\",
-        \"src_ip\": \"1.2.3.4\",
-        \"dest_ip\": \"5.6.7.8\",
-        \"proto\": \"TCP\",
-        \"in_iface\": \"eth0\",
-        \"alert\": {
-            \"action\": \"allowed\",
-            \"gid\": 1,
-            \"signature_id\": 2100498,
-            \"rev\": 9,
-            \"signature\": \"GPL ATTACK_RESPONSE id check returned root\",
-            \"category\": \"Potentially Bad Traffic\",
-            \"severity\": 2
+        "timestamp": "2025-12-15T07:46:41.123456+0000",
+        "event_type": "alert",
+        "src_ip": "1.2.3.4",
+        "dest_ip": "5.6.7.8",
+        "proto": "TCP",
+        "in_iface": "eth0",
+        "alert": {
+            "action": "allowed",
+            "gid": 1,
+            "signature_id": 2100498,
+            "rev": 9,
+            "signature": "GPL ATTACK_RESPONSE id check returned root",
+            "category": "Potentially Bad Traffic",
+            "severity": 2
         }
     }"#;
 
@@ -32,24 +32,24 @@ fn parses_suricata_eve_without_top_level_metadata() {
 #[test]
 fn parses_suricata_eve_with_metadata_present() {
     let json = r#"{
-        \"metadata\": {
-            \"sensor_id\": \"sensor-x\",
-            \"sensor_version\": \"1.0.0\",
-            \"sent_at\": 123,
-            \"hash_sha256\": \"abc\",
-            \"read_at\": 456,
-            \"received_at\": 789
+        "metadata": {
+            "sensor_id": "sensor-x",
+            "sensor_version": "1.0.0",
+            "sent_at": 123,
+            "hash_sha256": "abc",
+            "read_at": 456,
+            "received_at": 789
         },
-        \"timestamp\": \"2025-12-15T07:46:41.123456+0000\",
-        \"event_type\": \"alert\",
-        \"alert\": {
-            \"action\": \"allowed\",
-            \"gid\": 1,
-            \"signature_id\": 1,
-            \"rev\": 1,
-            \"signature\": \"x\",
-            \"category\": \"x\",
-            \"severity\": 1
+        "timestamp": "2025-12-15T07:46:41.123456+0000",
+        "event_type": "alert",
+        "alert": {
+            "action": "allowed",
+            "gid": 1,
+            "signature_id": 1,
+            "rev": 1,
+            "signature": "x",
+            "category": "x",
+            "severity": 1
         }
     }"#;
 
@@ -60,3 +60,42 @@ fn parses_suricata_eve_with_metadata_present() {
     assert_eq!(alert.metadata.sensor_version, "1.0.0");
     assert_eq!(alert.metadata.sent_at, 123);
 }
+
+#[test]
+fn maps_alert_target_to_snort_target_and_attacker_roles() {
+    // Response-direction alert: the packet flows from the victim (src_ip) to the attacker.
+    let json = r#"{
+        "timestamp": "2025-12-15T07:46:41.123456+0000",
+        "event_type": "alert",
+        "src_ip": "10.0.0.5",
+        "src_port": 80,
+        "dest_ip": "203.0.113.9",
+        "dest_port": 51515,
+        "proto": "TCP",
+        "alert": {
+            "action": "allowed",
+            "gid": 1,
+            "signature_id": 2100498,
+            "rev": 9,
+            "signature": "GPL ATTACK_RESPONSE id check returned root",
+            "category": "Potentially Bad Traffic",
+            "severity": 2,
+            "source": { "ip": "203.0.113.9", "port": 51515 },
+            "target": { "ip": "10.0.0.5", "port": 80 }
+        }
+    }"#;
+
+    let mut bytes = json.as_bytes().to_vec();
+    let alert: types::SuricataAlert = simd_json::from_slice(&mut bytes).expect("should parse");
+    let (_, mut metric) =
+        processor::convert_suricata_alert_to_sensor_event(&alert).expect("is an alert");
+
+    assert_eq!(metric.snort_target.as_deref(), Some("10.0.0.5:80"));
+    assert_eq!(metric.snort_src_address.as_deref(), Some("10.0.0.5"));
+
+    processor::apply_target_roles(&alert, &mut metric);
+    assert_eq!(metric.snort_src_address.as_deref(), Some("203.0.113.9"));
+    assert_eq!(metric.snort_src_ap.as_deref(), Some("203.0.113.9:51515"));
+    assert_eq!(metric.snort_dst_address.as_deref(), Some("10.0.0.5"));
+    assert_eq!(metric.snort_dst_port, Some(80));
+}
@@ -0,0 +1,72 @@
+use sensor_suricata_service_rust::pb::Metric;
+use sensor_suricata_service_rust::pdns::PassiveDns;
+use sensor_suricata_service_rust::types::SuricataAlert;
+
+fn parse(json: &str) -> SuricataAlert {
+    let mut bytes = json.as_bytes().to_vec();
+    simd_json::from_slice(&mut bytes).expect("should parse")
+}
+
+#[test]
+fn annotates_addresses_from_dns_answers() {
+    let pdns = PassiveDns::new(2);
+
+    // EVE v2 answer with CNAME chain: addresses map to the queried name.
+    pdns.observe(&parse(
+        r#"{"timestamp":"2024-01-01T00:00:00.000000+0000","event_type":"dns","dns":{"version":2,"type":"answer","rrname":"WWW.Example.com.","answers":[
+            {"rrname":"www.example.com","rrtype":"CNAME","rdata":"edge.example.net","ttl":60},
+            {"rrname":"edge.example.net","rrtype":"A","rdata":"203.0.113.10","ttl":60},
+            {"rrname":"edge.example.net","rrtype":"AAAA","rdata":"2001:db8::10","ttl":60}]}}"#,
+    ));
+    // Queries and non-dns events are ignored.
+    pdns.observe(&parse(
+        r#"{"timestamp":"2024-01-01T00:00:00.000000+0000","event_type":"dns","dns":{"type":"query","rrname":"other.example","rrtype":"A"}}"#,
+    ));
+    assert_eq!(pdns.get_cache_size(), 2);
+    assert_eq!(pdns.get_total_answers(), 2);
+
+    let mut metric = Metric {
+        snort_src_address: Some("10.0.0.5".to_string()),
+        snort_dst_address: Some("203.0.113.10".to_string()),
+        ..Default::default()
+    };
+    pdns.annotate(&mut metric);
+    assert!(metric.event_src_hostnames.is_empty());
+    assert_eq!(metric.event_dst_hostnames, vec!["www.example.com"]);
+}
+
+#[test]
+fn evicts_the_soonest_expiring_address_when_full() {
+    let pdns = PassiveDns::new(2);
+    let answer = |name: &str, ip: &str, ttl: u32| {
+        parse(&format!(
+            r#"{{"timestamp":"2024-01-01T00:00:00.000000+0000","event_type":"dns","dns":{{"type":"answer","rrname":"{}","rrtype":"A","rdata":"{}","ttl":{}}}}}"#,
+            name, ip, ttl
+        ))
+    };
+    let hostnames = |ip: &str| {
+        let mut metric = Metric {
+            snort_src_address: Some(ip.to_string()),
+            ..Default::default()
+        };
+        pdns.annotate(&mut metric);
+        metric.event_src_hostnames
+    };
+
+    pdns.observe(&answer("long.example", "192.0.2.1", 3600));
+    pdns.observe(&answer("short.example", "192.0.2.2", 600));
+    // Known addresses are updated in place without evicting anything.
+    pdns.observe(&answer("alias.example", "192.0.2.1", 3600));
+    assert_eq!(pdns.get_total_evicted(), 0);
+
+    // A new address makes room by evicting the one expiring soonest.
+    pdns.observe(&answer("new.example", "192.0.2.3", 1200));
+    assert_eq!(pdns.get_cache_size(), 2);
+    assert_eq!(pdns.get_total_evicted(), 1);
+    assert!(hostnames("192.0.2.2").is_empty());
+    assert_eq!(
+        hostnames("192.0.2.1"),
+        vec!["long.example", "alias.example"]
+    );
+    assert_eq!(hostnames("192.0.2.3"), vec!["new.example"]);
+}
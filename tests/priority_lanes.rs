@@ -0,0 +1,92 @@
+use sensor_suricata_service_rust::client::BatchChannels;
+use sensor_suricata_service_rust::pb::{IocMatch, Metric, SensorEvent};
+use sensor_suricata_service_rust::priority::{PriorityLane, PriorityRule};
+use sensor_suricata_service_rust::queue::{EventBatchQueue, FlushPolicy, MemoryLimits};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+fn event(hash: &str, sid: i64, priority: i64) -> SensorEvent {
+    SensorEvent {
+        event_hash_sha256: hash.to_string(),
+        event_metrics_count: 1,
+        snort_rule_sid: sid,
+        snort_priority: priority,
+        metrics: vec![Metric::default()],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn matches_severity_sids_and_ioc_hits() {
+    let rule = PriorityRule::new(1, " 2019401, 2024897 ,", true).unwrap();
+    assert!(rule.is_enabled());
+    assert!(rule.matches(&event("a", 1000, 1)));
+    assert!(!rule.matches(&event("a", 1000, 2)));
+    assert!(rule.matches(&event("a", 2024897, 3)));
+
+    let mut ioc_hit = event("a", 1000, 3);
+    ioc_hit.metrics[0]
+        .event_ioc_matches
+        .push(IocMatch::default());
+    assert!(rule.matches(&ioc_hit));
+
+    // An unset severity does not match.
+    assert!(!rule.matches(&event("a", 1000, 0)));
+
+    assert!(!PriorityRule::new(0, "", false).unwrap().is_enabled());
+    assert!(PriorityRule::new(0, "2019401,abc", false).is_err());
+}
+
+#[test]
+fn routes_matching_events_to_the_express_queue() {
+    let queue = EventBatchQueue::new(FlushPolicy::default(), MemoryLimits::default(), 1);
+    let lane = PriorityLane::new(
+        PriorityRule::new(1, "", false).unwrap(),
+        EventBatchQueue::new(FlushPolicy::default(), MemoryLimits::default(), 1),
+    );
+    for event in [event("a", 1000, 1), event("b", 1001, 3)] {
+        if let Some(event) = lane.add(event) {
+            queue.add(event);
+        }
+    }
+    assert_eq!(lane.get_total_routed(), 1);
+    assert_eq!(lane.queue().process_batch()[0].event_hash_sha256, "a");
+    assert_eq!(queue.process_batch()[0].event_hash_sha256, "b");
+}
+
+#[tokio::test]
+async fn takes_express_batches_first() {
+    let (express_tx, express_rx) = mpsc::channel(4);
+    let (regular_tx, regular_rx) = mpsc::channel(4);
+    let mut channels = BatchChannels::new(express_rx, regular_rx);
+
+    // Both were queued while disconnected.
+    regular_tx
+        .send(vec![event("regular", 1, 3)].into())
+        .await
+        .unwrap();
+    express_tx
+        .send(vec![event("express", 2, 1)].into())
+        .await
+        .unwrap();
+    let next = channels.recv().await.unwrap();
+    assert_eq!(next.events[0].event_hash_sha256, "express");
+    let next = channels.recv().await.unwrap();
+    assert_eq!(next.events[0].event_hash_sha256, "regular");
+
+    // A closed express channel does not end the stream.
+    drop(express_tx);
+    regular_tx
+        .send(vec![event("later", 1, 3)].into())
+        .await
+        .unwrap();
+    let next = channels.recv().await.unwrap();
+    assert_eq!(next.events[0].event_hash_sha256, "later");
+    drop(regular_tx);
+    assert!(
+        tokio::time::timeout(Duration::from_secs(1), channels.recv())
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
@@ -0,0 +1,130 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use sensor_suricata_service_rust::pb::Metric;
+use sensor_suricata_service_rust::privacy::{PrivacyCounters, PrivacyPolicy};
+use sensor_suricata_service_rust::types::SuricataAlert;
+use std::fs;
+
+fn alert(category: &str, payload: &str, url: &str) -> SuricataAlert {
+    let json = format!(
+        r#"{{
+            "timestamp": "2025-12-15T07:46:41.123456+0000",
+            "event_type": "alert",
+            "payload": "{}",
+            "http": {{ "hostname": "example.com", "url": "{}" }},
+            "files": [{{ "filename": "/export/alice@example.com.csv" }}],
+            "alert": {{
+                "action": "allowed",
+                "gid": 1,
+                "signature_id": 1,
+                "rev": 1,
+                "signature": "x",
+                "category": "{}",
+                "severity": 1
+            }}
+        }}"#,
+        BASE64.encode(payload),
+        url,
+        category
+    );
+    let mut bytes = json.into_bytes();
+    simd_json::from_slice(&mut bytes).expect("should parse")
+}
+
+#[test]
+fn applies_policy_by_category() {
+    let path = std::env::temp_dir().join(format!("privacy-{}.toml", std::process::id()));
+    fs::write(
+        &path,
+        r#"
+[patterns]
+session = 'sid=[0-9a-f]+'
+
+[default]
+payload = "truncate"
+truncate_bytes = 24
+redact = ["credentials", "email"]
+url = "redact"
+
+[[policies]]
+category = ["Potential Corporate Privacy Violation"]
+payload = "hash"
+url = "strip"
+filename = "strip"
+"#,
+    )
+    .unwrap();
+    let policy = PrivacyPolicy::load(&path).expect("should load");
+    let counters = PrivacyCounters::new();
+
+    let mut data = alert(
+        "Attempted Information Leak",
+        "POST /login password=hunter2 from bob@example.org trailing bytes",
+        "/login?user=bob&password=hunter2",
+    );
+    let mut metric = Metric::default();
+    counters.redact(&policy, &mut data).mark(&mut metric);
+    let payload = BASE64.decode(data.payload.unwrap()).unwrap();
+    assert_eq!(payload, b"POST /login [REDACTED] f");
+    assert_eq!(
+        data.http.unwrap().url.as_deref(),
+        Some("/login?user=bob&[REDACTED]")
+    );
+    // Filenames are kept by the default policy.
+    assert!(data.files.unwrap()[0].filename.is_some());
+    assert_eq!(metric.event_redacted_fields, vec!["payload", "http_url"]);
+    assert!(metric.event_payload_sha256.is_none());
+
+    let mut data = alert(
+        "potential corporate privacy violation",
+        "GET /",
+        "/index.html",
+    );
+    let mut metric = Metric::default();
+    counters.redact(&policy, &mut data).mark(&mut metric);
+    assert!(data.payload.is_none());
+    assert!(data.http.unwrap().url.is_none());
+    assert!(data.files.unwrap()[0].filename.is_none());
+    assert_eq!(
+        metric.event_payload_sha256.as_deref(),
+        Some("c767025d0edc7a064cf0003cc4d5a2f5f9e013c608f7a6909554afcdb1126fb2")
+    );
+
+    let count = |policy: &PrivacyPolicy, name: &str| {
+        let counters = counters.get_counters(policy);
+        counters.iter().find(|(n, _)| n == name).map(|(_, c)| *c)
+    };
+    assert_eq!(counters.get_total_redacted(), 2);
+    assert_eq!(count(&policy, "payload_truncated"), Some(1));
+    assert_eq!(count(&policy, "payload_hashed"), Some(1));
+    assert_eq!(count(&policy, "pattern_credentials"), Some(2));
+    assert_eq!(count(&policy, "pattern_email"), Some(1));
+    assert_eq!(count(&policy, "pattern_session"), Some(0));
+
+    // Counters carry over to a reloaded policy, pattern counters by name.
+    fs::write(&path, "[default]\nredact = [\"email\"]\n").unwrap();
+    let policy = PrivacyPolicy::load(&path).expect("should load");
+    let mut data = alert("Misc activity", "to bob@example.org", "/");
+    counters.redact(&policy, &mut data);
+    assert_eq!(counters.get_total_redacted(), 3);
+    assert_eq!(count(&policy, "payload_truncated"), Some(1));
+    assert_eq!(count(&policy, "pattern_email"), Some(2));
+    assert_eq!(count(&policy, "pattern_credentials"), Some(2));
+    assert_eq!(count(&policy, "pattern_session"), None);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn rejects_truncation_without_a_size() {
+    let path = std::env::temp_dir().join(format!("privacy-truncate-{}.toml", std::process::id()));
+    for policy in [
+        "[default]\npayload = \"truncate\"\n",
+        "[[policies]]\ncategory = [\"Misc activity\"]\npayload = \"truncate\"\ntruncate_bytes = 0\n",
+    ] {
+        fs::write(&path, policy).unwrap();
+        let error = PrivacyPolicy::load(&path).err().expect("should be rejected");
+        assert!(error.to_string().contains("truncate_bytes"), "{}", error);
+    }
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,189 @@
+use sensor_suricata_service_rust::pb::{Metric, SensorEvent};
+use sensor_suricata_service_rust::queue::{
+    EventBatchQueue, FlushPolicy, MemoryLimits, OverflowPolicy,
+};
+use std::time::Duration;
+
+fn event(hash: &str, sid: i64, priority: i64, timestamp: &str) -> SensorEvent {
+    SensorEvent {
+        event_hash_sha256: hash.to_string(),
+        event_metrics_count: 1,
+        snort_rule_gid: 1,
+        snort_rule_sid: sid,
+        snort_rule_rev: 1,
+        snort_priority: priority,
+        metrics: vec![Metric {
+            snort_timestamp: timestamp.to_string(),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+// One shard so that the caps apply to the whole queue.
+fn queue(limits: MemoryLimits) -> EventBatchQueue {
+    let policy = FlushPolicy {
+        interval: Duration::from_secs(60),
+        ..Default::default()
+    };
+    EventBatchQueue::new(policy, limits, 1)
+}
+
+#[test]
+fn evicts_oldest_records() {
+    let queue = queue(MemoryLimits {
+        max_records: 2,
+        ..Default::default()
+    });
+    for hash in ["a", "b", "c"] {
+        queue.add(event(hash, 1001, 2, "t1"));
+    }
+    assert_eq!(queue.get_queue_size(), 2);
+    assert_eq!(queue.get_total_evicted(), 1);
+    assert_eq!(queue.get_total_dropped_metrics(), 1);
+
+    // Merging into a queued record needs no room.
+    queue.add(event("b", 1001, 2, "t2"));
+    assert_eq!(queue.get_total_evicted(), 1);
+}
+
+#[test]
+fn evicts_lowest_severity_first() {
+    let queue = queue(MemoryLimits {
+        max_records: 2,
+        overflow: OverflowPolicy::EvictLowestSeverity,
+        ..Default::default()
+    });
+    queue.add(event("high", 1001, 1, "t1"));
+    queue.add(event("low", 1002, 3, "t1"));
+    queue.add(event("medium", 1003, 2, "t1"));
+    // Less severe than everything queued, so it is the one dropped.
+    queue.add(event("lowest", 1004, 4, "t1"));
+
+    assert_eq!(queue.get_total_evicted(), 1);
+    assert_eq!(queue.get_total_dropped_metrics(), 2);
+    assert_eq!(queue.get_queue_size(), 2);
+}
+
+#[test]
+fn collapses_into_signature_summaries() {
+    let queue = queue(MemoryLimits {
+        max_records: 1,
+        overflow: OverflowPolicy::Summarize,
+        ..Default::default()
+    });
+    queue.add(event("a", 1001, 2, "t1"));
+    for hash in ["b", "c", "d"] {
+        queue.add(event(hash, 1002, 2, "t2"));
+    }
+    assert_eq!(queue.get_total_summarized(), 3);
+    assert_eq!(queue.get_queue_size(), 2);
+}
+
+#[test]
+fn caps_metrics_per_record() {
+    for (overflow, kept) in [
+        (OverflowPolicy::EvictOldest, ["t3", "t4"]),
+        (OverflowPolicy::Summarize, ["t1", "t2"]),
+    ] {
+        let queue = EventBatchQueue::new(
+            FlushPolicy::default(),
+            MemoryLimits {
+                max_record_metrics: 2,
+                overflow,
+                ..Default::default()
+            },
+            1,
+        );
+        for timestamp in ["t1", "t2", "t3", "t4"] {
+            queue.add(event("a", 1001, 2, timestamp));
+        }
+        let batch = queue.process_batch();
+        assert_eq!(batch.len(), 1);
+        let timestamps: Vec<&str> = batch[0]
+            .metrics
+            .iter()
+            .map(|m| m.snort_timestamp.as_str())
+            .collect();
+        assert_eq!(timestamps, kept);
+        assert_eq!(batch[0].event_metrics_count, 2);
+        assert_eq!(batch[0].event_dropped_metrics, 2);
+        assert_eq!(queue.get_total_dropped_metrics(), 2);
+
+        // An event that arrives over the cap is trimmed on insertion, adding
+        // to the metrics it already dropped.
+        let mut merged = event("b", 1002, 2, "t1");
+        merged.metrics = [batch[0].metrics.clone(), batch[0].metrics.clone()].concat();
+        merged.event_metrics_count = 4;
+        merged.event_dropped_metrics = 1;
+        queue.add(merged);
+        let batch = queue.process_batch();
+        assert_eq!(batch[0].metrics.len(), 2);
+        assert_eq!(batch[0].event_metrics_count, 2);
+        assert_eq!(batch[0].event_dropped_metrics, 3);
+        assert_eq!(queue.get_total_dropped_metrics(), 4);
+    }
+}
+
+#[test]
+fn caps_queued_bytes() {
+    let queue = EventBatchQueue::new(
+        FlushPolicy::default(),
+        MemoryLimits {
+            max_bytes: 400,
+            overflow: OverflowPolicy::Summarize,
+            ..Default::default()
+        },
+        1,
+    );
+    for i in 0..20 {
+        queue.add(event(&format!("{:064}", i), 1001, 2, "2025-12-15T07:46:41"));
+    }
+    let batch = queue.process_batch();
+    let alerts: i64 = batch
+        .iter()
+        .map(|e| e.metrics.len() as i64 + e.event_dropped_metrics)
+        .sum();
+    assert_eq!(alerts, 20);
+    assert!(batch.len() < 20);
+    let summary = batch
+        .iter()
+        .find(|e| e.event_dropped_metrics > 0)
+        .expect("should summarize");
+    assert_eq!(summary.metrics.len(), 1);
+    assert_eq!(summary.snort_rule_sid, 1001);
+}
+
+#[test]
+fn flushes_records_added_again_after_eviction() {
+    let policy = FlushPolicy {
+        interval: Duration::from_millis(200),
+        ..Default::default()
+    };
+    let queue = EventBatchQueue::new(
+        policy,
+        MemoryLimits {
+            max_records: 1,
+            ..Default::default()
+        },
+        1,
+    );
+    // Each add evicts the other key, leaving its expiry entry behind.
+    for hash in ["a", "b", "a", "b", "a"] {
+        queue.add(event(hash, 1001, 2, "t1"));
+    }
+    assert_eq!(queue.get_total_evicted(), 4);
+
+    std::thread::sleep(Duration::from_millis(400));
+    let batch = queue.process_batch();
+    assert_eq!(batch.len(), 1);
+    assert_eq!(batch[0].event_hash_sha256, "a");
+    assert_eq!(batch[0].metrics.len(), 1);
+    assert_eq!(queue.get_queue_size(), 0);
+
+    // The key is flushed again after its own interval.
+    queue.add(event("b", 1001, 2, "t2"));
+    assert!(queue.process_batch().is_empty());
+    std::thread::sleep(Duration::from_millis(400));
+    assert_eq!(queue.process_batch().len(), 1);
+}
@@ -0,0 +1,72 @@
+use sensor_suricata_service_rust::pb::SensorEvent;
+use sensor_suricata_service_rust::rules::RuleSet;
+use std::fs;
+
+#[test]
+fn enriches_event_from_rules_directory() {
+    let dir = std::env::temp_dir().join(format!("rules-enrichment-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("local.rules"),
+        concat!(
+            "# alert tcp any any -> any any (msg:\"disabled\"; sid:1; rev:1;)\n",
+            "alert http any any -> any any (msg:\"ET EXPLOIT Log4j; JNDI\"; \\\n",
+            "    flowbits:set,ET.log4j; reference:cve,2021-44228; \\\n",
+            "    reference:url,example.com/log4j; sid:2034647; rev:3;)\n",
+        ),
+    )
+    .unwrap();
+    fs::write(dir.join("ignored.txt"), "not a rule file").unwrap();
+    let reference_config = dir.join("reference.config");
+    fs::write(
+        &reference_config,
+        concat!(
+            "config reference: cve http://cve.mitre.org/cgi-bin/cvename.cgi?name=\n",
+            "config reference: url http://\n",
+        ),
+    )
+    .unwrap();
+
+    let ruleset = RuleSet::load(&dir, Some(&reference_config)).expect("should load");
+    assert_eq!(ruleset.rule_count(), 1);
+
+    let mut event = SensorEvent {
+        snort_rule_gid: 1,
+        snort_rule_sid: 2034647,
+        snort_rule_rev: 3,
+        ..Default::default()
+    };
+    ruleset.enrich(&mut event);
+
+    assert!(event
+        .snort_rule_text
+        .unwrap()
+        .contains("msg:\"ET EXPLOIT Log4j; JNDI\""));
+    assert_eq!(event.snort_rule_flowbits, vec!["set,ET.log4j"]);
+    assert_eq!(event.snort_rule_references.len(), 2);
+    assert_eq!(event.snort_rule_references[0].system, "cve");
+    assert_eq!(
+        event.snort_rule_references[0].url.as_deref(),
+        Some("http://cve.mitre.org/cgi-bin/cvename.cgi?name=2021-44228")
+    );
+    assert_eq!(
+        event.snort_rule_references[1].url.as_deref(),
+        Some("http://example.com/log4j")
+    );
+    assert_eq!(
+        event.snort_ruleset_version.as_deref(),
+        Some(ruleset.version())
+    );
+
+    // A different revision of the same signature is not in the ruleset.
+    let mut stale = SensorEvent {
+        snort_rule_gid: 1,
+        snort_rule_sid: 2034647,
+        snort_rule_rev: 2,
+        ..Default::default()
+    };
+    ruleset.enrich(&mut stale);
+    assert!(stale.snort_rule_text.is_none());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,107 @@
+use sensor_suricata_service_rust::processor::{convert_suricata_alert_to_sensor_event, rehash};
+use sensor_suricata_service_rust::scripting::{ScriptCounters, ScriptSet};
+use sensor_suricata_service_rust::types::SuricataAlert;
+use std::fs;
+use std::time::Duration;
+
+fn alert(src_ip: &str) -> SuricataAlert {
+    let json = format!(
+        r#"{{
+            "timestamp": "2025-12-15T07:46:41.123456+0000",
+            "event_type": "alert",
+            "src_ip": "{}",
+            "dest_ip": "192.0.2.1",
+            "in_iface": "eth0",
+            "alert": {{
+                "action": "allowed",
+                "gid": 1,
+                "signature_id": 2001,
+                "rev": 1,
+                "signature": "x",
+                "category": "x",
+                "severity": 3
+            }}
+        }}"#,
+        src_ip
+    );
+    let mut bytes = json.into_bytes();
+    simd_json::from_slice(&mut bytes).expect("should parse")
+}
+
+#[test]
+fn scripts_modify_drop_and_are_contained() {
+    let dir = std::env::temp_dir().join(format!("scripts-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("10-tag.rhai"),
+        r#"
+        event.snort_interface = "site-a/" + event.snort_interface;
+        metric.event_direction = "inbound";
+        metric.snort_vlan = 42;
+        "#,
+    )
+    .unwrap();
+    fs::write(
+        dir.join("20-drop-lab.rhai"),
+        r#"!(alert.src_ip.starts_with("10.99.") && event.snort_priority > 2)"#,
+    )
+    .unwrap();
+    fs::write(dir.join("30-spin.rhai"), "loop { metric.snort_vlan = 0; }").unwrap();
+    fs::write(dir.join("40-broken.rhai"), "metric.snort_vlan = 1; x.y").unwrap();
+    fs::write(dir.join("50-sandbox.rhai"), r#"import "/etc/passwd" as p;"#).unwrap();
+    fs::write(dir.join("notes.txt"), "not a script").unwrap();
+
+    let scripts = ScriptSet::load(&dir, Duration::from_millis(20)).expect("should load");
+    assert_eq!(scripts.script_count(), 5);
+    let counters = ScriptCounters::new();
+
+    let data = alert("198.51.100.7");
+    let (mut event, mut metric) = convert_suricata_alert_to_sensor_event(&data).unwrap();
+    let original = event.clone();
+    assert!(counters.run(&scripts, &data, &mut event, &mut metric));
+    assert_eq!(event.snort_interface, "site-a/eth0");
+    // The hash follows the fields the scripts changed.
+    rehash(&mut event);
+    assert_ne!(event.event_hash_sha256, original.event_hash_sha256);
+    let mut unchanged = original.clone();
+    rehash(&mut unchanged);
+    assert_eq!(unchanged.event_hash_sha256, original.event_hash_sha256);
+    assert_eq!(metric.event_direction.as_deref(), Some("inbound"));
+    // Failed scripts leave the event as it was.
+    assert_eq!(metric.snort_vlan, Some(42));
+
+    let data = alert("10.99.0.1");
+    let (mut event, mut metric) = convert_suricata_alert_to_sensor_event(&data).unwrap();
+    assert!(!counters.run(&scripts, &data, &mut event, &mut metric));
+
+    assert_eq!(
+        counters.get_script_counters(&scripts),
+        vec![
+            ("10-tag".to_string(), 2, 0, 0, 0),
+            ("20-drop-lab".to_string(), 2, 1, 0, 0),
+            ("30-spin".to_string(), 1, 0, 0, 1),
+            ("40-broken".to_string(), 1, 0, 1, 0),
+            ("50-sandbox".to_string(), 1, 0, 1, 0),
+        ]
+    );
+
+    // Counters carry over to reloaded scripts by name.
+    fs::remove_file(dir.join("30-spin.rhai")).unwrap();
+    fs::remove_file(dir.join("40-broken.rhai")).unwrap();
+    fs::remove_file(dir.join("50-sandbox.rhai")).unwrap();
+    fs::write(dir.join("15-noop.rhai"), "true").unwrap();
+    let scripts = ScriptSet::load(&dir, Duration::from_millis(20)).expect("should load");
+    let data = alert("10.99.0.1");
+    let (mut event, mut metric) = convert_suricata_alert_to_sensor_event(&data).unwrap();
+    assert!(!counters.run(&scripts, &data, &mut event, &mut metric));
+    assert_eq!(
+        counters.get_script_counters(&scripts),
+        vec![
+            ("10-tag".to_string(), 3, 0, 0, 0),
+            ("15-noop".to_string(), 1, 0, 0, 0),
+            ("20-drop-lab".to_string(), 3, 2, 0, 0),
+        ]
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,100 @@
+use sensor_suricata_service_rust::filter::{FilterCounters, SignatureFilter};
+use sensor_suricata_service_rust::types::SuricataAlert;
+use std::fs;
+
+fn alert(sid: i64, severity: i64, src_ip: &str) -> SuricataAlert {
+    let json = format!(
+        r#"{{
+            "timestamp": "2025-12-15T07:46:41.123456+0000",
+            "event_type": "alert",
+            "src_ip": "{}",
+            "dest_ip": "192.0.2.1",
+            "proto": "UDP",
+            "in_iface": "eth0",
+            "alert": {{
+                "action": "allowed",
+                "gid": 1,
+                "signature_id": {},
+                "rev": 1,
+                "signature": "x",
+                "category": "Not Suspicious Traffic",
+                "severity": {}
+            }}
+        }}"#,
+        src_ip, sid, severity
+    );
+    let mut bytes = json.into_bytes();
+    simd_json::from_slice(&mut bytes).expect("should parse")
+}
+
+#[test]
+fn first_matching_filter_rule_decides() {
+    let path = std::env::temp_dir().join(format!("signature-filter-{}.toml", std::process::id()));
+    fs::write(
+        &path,
+        r#"
+default = "include"
+
+[[rules]]
+name = "keep-scanner-lab"
+mode = "include"
+src = ["10.99.0.0/16"]
+
+[[rules]]
+name = "drop-noisy-range"
+mode = "exclude"
+sid = ["2200000-2299999", "2013504"]
+severity = [3]
+protocol = ["udp"]
+"#,
+    )
+    .unwrap();
+
+    let filter = SignatureFilter::load(&path).expect("should load");
+    let counters = FilterCounters::new();
+
+    assert!(!counters.allows(&filter, &alert(2200075, 3, "10.1.2.3")));
+    assert!(!counters.allows(&filter, &alert(2013504, 3, "10.1.2.3")));
+    // Lab traffic is kept by the earlier include rule.
+    assert!(counters.allows(&filter, &alert(2200075, 3, "10.99.4.4")));
+    // Higher severities are not matched by the exclude rule.
+    assert!(counters.allows(&filter, &alert(2200075, 1, "10.1.2.3")));
+
+    assert_eq!(counters.get_total_dropped(), 2);
+    assert_eq!(
+        counters.get_rule_counters(&filter),
+        vec![
+            ("keep-scanner-lab".to_string(), 1, 0),
+            ("drop-noisy-range".to_string(), 2, 2),
+        ]
+    );
+
+    // Rule counters carry over to a reloaded filter by rule name.
+    fs::write(
+        &path,
+        r#"
+[[rules]]
+name = "drop-noisy-range"
+mode = "exclude"
+sid = ["2200000-2299999"]
+
+[[rules]]
+name = "drop-lab"
+mode = "exclude"
+src = ["10.99.0.0/16"]
+"#,
+    )
+    .unwrap();
+    let filter = SignatureFilter::load(&path).expect("should reload");
+    assert!(!counters.allows(&filter, &alert(2200075, 1, "10.1.2.3")));
+    assert_eq!(counters.get_total_dropped(), 3);
+    assert_eq!(
+        counters.get_rule_counters(&filter),
+        vec![
+            ("drop-noisy-range".to_string(), 3, 3),
+            ("drop-lab".to_string(), 0, 0),
+        ]
+    );
+
+    fs::remove_file(&path).unwrap();
+}
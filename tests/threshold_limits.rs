@@ -0,0 +1,109 @@
+use sensor_suricata_service_rust::threshold::{RateLimiter, ThresholdRules};
+use sensor_suricata_service_rust::types::SuricataAlert;
+use std::fs;
+use std::time::{Duration, Instant};
+
+fn alert(sid: i64, src_ip: &str) -> SuricataAlert {
+    let json = format!(
+        r#"{{
+            "timestamp": "2025-12-15T07:46:41.123456+0000",
+            "event_type": "alert",
+            "src_ip": "{}",
+            "dest_ip": "192.0.2.1",
+            "alert": {{
+                "action": "allowed",
+                "gid": 1,
+                "signature_id": {},
+                "rev": 1,
+                "signature": "x",
+                "category": "x",
+                "severity": 2
+            }}
+        }}"#,
+        src_ip, sid
+    );
+    let mut bytes = json.into_bytes();
+    simd_json::from_slice(&mut bytes).expect("should parse")
+}
+
+#[test]
+fn limits_and_thresholds_per_tracked_address() {
+    let path = std::env::temp_dir().join(format!("threshold-{}.config", std::process::id()));
+    fs::write(
+        &path,
+        concat!(
+            "# comments are ignored\n",
+            "threshold gen_id 1, sig_id 100, type limit, track by_src, count 2, seconds 3600\n",
+            "event_filter gen_id 1, sig_id 200, type threshold, track by_dst, count 3, seconds 3600\n",
+        ),
+    )
+    .unwrap();
+
+    let rules = ThresholdRules::load(&path).expect("should load");
+    let limiter = RateLimiter::new();
+
+    // limit: the first two alerts per source pass, the rest are suppressed.
+    let results: Vec<_> = (0..5)
+        .map(|_| limiter.check(&rules, &alert(100, "10.0.0.1")))
+        .collect();
+    assert_eq!(results, vec![Some(0), Some(0), None, None, None]);
+    assert_eq!(limiter.check(&rules, &alert(100, "10.0.0.2")), Some(0));
+
+    // threshold: every third alert passes and reports the two held back.
+    let results: Vec<_> = (0..6)
+        .map(|_| limiter.check(&rules, &alert(200, "10.0.0.1")))
+        .collect();
+    assert_eq!(results, vec![None, None, Some(2), None, None, Some(2)]);
+
+    // Signatures without an entry are untouched.
+    assert_eq!(limiter.check(&rules, &alert(300, "10.0.0.1")), Some(0));
+    assert_eq!(limiter.get_total_suppressed(), 7);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn keeps_tracking_across_reloads_and_reports_expired_counts() {
+    let path = std::env::temp_dir().join(format!("threshold-reload-{}.config", std::process::id()));
+    fs::write(
+        &path,
+        "threshold gen_id 1, sig_id 100, type limit, track by_src, count 1, seconds 3600\n",
+    )
+    .unwrap();
+    let limiter = RateLimiter::new();
+
+    let rules = ThresholdRules::load(&path).expect("should load");
+    assert_eq!(limiter.check(&rules, &alert(100, "10.0.0.1")), Some(0));
+    assert_eq!(limiter.check(&rules, &alert(100, "10.0.0.1")), None);
+
+    // Reloaded rules keep counting where the previous ones left off.
+    let rules = ThresholdRules::load(&path).expect("should reload");
+    assert_eq!(rules.rule_count(), 1);
+    assert_eq!(limiter.check(&rules, &alert(100, "10.0.0.1")), None);
+    assert_eq!(limiter.check(&rules, &alert(100, "10.0.0.2")), Some(0));
+    assert_eq!(limiter.get_tracked_count(), 2);
+
+    // Idle keys are dropped with the alerts they never reported.
+    assert_eq!(limiter.expire(&rules, Instant::now()), 0);
+    let later = Instant::now() + Duration::from_secs(3 * 3600);
+    assert_eq!(limiter.expire(&rules, later), 2);
+    assert_eq!(limiter.get_tracked_count(), 0);
+    assert_eq!(limiter.get_total_expired_suppressed(), 2);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn rejects_entries_without_a_time_window() {
+    let path = std::env::temp_dir().join(format!("threshold-zero-{}.config", std::process::id()));
+    fs::write(
+        &path,
+        "threshold gen_id 1, sig_id 100, type threshold, track by_src, count 5, seconds 0\n",
+    )
+    .unwrap();
+    let error = ThresholdRules::load(&path)
+        .err()
+        .expect("should be rejected");
+    assert!(error.to_string().contains("time window"), "{}", error);
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,119 @@
+use sensor_suricata_service_rust::pb::{Metric, SensorEvent};
+use sensor_suricata_service_rust::plugins::{PluginCounters, PluginSet};
+use sensor_suricata_service_rust::processor::rehash;
+use sensor_suricata_service_rust::queue::{EventBatchQueue, FlushPolicy, MemoryLimits};
+use std::fs;
+use std::path::Path;
+
+// Every plugin takes its input at offset 1024 of a single page.
+fn plugin(dir: &Path, name: &str, data: &str, enrich_body: &str) {
+    let wat = format!(
+        r#"(module
+            (memory (export "memory") 1)
+            (data (i32.const 0) "{}")
+            (func (export "alloc") (param i32) (result i32) i32.const 1024)
+            (func (export "enrich") (param $ptr i32) (param $len i32) (result i64) {}))"#,
+        data, enrich_body
+    );
+    fs::write(dir.join(name), wat::parse_str(wat).unwrap()).unwrap();
+}
+
+#[test]
+fn plugins_replace_drop_and_are_contained() {
+    let dir = std::env::temp_dir().join(format!("plugins-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // Appends field 16 (snort_protocol) = "wasm" to the encoded event.
+    plugin(
+        &dir,
+        "10-append.wasm",
+        "\\82\\01\\04wasm",
+        r#"(memory.copy (i32.add (local.get $ptr) (local.get $len)) (i32.const 0) (i32.const 7))
+           (i64.or
+             (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+             (i64.extend_i32_u (i32.add (local.get $len) (i32.const 7))))"#,
+    );
+    plugin(&dir, "20-spin.wasm", "", "(loop $l (br $l)) (i64.const 0)");
+    // Returns 3 bytes that are not a valid event.
+    plugin(&dir, "30-garbage.wasm", "\\ff\\ff\\ff", "(i64.const 3)");
+    // Claims 4 GiB of output, more than the limit and its memory.
+    plugin(&dir, "35-huge.wasm", "", "(i64.const 0xffffffff)");
+    // Points 16 bytes past the end of its single page.
+    plugin(&dir, "36-outside.wasm", "", "(i64.const 0x1000000000010)");
+    plugin(&dir, "40-drop.wasm", "", "(i64.const -1)");
+
+    let event = SensorEvent {
+        snort_protocol: "TCP".to_string(),
+        metrics: vec![Metric {
+            snort_src_address: Some("198.51.100.7".to_string()),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let single =
+        PluginSet::load(&dir.join("10-append.wasm"), 1_000_000, 1024).expect("should load");
+    let mut appended = event.clone();
+    assert!(PluginCounters::new().run(&single, &mut appended));
+    assert_eq!(appended.snort_protocol, "wasm");
+    assert_eq!(appended.metrics, event.metrics);
+
+    // Events that differ only in what a plugin set are not merged.
+    let mut untouched = event.clone();
+    rehash(&mut appended);
+    rehash(&mut untouched);
+    let queue = EventBatchQueue::new(FlushPolicy::default(), MemoryLimits::default(), 1);
+    queue.add(appended);
+    queue.add(untouched);
+    let batch = queue.process_batch();
+    assert_eq!(batch.len(), 2);
+    assert!(batch.iter().all(|e| e.metrics.len() == 1));
+
+    let plugins = PluginSet::load(&dir, 1_000_000, 1024).expect("should load");
+    assert_eq!(plugins.plugin_count(), 6);
+    let counters = PluginCounters::new();
+    let mut dropped = event.clone();
+    assert!(!counters.run(&plugins, &mut dropped));
+    assert_eq!(
+        counters.get_plugin_counters(&plugins),
+        vec![
+            ("10-append".to_string(), 1, 0, 0, 0),
+            ("20-spin".to_string(), 1, 0, 0, 1),
+            ("30-garbage".to_string(), 1, 0, 1, 0),
+            ("35-huge".to_string(), 1, 0, 1, 0),
+            ("36-outside".to_string(), 1, 0, 1, 0),
+            ("40-drop".to_string(), 1, 1, 0, 0),
+        ]
+    );
+
+    // Counters carry over to reloaded plugins by name.
+    for name in ["20-spin", "30-garbage", "35-huge", "36-outside"] {
+        fs::remove_file(dir.join(format!("{}.wasm", name))).unwrap();
+    }
+    let plugins = PluginSet::load(&dir, 1_000_000, 1024).expect("should load");
+    let mut dropped = event.clone();
+    assert!(!counters.run(&plugins, &mut dropped));
+    assert_eq!(
+        counters.get_plugin_counters(&plugins),
+        vec![
+            ("10-append".to_string(), 2, 0, 0, 0),
+            ("40-drop".to_string(), 2, 2, 0, 0),
+        ]
+    );
+
+    // Plugins may not import host functions.
+    fs::write(
+        dir.join("50-import.wasm"),
+        wat::parse_str(
+            r#"(module
+                (import "env" "f" (func))
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32) i32.const 0)
+                (func (export "enrich") (param i32 i32) (result i64) i64.const 0))"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert!(PluginSet::load(&dir, 1_000_000, 1024).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}